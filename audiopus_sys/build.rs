@@ -2,7 +2,7 @@
 
 #[cfg(feature = "generate_binding")]
 use std::path::PathBuf;
-use std::{env, fmt::Display, path::Path};
+use std::{collections::HashMap, env, fmt::Display, fs, path::Path};
 
 /// Outputs the library-file's prefix as word usable for actual arguments on
 /// commands or paths.
@@ -29,6 +29,20 @@ use core::fmt::Debug;
 use core::marker::Copy;
 use core::prelude::rust_2024::derive;
         "#;
+    const EXTRA_MODULES: &'static str = r#"
+#[cfg(feature = "dred")]
+mod dred;
+#[cfg(feature = "dred")]
+pub use dred::*;
+#[cfg(feature = "custom-modes")]
+mod custom;
+#[cfg(feature = "custom-modes")]
+pub use custom::*;
+#[cfg(feature = "rust-alloc")]
+mod rust_alloc;
+#[cfg(feature = "rust-alloc")]
+pub use rust_alloc::*;
+"#;
 
     let bindings = bindgen::Builder::default()
         .use_core()
@@ -39,6 +53,7 @@ use core::prelude::rust_2024::derive;
         .raw_line(FEATURES)
         .raw_line(ALLOW_UNCONVENTIONALS)
         .raw_line(USE_CORE)
+        .raw_line(EXTRA_MODULES)
         .default_macro_constant_type(bindgen::MacroTypeVariation::Signed)
         // TODO: use feature for target selection, arbitraged here because we need
         // to support compile xtensa in firmware but clang cannot find stdint with xtensa
@@ -57,22 +72,210 @@ use core::prelude::rust_2024::derive;
     println!("cargo:info=Successfully generated binding.");
 }
 
-fn build_opus(is_static: bool) {
-    let opus_path = Path::new("opus");
+/// CMake options for the deep learning features of Opus 1.5, by cargo feature.
+const DNN_OPTIONS: &[(&str, bool)] = &[
+    ("OPUS_DEEP_PLC", cfg!(feature = "deep-plc")),
+    ("OPUS_DRED", cfg!(feature = "dred")),
+    ("OPUS_OSCE", cfg!(feature = "osce")),
+];
+
+fn opus_source() -> String {
+    // the bundled Opus is 1.3, the deep learning features need a 1.5 tree
+    println!("cargo:rerun-if-env-changed=OPUS_SOURCE_DIR");
+    let opus_source = env::var("OPUS_SOURCE_DIR").unwrap_or_else(|_| "opus".into());
 
     println!(
         "cargo:info=Opus source path used: {:?}.",
-        opus_path
+        Path::new(&opus_source)
             .canonicalize()
             .expect("Could not canonicalise to absolute path")
     );
+    opus_source
+}
+
+/// Preprocessor defines for the build options of Opus, by cargo feature.
+const BUILD_DEFINES: &[(&str, bool)] = &[
+    ("DISABLE_FLOAT_API", cfg!(feature = "disable-float-api")),
+    ("CUSTOM_MODES", cfg!(feature = "custom-modes")),
+    ("SMALL_FOOTPRINT", cfg!(feature = "small-footprint")),
+];
+
+/// The directory of `custom_support.h`, which routes the allocations of Opus
+/// to the functions of the `alloc-hooks` feature.
+fn custom_support_dir() -> String {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").expect("Cargo sets the manifest directory");
+    let dir = Path::new(&manifest_dir).join("src");
+    println!(
+        "cargo:rerun-if-changed={}",
+        dir.join("custom_support.h").display()
+    );
+    dir.display().to_string()
+}
+
+/// Whether to build Opus in fixed-point.
+///
+/// Set by the `fixed-point` feature, `LIBOPUS_FIXED_POINT` or
+/// `OPUS_FIXED_POINT`, otherwise picked for targets without a usable FPU:
+/// Xtensa, whose single-precision FPU is of no help to Opus' float build, and
+/// RISC-V without the `f` extension.
+fn is_fixed_point() -> bool {
+    println!("cargo:rerun-if-env-changed=LIBOPUS_FIXED_POINT");
+    println!("cargo:rerun-if-env-changed=OPUS_FIXED_POINT");
+    if cfg!(feature = "fixed-point")
+        || env::var("LIBOPUS_FIXED_POINT").is_ok()
+        || env::var("OPUS_FIXED_POINT").is_ok()
+    {
+        return true;
+    }
+
+    let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+    let features = env::var("CARGO_CFG_TARGET_FEATURE").unwrap_or_default();
+    match arch.as_str() {
+        "xtensa" => true,
+        "riscv32" | "riscv64" => !features.split(',').any(|feature| feature == "f"),
+        _ => false,
+    }
+}
+
+/// Whether to compile Opus with `cc` rather than CMake.
+///
+/// Bare-metal targets have no CMake toolchain support, so they always use
+/// `cc`; elsewhere `LIBOPUS_BUILD_CC` or `OPUS_BUILD_CC` asks for it.
+fn is_cc_build() -> bool {
+    println!("cargo:rerun-if-env-changed=LIBOPUS_BUILD_CC");
+    println!("cargo:rerun-if-env-changed=OPUS_BUILD_CC");
+    env::var("CARGO_CFG_TARGET_OS").is_ok_and(|os| os == "none")
+        || env::var("LIBOPUS_BUILD_CC").is_ok()
+        || env::var("OPUS_BUILD_CC").is_ok()
+}
+
+fn build_opus(is_static: bool) {
+    let opus_source = opus_source();
 
     println!("cargo:info=Building Opus via CMake.");
-    let opus_build_dir = cmake::build(opus_path);
+    let mut config = cmake::Config::new(&opus_source);
+    for (option, enabled) in DNN_OPTIONS {
+        if *enabled {
+            config.define(option, "ON");
+        }
+    }
+    if is_fixed_point() {
+        config.define("OPUS_FIXED_POINT", "ON");
+    }
+    if cfg!(feature = "disable-float-api") {
+        config.define("OPUS_ENABLE_FLOAT_API", "OFF");
+    }
+    if cfg!(feature = "custom-modes") {
+        config.define("OPUS_CUSTOM_MODES", "ON");
+    }
+    if cfg!(feature = "small-footprint") {
+        // there is no CMake option for it
+        config.cflag("-DSMALL_FOOTPRINT");
+    }
+    if cfg!(feature = "alloc-hooks") {
+        config
+            .cflag("-DCUSTOM_SUPPORT")
+            .cflag(format!("-I{}", custom_support_dir()));
+    }
+    let opus_build_dir = config.build();
     link_opus(is_static, opus_build_dir.display());
     //panic!("building opus to {}", opus_build_dir.display());
 }
 
+/// Reads the source lists of an automake fragment such as `celt_sources.mk`.
+fn read_source_lists(path: &Path) -> HashMap<String, Vec<String>> {
+    let text = fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Could not read {}: {}", path.display(), e));
+
+    text.replace("\\\n", " ")
+        .lines()
+        .filter_map(|line| {
+            let (name, files) = line.split_once('=')?;
+            let files = files.split_whitespace().map(str::to_owned).collect();
+            Some((name.trim().to_owned(), files))
+        })
+        .collect()
+}
+
+/// Compiles Opus with `cc`, which works for any target its C compiler does,
+/// using the source lists the Opus tree keeps for automake.
+///
+/// The library is always linked statically. Its optimisation level follows
+/// the cargo profile, so `opt-level = "s"` builds Opus for size as well.
+fn build_opus_cc() {
+    if DNN_OPTIONS.iter().any(|(_, enabled)| *enabled) {
+        panic!("The deep learning features of Opus can only be built via CMake.");
+    }
+
+    let opus_source = opus_source();
+    let opus_path = Path::new(&opus_source);
+    let fixed_point = is_fixed_point();
+    println!(
+        "cargo:info=Building {} Opus via cc.",
+        if fixed_point {
+            "fixed-point"
+        } else {
+            "floating-point"
+        }
+    );
+
+    let mut lists = HashMap::new();
+    for fragment in ["opus_sources.mk", "celt_sources.mk", "silk_sources.mk"] {
+        let path = opus_path.join(fragment);
+        println!("cargo:rerun-if-changed={}", path.display());
+        lists.extend(read_source_lists(&path));
+    }
+
+    let (silk_sources, silk_dir) = if fixed_point {
+        ("SILK_SOURCES_FIXED", "silk/fixed")
+    } else {
+        ("SILK_SOURCES_FLOAT", "silk/float")
+    };
+
+    let mut build = cc::Build::new();
+    for dir in ["include", "celt", "silk", silk_dir, "src"] {
+        build.include(opus_path.join(dir));
+    }
+    build
+        .define("OPUS_BUILD", None)
+        .define("VAR_ARRAYS", None)
+        .define("HAVE_LRINT", None)
+        .define("HAVE_LRINTF", None)
+        .warnings(false);
+    if fixed_point {
+        build.define("FIXED_POINT", None);
+    }
+    for (define, enabled) in BUILD_DEFINES {
+        if *enabled {
+            build.define(define, None);
+        }
+    }
+    if cfg!(feature = "alloc-hooks") {
+        build
+            .define("CUSTOM_SUPPORT", None)
+            .include(custom_support_dir());
+    }
+    if env::var("CARGO_CFG_TARGET_ARCH").is_ok_and(|arch| arch == "xtensa") {
+        // as esp-idf does, calls may have to reach from flash to IRAM
+        build.flag("-mlongcalls");
+    }
+
+    let mut groups = vec!["OPUS_SOURCES", "CELT_SOURCES", "SILK_SOURCES", silk_sources];
+    if !cfg!(feature = "disable-float-api") {
+        groups.push("OPUS_SOURCES_FLOAT");
+    }
+    for group in groups {
+        let sources = lists
+            .get(group)
+            .unwrap_or_else(|| panic!("{} is missing from the Opus source lists", group));
+        for source in sources {
+            build.file(opus_path.join(source));
+        }
+    }
+
+    build.compile("opus");
+}
+
 fn link_opus(is_static: bool, opus_build_dir: impl Display) {
     let is_static_text = rustc_linking_word(is_static);
 
@@ -148,11 +351,21 @@ fn main() {
     #[cfg(feature = "generate_binding")]
     generate_binding();
 
+    if is_cc_build() {
+        build_opus_cc();
+
+        return;
+    }
+
     let is_static = is_static_build();
 
     #[cfg(any(unix, target_env = "gnu"))]
     {
-        if std::env::var("LIBOPUS_NO_PKG").is_ok() || std::env::var("OPUS_NO_PKG").is_ok() {
+        // the allocation hooks need Opus built from source
+        if std::env::var("LIBOPUS_NO_PKG").is_ok()
+            || std::env::var("OPUS_NO_PKG").is_ok()
+            || cfg!(feature = "alloc-hooks")
+        {
             println!("cargo:info=Bypassed `pkg-config`.");
         } else if find_via_pkg_config(is_static) {
             println!("cargo:info=Found `Opus` via `pkg_config`.");
@@ -164,6 +377,21 @@ fn main() {
     }
 
     if let Some(installed_opus) = find_installed_opus() {
+        if cfg!(feature = "alloc-hooks") {
+            panic!("The allocation hooks need Opus to be built from source, not pre-installed.");
+        }
+        if DNN_OPTIONS.iter().any(|(_, enabled)| *enabled) {
+            println!(
+                "cargo:warning=Linking a pre-installed Opus, \
+                 it must be built with the enabled deep learning features."
+            );
+        }
+        if BUILD_DEFINES.iter().any(|(_, enabled)| *enabled) || cfg!(feature = "fixed-point") {
+            println!(
+                "cargo:warning=Linking a pre-installed Opus, \
+                 it must be built with the enabled build options."
+            );
+        }
         link_opus(is_static, installed_opus);
     } else {
         build_opus(is_static);
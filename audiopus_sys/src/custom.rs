@@ -0,0 +1,143 @@
+//! Bindings to the Opus Custom API, only present in an Opus built with the
+//! `custom-modes` feature. These are written by hand as the binding is
+//! generated without `opus_custom.h`.
+
+use super::{opus_int16, opus_int32};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct OpusCustomMode {
+    _unused: [u8; 0],
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct OpusCustomEncoder {
+    _unused: [u8; 0],
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct OpusCustomDecoder {
+    _unused: [u8; 0],
+}
+unsafe extern "C" {
+    #[doc = " Creates a new mode struct that will be passed to an encoder or decoder.\n The mode MUST NOT BE DESTROYED until the encoders and decoders that use it are destroyed as well.\n @param [in] Fs <tt>int</tt>: Sampling rate (8000 to 96000 Hz)\n @param [in] frame_size <tt>int</tt>: Number of samples (per channel) to encode in each packet (64 - 1024, prime factorization must contain zero or more 2s, 3s, or 5s and no other primes)\n @param [out] error <tt>int*</tt>: Returned error code (if NULL, no error will be returned)\n @return A newly created mode"]
+    pub fn opus_custom_mode_create(
+        Fs: opus_int32,
+        frame_size: ::core::ffi::c_int,
+        error: *mut ::core::ffi::c_int,
+    ) -> *mut OpusCustomMode;
+}
+unsafe extern "C" {
+    #[doc = " Destroys a mode struct. Only call this after all encoders and decoders using this mode are destroyed as well.\n @param [in] mode <tt>OpusCustomMode*</tt>: Mode to be freed."]
+    pub fn opus_custom_mode_destroy(mode: *mut OpusCustomMode);
+}
+unsafe extern "C" {
+    #[doc = " Gets the size of an OpusCustomEncoder structure.\n @param [in] mode <tt>OpusCustomMode *</tt>: Mode configuration\n @param [in] channels <tt>int</tt>: Number of channels\n @returns size"]
+    pub fn opus_custom_encoder_get_size(
+        mode: *const OpusCustomMode,
+        channels: ::core::ffi::c_int,
+    ) -> ::core::ffi::c_int;
+}
+unsafe extern "C" {
+    #[doc = " Initializes a previously allocated encoder state.\n @param [in] st <tt>OpusCustomEncoder*</tt>: Encoder state\n @param [in] mode <tt>OpusCustomMode *</tt>: Contains all the information about the characteristics of the stream (must be the same characteristics as used for the decoder)\n @param [in] channels <tt>int</tt>: Number of channels\n @return OPUS_OK Success or @ref opus_errorcodes"]
+    pub fn opus_custom_encoder_init(
+        st: *mut OpusCustomEncoder,
+        mode: *const OpusCustomMode,
+        channels: ::core::ffi::c_int,
+    ) -> ::core::ffi::c_int;
+}
+unsafe extern "C" {
+    #[doc = " Creates a new encoder state. Each stream needs its own encoder state (can't be shared across simultaneous streams).\n @param [in] mode <tt>OpusCustomMode*</tt>: Contains all the information about the characteristics of the stream (must be the same characteristics as used for the decoder)\n @param [in] channels <tt>int</tt>: Number of channels\n @param [out] error <tt>int*</tt>: Returns an error code\n @return Newly created encoder state."]
+    pub fn opus_custom_encoder_create(
+        mode: *const OpusCustomMode,
+        channels: ::core::ffi::c_int,
+        error: *mut ::core::ffi::c_int,
+    ) -> *mut OpusCustomEncoder;
+}
+unsafe extern "C" {
+    #[doc = " Destroys an encoder state.\n @param[in] st <tt>OpusCustomEncoder*</tt>: State to be freed."]
+    pub fn opus_custom_encoder_destroy(st: *mut OpusCustomEncoder);
+}
+unsafe extern "C" {
+    #[doc = " Encodes a frame of audio.\n @param [in] st <tt>OpusCustomEncoder*</tt>: Encoder state\n @param [in] pcm <tt>float*</tt>: PCM audio in float format, with a normal range of +/-1.0.\n @param [in] frame_size <tt>int</tt>: Number of samples per frame of input signal\n @param [out] compressed <tt>char *</tt>: The compressed data is written here.\n @param [in] maxCompressedBytes <tt>int</tt>: Maximum number of bytes to use for compressing the frame\n @return Number of bytes written to \"compressed\" or @ref opus_errorcodes"]
+    pub fn opus_custom_encode_float(
+        st: *mut OpusCustomEncoder,
+        pcm: *const f32,
+        frame_size: ::core::ffi::c_int,
+        compressed: *mut ::core::ffi::c_uchar,
+        maxCompressedBytes: ::core::ffi::c_int,
+    ) -> ::core::ffi::c_int;
+}
+unsafe extern "C" {
+    #[doc = " Encodes a frame of audio.\n @param [in] st <tt>OpusCustomEncoder*</tt>: Encoder state\n @param [in] pcm <tt>opus_int16*</tt>: PCM audio in signed 16-bit format (native endian).\n @param [in] frame_size <tt>int</tt>: Number of samples per frame of input signal\n @param [out] compressed <tt>char *</tt>: The compressed data is written here.\n @param [in] maxCompressedBytes <tt>int</tt>: Maximum number of bytes to use for compressing the frame\n @return Number of bytes written to \"compressed\" or @ref opus_errorcodes"]
+    pub fn opus_custom_encode(
+        st: *mut OpusCustomEncoder,
+        pcm: *const opus_int16,
+        frame_size: ::core::ffi::c_int,
+        compressed: *mut ::core::ffi::c_uchar,
+        maxCompressedBytes: ::core::ffi::c_int,
+    ) -> ::core::ffi::c_int;
+}
+unsafe extern "C" {
+    #[doc = " Perform a CTL function on an Opus custom encoder."]
+    pub fn opus_custom_encoder_ctl(
+        st: *mut OpusCustomEncoder,
+        request: ::core::ffi::c_int,
+        ...
+    ) -> ::core::ffi::c_int;
+}
+unsafe extern "C" {
+    #[doc = " Gets the size of an OpusCustomDecoder structure.\n @param [in] mode <tt>OpusCustomMode *</tt>: Mode configuration\n @param [in] channels <tt>int</tt>: Number of channels\n @returns size"]
+    pub fn opus_custom_decoder_get_size(
+        mode: *const OpusCustomMode,
+        channels: ::core::ffi::c_int,
+    ) -> ::core::ffi::c_int;
+}
+unsafe extern "C" {
+    #[doc = " Initializes a previously allocated decoder state.\n @param [in] st <tt>OpusCustomDecoder*</tt>: Decoder state\n @param [in] mode <tt>OpusCustomMode *</tt>: Contains all the information about the characteristics of the stream (must be the same characteristics as used for the encoder)\n @param [in] channels <tt>int</tt>: Number of channels\n @return OPUS_OK Success or @ref opus_errorcodes"]
+    pub fn opus_custom_decoder_init(
+        st: *mut OpusCustomDecoder,
+        mode: *const OpusCustomMode,
+        channels: ::core::ffi::c_int,
+    ) -> ::core::ffi::c_int;
+}
+unsafe extern "C" {
+    #[doc = " Creates a new decoder state. Each stream needs its own decoder state (can't be shared across simultaneous streams).\n @param [in] mode <tt>OpusCustomMode</tt>: Contains all the information about the characteristics of the stream (must be the same characteristics as used for the encoder)\n @param [in] channels <tt>int</tt>: Number of channels\n @param [out] error <tt>int*</tt>: Returns an error code\n @return Newly created decoder state."]
+    pub fn opus_custom_decoder_create(
+        mode: *const OpusCustomMode,
+        channels: ::core::ffi::c_int,
+        error: *mut ::core::ffi::c_int,
+    ) -> *mut OpusCustomDecoder;
+}
+unsafe extern "C" {
+    #[doc = " Destroys a decoder state.\n @param[in] st <tt>OpusCustomDecoder*</tt>: State to be freed."]
+    pub fn opus_custom_decoder_destroy(st: *mut OpusCustomDecoder);
+}
+unsafe extern "C" {
+    #[doc = " Decode an opus custom frame with floating point output\n @param [in] st <tt>OpusCustomDecoder*</tt>: Decoder state\n @param [in] data <tt>char*</tt>: Input payload. Use a NULL pointer to indicate packet loss\n @param [in] len <tt>int</tt>: Number of bytes in payload\n @param [out] pcm <tt>float*</tt>: Output signal (interleaved if 2 channels). length is frame_size*channels*sizeof(float)\n @param [in] frame_size Number of samples per channel of available space in *pcm.\n @returns Number of decoded samples or @ref opus_errorcodes"]
+    pub fn opus_custom_decode_float(
+        st: *mut OpusCustomDecoder,
+        data: *const ::core::ffi::c_uchar,
+        len: ::core::ffi::c_int,
+        pcm: *mut f32,
+        frame_size: ::core::ffi::c_int,
+    ) -> ::core::ffi::c_int;
+}
+unsafe extern "C" {
+    #[doc = " Decode an opus custom frame\n @param [in] st <tt>OpusCustomDecoder*</tt>: Decoder state\n @param [in] data <tt>char*</tt>: Input payload. Use a NULL pointer to indicate packet loss\n @param [in] len <tt>int</tt>: Number of bytes in payload\n @param [out] pcm <tt>opus_int16*</tt>: Output signal (interleaved if 2 channels). length is frame_size*channels*sizeof(opus_int16)\n @param [in] frame_size Number of samples per channel of available space in *pcm.\n @returns Number of decoded samples or @ref opus_errorcodes"]
+    pub fn opus_custom_decode(
+        st: *mut OpusCustomDecoder,
+        data: *const ::core::ffi::c_uchar,
+        len: ::core::ffi::c_int,
+        pcm: *mut opus_int16,
+        frame_size: ::core::ffi::c_int,
+    ) -> ::core::ffi::c_int;
+}
+unsafe extern "C" {
+    #[doc = " Perform a CTL function on an Opus custom decoder."]
+    pub fn opus_custom_decoder_ctl(
+        st: *mut OpusCustomDecoder,
+        request: ::core::ffi::c_int,
+        ...
+    ) -> ::core::ffi::c_int;
+}
@@ -0,0 +1,24 @@
+/* Included by Opus' `celt/os_support.h` as audiopus_sys builds it with
+ * CUSTOM_SUPPORT for the `alloc-hooks` feature. Opus then allocates through
+ * these functions, defined by the application or, with the `rust-alloc`
+ * feature, by audiopus_sys on top of the Rust global allocator. */
+#ifndef AUDIOPUS_CUSTOM_SUPPORT_H
+#define AUDIOPUS_CUSTOM_SUPPORT_H
+
+#include <stddef.h>
+
+void *audiopus_malloc(size_t size);
+void *audiopus_realloc(void *ptr, size_t size);
+void audiopus_free(void *ptr);
+
+#define OVERRIDE_OPUS_ALLOC
+#define opus_alloc(size) audiopus_malloc(size)
+
+/* only used by Opus 1.5 */
+#define OVERRIDE_OPUS_REALLOC
+#define opus_realloc(ptr, size) audiopus_realloc(ptr, size)
+
+#define OVERRIDE_OPUS_FREE
+#define opus_free(ptr) audiopus_free(ptr)
+
+#endif
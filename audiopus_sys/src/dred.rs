@@ -0,0 +1,95 @@
+//! Bindings to the Deep Redundancy (DRED) API of Opus 1.5. These are written
+//! by hand as the bundled Opus headers predate it.
+
+use super::{opus_int16, opus_int32, OpusDecoder};
+
+pub const OPUS_SET_DRED_DURATION_REQUEST: i32 = 4050;
+pub const OPUS_GET_DRED_DURATION_REQUEST: i32 = 4051;
+pub const OPUS_SET_DNN_BLOB_REQUEST: i32 = 4052;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct OpusDREDDecoder {
+    _unused: [u8; 0],
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct OpusDRED {
+    _unused: [u8; 0],
+}
+unsafe extern "C" {
+    #[doc = " Gets the size of an <code>OpusDREDDecoder</code> structure.\n @returns The size in bytes."]
+    pub fn opus_dred_decoder_get_size() -> ::core::ffi::c_int;
+}
+unsafe extern "C" {
+    #[doc = " Allocates and initializes an OpusDREDDecoder state.\n @param [out] error <tt>int*</tt>: #OPUS_OK Success or @ref opus_errorcodes"]
+    pub fn opus_dred_decoder_create(error: *mut ::core::ffi::c_int) -> *mut OpusDREDDecoder;
+}
+unsafe extern "C" {
+    #[doc = " Initializes an <code>OpusDREDDecoder</code> state.\n @param[in] dec <tt>OpusDREDDecoder*</tt>: State to be initialized."]
+    pub fn opus_dred_decoder_init(dec: *mut OpusDREDDecoder) -> ::core::ffi::c_int;
+}
+unsafe extern "C" {
+    #[doc = " Frees an <code>OpusDREDDecoder</code> allocated by opus_dred_decoder_create().\n @param[in] dec <tt>OpusDREDDecoder*</tt>: State to be freed."]
+    pub fn opus_dred_decoder_destroy(dec: *mut OpusDREDDecoder);
+}
+unsafe extern "C" {
+    #[doc = " Perform a CTL function on an Opus DRED decoder."]
+    pub fn opus_dred_decoder_ctl(
+        dred_dec: *mut OpusDREDDecoder,
+        request: ::core::ffi::c_int,
+        ...
+    ) -> ::core::ffi::c_int;
+}
+unsafe extern "C" {
+    #[doc = " Gets the size of an <code>OpusDRED</code> structure.\n @returns The size in bytes."]
+    pub fn opus_dred_get_size() -> ::core::ffi::c_int;
+}
+unsafe extern "C" {
+    #[doc = " Allocates and initializes a DRED state.\n @param [out] error <tt>int*</tt>: #OPUS_OK Success or @ref opus_errorcodes"]
+    pub fn opus_dred_alloc(error: *mut ::core::ffi::c_int) -> *mut OpusDRED;
+}
+unsafe extern "C" {
+    #[doc = " Frees an <code>OpusDRED</code> allocated by opus_dred_create().\n @param[in] dec <tt>OpusDRED*</tt>: State to be freed."]
+    pub fn opus_dred_free(dec: *mut OpusDRED);
+}
+unsafe extern "C" {
+    #[doc = " Decode an Opus DRED packet.\n @param [in] dred_dec <tt>OpusDRED*</tt>: DRED Decoder state\n @param [in] dred <tt>OpusDRED*</tt>: DRED state\n @param [in] data <tt>char*</tt>: Input payload\n @param [in] len <tt>opus_int32</tt>: Number of bytes in payload\n @param [in] max_dred_samples <tt>opus_int32</tt>: Maximum number of DRED samples that may be needed (if available in the packet).\n @param [in] sampling_rate <tt>opus_int32</tt>: Sampling rate used for max_dred_samples argument. Needs not match the actual sampling rate of the decoder.\n @param [out] dred_end <tt>opus_int32*</tt>: Number of non-encoded (silence) samples between the DRED timestamp and the last DRED sample.\n @param [in] defer_processing <tt>int</tt>: Flag (0 or 1). If set to one, the CPU-intensive part of the DRED decoding is deferred until opus_dred_process() is called.\n @returns Offset (positive) of the first decoded DRED samples, zero if no DRED is present, or @ref opus_errorcodes"]
+    pub fn opus_dred_parse(
+        dred_dec: *mut OpusDREDDecoder,
+        dred: *mut OpusDRED,
+        data: *const ::core::ffi::c_uchar,
+        len: opus_int32,
+        max_dred_samples: opus_int32,
+        sampling_rate: opus_int32,
+        dred_end: *mut ::core::ffi::c_int,
+        defer_processing: ::core::ffi::c_int,
+    ) -> ::core::ffi::c_int;
+}
+unsafe extern "C" {
+    #[doc = " Finish decoding an Opus DRED packet. The function only needs to be called if opus_dred_parse() was called with defer_processing=1.\n The source and destination will often be the same DRED state.\n @param [in] dred_dec <tt>OpusDRED*</tt>: DRED Decoder state\n @param [in] src <tt>OpusDRED*</tt>: Source DRED state to start the processing from.\n @param [out] dst <tt>OpusDRED*</tt>: Destination DRED state to store the updated state after processing.\n @returns @ref opus_errorcodes"]
+    pub fn opus_dred_process(
+        dred_dec: *mut OpusDREDDecoder,
+        src: *const OpusDRED,
+        dst: *mut OpusDRED,
+    ) -> ::core::ffi::c_int;
+}
+unsafe extern "C" {
+    #[doc = " Decode audio from an Opus DRED packet with floating point output.\n @param [in] st <tt>OpusDecoder*</tt>: Decoder state\n @param [in] dred <tt>OpusDRED*</tt>: DRED state\n @param [in] dred_offset <tt>opus_int32</tt>: position of the redundancy to decode (in samples before the beginning of the real audio data in the packet).\n @param [out] pcm <tt>opus_int16*</tt>: Output signal (interleaved if 2 channels). length\n  is frame_size*channels*sizeof(opus_int16)\n @param [in] frame_size Number of samples per channel to decode in \\a pcm.\n  frame_size <b>must</b> be a multiple of 2.5 ms.\n @returns Number of decoded samples or @ref opus_errorcodes"]
+    pub fn opus_decoder_dred_decode(
+        st: *mut OpusDecoder,
+        dred: *const OpusDRED,
+        dred_offset: opus_int32,
+        pcm: *mut opus_int16,
+        frame_size: opus_int32,
+    ) -> ::core::ffi::c_int;
+}
+unsafe extern "C" {
+    #[doc = " Decode audio from an Opus DRED packet with floating point output.\n @param [in] st <tt>OpusDecoder*</tt>: Decoder state\n @param [in] dred <tt>OpusDRED*</tt>: DRED state\n @param [in] dred_offset <tt>opus_int32</tt>: position of the redundancy to decode (in samples before the beginning of the real audio data in the packet).\n @param [out] pcm <tt>float*</tt>: Output signal (interleaved if 2 channels). length\n  is frame_size*channels*sizeof(float)\n @param [in] frame_size Number of samples per channel to decode in \\a pcm.\n  frame_size <b>must</b> be a multiple of 2.5 ms.\n @returns Number of decoded samples or @ref opus_errorcodes"]
+    pub fn opus_decoder_dred_decode_float(
+        st: *mut OpusDecoder,
+        dred: *const OpusDRED,
+        dred_offset: opus_int32,
+        pcm: *mut f32,
+        frame_size: opus_int32,
+    ) -> ::core::ffi::c_int;
+}
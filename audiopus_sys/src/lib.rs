@@ -14,6 +14,20 @@ use core::marker::Copy;
 use core::prelude::rust_2024::derive;
         
 
+#[cfg(feature = "dred")]
+mod dred;
+#[cfg(feature = "dred")]
+pub use dred::*;
+#[cfg(feature = "custom-modes")]
+mod custom;
+#[cfg(feature = "custom-modes")]
+pub use custom::*;
+#[cfg(feature = "rust-alloc")]
+mod rust_alloc;
+#[cfg(feature = "rust-alloc")]
+pub use rust_alloc::*;
+
+
 pub const OPUS_OK: i32 = 0;
 pub const OPUS_BAD_ARG: i32 = -1;
 pub const OPUS_BUFFER_TOO_SMALL: i32 = -2;
@@ -0,0 +1,78 @@
+//! The allocation functions Opus calls with the `rust-alloc` feature, backed
+//! by the Rust global allocator so that Opus' memory is accounted for along
+//! with the rest of the heap.
+
+extern crate alloc;
+
+use alloc::alloc::{alloc, dealloc, realloc, Layout};
+use core::{ffi::c_void, mem, ptr};
+
+/// Alignment of every block, what `malloc` guarantees on common platforms.
+const ALIGN: usize = 2 * mem::size_of::<usize>();
+
+/// Bytes before each block keeping its size, as `free` isn't told it.
+const HEADER: usize = ALIGN;
+
+fn layout(size: usize) -> Option<Layout> {
+    Layout::from_size_align(size.checked_add(HEADER)?, ALIGN).ok()
+}
+
+/// Finds the start and layout of the allocation behind a block.
+unsafe fn allocation(block: *mut c_void) -> (*mut u8, Layout) {
+    let base = block.cast::<u8>().sub(HEADER);
+    let size = base.cast::<usize>().read();
+    // it was allocated with this layout
+    (
+        base,
+        Layout::from_size_align_unchecked(size + HEADER, ALIGN),
+    )
+}
+
+/// Records the size of the block in an allocation and returns the block.
+unsafe fn into_block(base: *mut u8, size: usize) -> *mut c_void {
+    if base.is_null() {
+        return ptr::null_mut();
+    }
+    base.cast::<usize>().write(size);
+    base.add(HEADER).cast()
+}
+
+/// `malloc` for Opus.
+#[no_mangle]
+pub extern "C" fn audiopus_malloc(size: usize) -> *mut c_void {
+    match layout(size) {
+        // the layout is never zero-sized thanks to the header
+        Some(layout) => unsafe { into_block(alloc(layout), size) },
+        None => ptr::null_mut(),
+    }
+}
+
+/// `realloc` for Opus, only called by Opus 1.5.
+///
+/// # Safety
+///
+/// `block` must be null or a live block from these functions.
+#[no_mangle]
+pub unsafe extern "C" fn audiopus_realloc(block: *mut c_void, size: usize) -> *mut c_void {
+    if block.is_null() {
+        return audiopus_malloc(size);
+    }
+    if layout(size).is_none() {
+        return ptr::null_mut();
+    }
+    let (base, layout) = allocation(block);
+    into_block(realloc(base, layout, size + HEADER), size)
+}
+
+/// `free` for Opus.
+///
+/// # Safety
+///
+/// `block` must be null or a live block from these functions.
+#[no_mangle]
+pub unsafe extern "C" fn audiopus_free(block: *mut c_void) {
+    if !block.is_null() {
+        let (base, layout) = allocation(block);
+        dealloc(base, layout);
+    }
+}
@@ -0,0 +1,5 @@
+//! Audio devices: their sample formats, and drivers for the codec chips set
+//! up over I²C.
+
+pub mod codec;
+pub mod format;
@@ -0,0 +1,76 @@
+use core::{fmt::Debug, future::Future};
+
+use embedded_hal_async::i2c::I2c;
+
+use super::format::BitDepth;
+
+pub mod es8311;
+pub mod es8388;
+#[cfg(test)]
+mod testing;
+
+pub use es8311::Es8311;
+pub use es8388::Es8388;
+
+/// A codec chip controlled over I²C, streaming audio over a single I²S bus.
+///
+/// The chip is always an I²S slave, clocked by an MCLK of 256 × sample rate.
+pub trait AudioCodec {
+    type Error: Debug;
+
+    /// Reset the chip and bring both ADC and DAC up.
+    fn init(
+        &mut self,
+        sample_rate: u32,
+        bits: BitDepth,
+    ) -> impl Future<Output = Result<(), Self::Error>>;
+
+    /// Set the speaker volume, in percent.
+    fn set_volume(&mut self, volume: u8) -> impl Future<Output = Result<(), Self::Error>>;
+
+    fn set_mute(&mut self, mute: bool) -> impl Future<Output = Result<(), Self::Error>>;
+
+    /// Set the microphone PGA gain in dB, rounded down to what the chip supports.
+    fn set_mic_gain(&mut self, db: u8) -> impl Future<Output = Result<(), Self::Error>>;
+}
+
+/// Register access shared by the codec drivers.
+struct Registers<I> {
+    i2c: I,
+    address: u8,
+}
+
+impl<I: I2c> Registers<I> {
+    async fn write(&mut self, reg: u8, value: u8) -> Result<(), I::Error> {
+        self.i2c.write(self.address, &[reg, value]).await
+    }
+
+    async fn read(&mut self, reg: u8) -> Result<u8, I::Error> {
+        let mut value = [0];
+        self.i2c
+            .write_read(self.address, &[reg], &mut value)
+            .await?;
+        Ok(value[0])
+    }
+
+    async fn update(&mut self, reg: u8, mask: u8, value: u8) -> Result<(), I::Error> {
+        let old = self.read(reg).await?;
+        self.write(reg, (old & !mask) | (value & mask)).await
+    }
+
+    async fn write_all(&mut self, seq: &[(u8, u8)]) -> Result<(), I::Error> {
+        for &(reg, value) in seq {
+            self.write(reg, value).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Word length field shared by the Everest serial data port registers.
+fn word_length(bits: BitDepth) -> u8 {
+    match bits {
+        BitDepth::Bits24 => 0b000,
+        BitDepth::Bits16 => 0b011,
+        BitDepth::Bits32 => 0b100,
+    }
+}
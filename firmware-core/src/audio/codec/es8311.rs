@@ -0,0 +1,222 @@
+use embedded_hal_async::i2c::I2c;
+use log::debug;
+
+use super::{word_length, AudioCodec, Registers};
+use crate::audio::format::BitDepth;
+
+/// I²C address with CE pulled low.
+pub const ADDRESS: u8 = 0x18;
+
+const RESET: u8 = 0x00;
+const CLK_MANAGER1: u8 = 0x01;
+const CLK_MANAGER2: u8 = 0x02;
+const CLK_MANAGER3: u8 = 0x03;
+const CLK_MANAGER4: u8 = 0x04;
+const CLK_MANAGER5: u8 = 0x05;
+const CLK_MANAGER6: u8 = 0x06;
+const CLK_MANAGER7: u8 = 0x07;
+const CLK_MANAGER8: u8 = 0x08;
+const SDP_IN: u8 = 0x09;
+const SDP_OUT: u8 = 0x0A;
+const SYSTEM0B: u8 = 0x0B;
+const SYSTEM0C: u8 = 0x0C;
+const SYSTEM0D: u8 = 0x0D;
+const SYSTEM0E: u8 = 0x0E;
+const SYSTEM10: u8 = 0x10;
+const SYSTEM11: u8 = 0x11;
+const SYSTEM12: u8 = 0x12;
+const SYSTEM13: u8 = 0x13;
+const SYSTEM14: u8 = 0x14;
+const ADC15: u8 = 0x15;
+const ADC16: u8 = 0x16;
+const ADC17: u8 = 0x17;
+const ADC1B: u8 = 0x1B;
+const ADC1C: u8 = 0x1C;
+const DAC31: u8 = 0x31;
+const DAC32: u8 = 0x32;
+const DAC37: u8 = 0x37;
+const GP45: u8 = 0x45;
+
+/// Everest ES8311 mono codec.
+pub struct Es8311<I> {
+    regs: Registers<I>,
+}
+
+impl<I: I2c> Es8311<I> {
+    pub fn new(i2c: I, address: u8) -> Self {
+        Self {
+            regs: Registers { i2c, address },
+        }
+    }
+
+    pub fn release(self) -> I {
+        self.regs.i2c
+    }
+}
+
+impl<I: I2c> AudioCodec for Es8311<I> {
+    type Error = I::Error;
+
+    async fn init(&mut self, sample_rate: u32, bits: BitDepth) -> Result<(), Self::Error> {
+        debug!("es8311: init at {sample_rate}Hz {bits:?}");
+        self.regs
+            .write_all(&[
+                (GP45, 0x00),
+                (CLK_MANAGER1, 0x30),
+                (CLK_MANAGER2, 0x00),
+                (CLK_MANAGER3, 0x10),
+                (ADC16, 0x24),
+                (CLK_MANAGER4, 0x10),
+                (CLK_MANAGER5, 0x00),
+                (SYSTEM0B, 0x00),
+                (SYSTEM0C, 0x00),
+                (SYSTEM10, 0x1F),
+                (SYSTEM11, 0x7F),
+                // power on state machine, slave mode
+                (RESET, 0x80),
+                // all clocks on, MCLK from the MCLK pin
+                (CLK_MANAGER1, 0x3F),
+            ])
+            .await?;
+
+        // With MCLK at 256fs every rate shares the same dividers: no pre-divide
+        // or multiply, ADC/DAC OSR of 16, BCLK = MCLK / 4 and LRCK = MCLK / 256.
+        self.regs
+            .write_all(&[
+                (CLK_MANAGER2, 0x00),
+                (CLK_MANAGER5, 0x00),
+                (CLK_MANAGER3, 0x10),
+                (CLK_MANAGER4, 0x10),
+                (CLK_MANAGER7, 0x00),
+                (CLK_MANAGER8, 0xFF),
+            ])
+            .await?;
+        self.regs.update(CLK_MANAGER6, 0x1F, 0x03).await?;
+
+        // I²S Philips format, unmuted
+        let sdp = word_length(bits) << 2;
+        self.regs.write(SDP_IN, sdp).await?;
+        self.regs.write(SDP_OUT, sdp).await?;
+
+        self.regs
+            .write_all(&[
+                (SYSTEM13, 0x10),
+                (ADC1B, 0x0A),
+                (ADC1C, 0x6A),
+                // start ADC and DAC
+                (ADC17, 0xBF),
+                (SYSTEM0E, 0x02),
+                (SYSTEM12, 0x00),
+                // MIC1P/MIC1N differential input, PGA +30dB
+                (SYSTEM14, 0x1A),
+                (SYSTEM0D, 0x01),
+                (ADC15, 0x40),
+                (DAC37, 0x08),
+                (GP45, 0x00),
+            ])
+            .await
+    }
+
+    async fn set_volume(&mut self, volume: u8) -> Result<(), Self::Error> {
+        // 0x00 is -95.5dB and 0xBF is 0dB, in 0.5dB steps
+        let reg = volume.min(100) as u32 * 0xBF / 100;
+        self.regs.write(DAC32, reg as u8).await
+    }
+
+    async fn set_mute(&mut self, mute: bool) -> Result<(), Self::Error> {
+        self.regs
+            .update(DAC31, 0x60, if mute { 0x60 } else { 0x00 })
+            .await
+    }
+
+    async fn set_mic_gain(&mut self, db: u8) -> Result<(), Self::Error> {
+        // digital mic gain from 0dB to 42dB in 6dB steps
+        self.regs.update(ADC16, 0x07, (db / 6).min(7)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::audio::codec::testing::Bus;
+
+    fn init(bits: BitDepth) -> Bus {
+        let mut codec = Es8311::new(Bus::new(ADDRESS), ADDRESS);
+        block_on(codec.init(16000, bits)).unwrap();
+        codec.release()
+    }
+
+    #[test]
+    fn init_sequence() {
+        let bus = init(BitDepth::Bits16);
+        #[rustfmt::skip]
+        let expected = [
+            (GP45, 0x00), (CLK_MANAGER1, 0x30), (CLK_MANAGER2, 0x00),
+            (CLK_MANAGER3, 0x10), (ADC16, 0x24), (CLK_MANAGER4, 0x10),
+            (CLK_MANAGER5, 0x00), (SYSTEM0B, 0x00), (SYSTEM0C, 0x00),
+            (SYSTEM10, 0x1F), (SYSTEM11, 0x7F), (RESET, 0x80), (CLK_MANAGER1, 0x3F),
+            (CLK_MANAGER2, 0x00), (CLK_MANAGER5, 0x00), (CLK_MANAGER3, 0x10),
+            (CLK_MANAGER4, 0x10), (CLK_MANAGER7, 0x00), (CLK_MANAGER8, 0xFF),
+            (CLK_MANAGER6, 0x03), (SDP_IN, 0x0C), (SDP_OUT, 0x0C),
+            (SYSTEM13, 0x10), (ADC1B, 0x0A), (ADC1C, 0x6A), (ADC17, 0xBF),
+            (SYSTEM0E, 0x02), (SYSTEM12, 0x00), (SYSTEM14, 0x1A), (SYSTEM0D, 0x01),
+            (ADC15, 0x40), (DAC37, 0x08), (GP45, 0x00),
+        ];
+        assert_eq!(bus.writes, expected);
+    }
+
+    #[test]
+    fn word_length() {
+        for (bits, sdp) in [
+            (BitDepth::Bits16, 0x0C),
+            (BitDepth::Bits24, 0x00),
+            (BitDepth::Bits32, 0x10),
+        ] {
+            let bus = init(bits);
+            assert_eq!(bus.registers[SDP_IN as usize], sdp, "{bits:?}");
+            assert_eq!(bus.registers[SDP_OUT as usize], sdp, "{bits:?}");
+        }
+    }
+
+    #[test]
+    fn clock_divider_keeps_upper_bits() {
+        let mut bus = Bus::new(ADDRESS);
+        bus.registers[CLK_MANAGER6 as usize] = 0xFF;
+        let mut codec = Es8311::new(bus, ADDRESS);
+        block_on(codec.init(16000, BitDepth::Bits16)).unwrap();
+        assert_eq!(codec.release().registers[CLK_MANAGER6 as usize], 0xE3);
+    }
+
+    #[test]
+    fn volume() {
+        let mut codec = Es8311::new(Bus::new(ADDRESS), ADDRESS);
+        for (volume, reg) in [(0, 0x00), (50, 0x5F), (100, 0xBF), (255, 0xBF)] {
+            block_on(codec.set_volume(volume)).unwrap();
+            assert_eq!(codec.regs.i2c.registers[DAC32 as usize], reg, "{volume}");
+        }
+    }
+
+    #[test]
+    fn mute_keeps_other_bits() {
+        let mut bus = Bus::new(ADDRESS);
+        bus.registers[DAC31 as usize] = 0x81;
+        let mut codec = Es8311::new(bus, ADDRESS);
+        block_on(codec.set_mute(true)).unwrap();
+        assert_eq!(codec.regs.i2c.registers[DAC31 as usize], 0xE1);
+        block_on(codec.set_mute(false)).unwrap();
+        assert_eq!(codec.regs.i2c.registers[DAC31 as usize], 0x81);
+    }
+
+    #[test]
+    fn mic_gain() {
+        let mut bus = Bus::new(ADDRESS);
+        bus.registers[ADC16 as usize] = 0x24;
+        let mut codec = Es8311::new(bus, ADDRESS);
+        for (db, reg) in [(0, 0x20), (17, 0x22), (18, 0x23), (42, 0x27), (60, 0x27)] {
+            block_on(codec.set_mic_gain(db)).unwrap();
+            assert_eq!(codec.regs.i2c.registers[ADC16 as usize], reg, "{db}");
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use embedded_hal_async::i2c::I2c;
+use log::debug;
+
+use super::{word_length, AudioCodec, Registers};
+use crate::audio::format::BitDepth;
+
+/// I²C address with CE pulled low.
+pub const ADDRESS: u8 = 0x10;
+
+const CONTROL1: u8 = 0x00;
+const CONTROL2: u8 = 0x01;
+const CHIP_POWER: u8 = 0x02;
+const ADC_POWER: u8 = 0x03;
+const DAC_POWER: u8 = 0x04;
+const MASTER_MODE: u8 = 0x08;
+const ADC_CONTROL1: u8 = 0x09;
+const ADC_CONTROL2: u8 = 0x0A;
+const ADC_CONTROL3: u8 = 0x0B;
+const ADC_CONTROL4: u8 = 0x0C;
+const ADC_CONTROL5: u8 = 0x0D;
+const ADC_CONTROL8: u8 = 0x10;
+const ADC_CONTROL9: u8 = 0x11;
+const DAC_CONTROL1: u8 = 0x17;
+const DAC_CONTROL2: u8 = 0x18;
+const DAC_CONTROL3: u8 = 0x19;
+const DAC_CONTROL4: u8 = 0x1A;
+const DAC_CONTROL5: u8 = 0x1B;
+const DAC_CONTROL16: u8 = 0x26;
+const DAC_CONTROL17: u8 = 0x27;
+const DAC_CONTROL20: u8 = 0x2A;
+const DAC_CONTROL21: u8 = 0x2B;
+const DAC_CONTROL23: u8 = 0x2D;
+const DAC_CONTROL24: u8 = 0x2E;
+const DAC_CONTROL27: u8 = 0x31;
+
+/// Everest ES8388 stereo codec.
+pub struct Es8388<I> {
+    regs: Registers<I>,
+}
+
+impl<I: I2c> Es8388<I> {
+    pub fn new(i2c: I, address: u8) -> Self {
+        Self {
+            regs: Registers { i2c, address },
+        }
+    }
+
+    pub fn release(self) -> I {
+        self.regs.i2c
+    }
+}
+
+impl<I: I2c> AudioCodec for Es8388<I> {
+    type Error = I::Error;
+
+    async fn init(&mut self, sample_rate: u32, bits: BitDepth) -> Result<(), Self::Error> {
+        debug!("es8388: init at {sample_rate}Hz {bits:?}");
+        let word_length = word_length(bits);
+        self.regs
+            .write_all(&[
+                // mute DAC while configuring
+                (DAC_CONTROL3, 0x04),
+                (CONTROL2, 0x50),
+                (CHIP_POWER, 0x00),
+                // slave mode
+                (MASTER_MODE, 0x00),
+            ])
+            .await?;
+
+        self.regs
+            .write_all(&[
+                (DAC_POWER, 0xC0),
+                // play and record mode
+                (CONTROL1, 0x12),
+                // I²S Philips format
+                (DAC_CONTROL1, word_length << 3),
+                // single speed, 256fs
+                (DAC_CONTROL2, 0x02),
+                // LIN1/RIN1 to the output mixers
+                (DAC_CONTROL16, 0x00),
+                // left DAC to left mixer, right DAC to right mixer, 0dB
+                (DAC_CONTROL17, 0x90),
+                (DAC_CONTROL20, 0x90),
+                // ADC and DAC share LRCK
+                (DAC_CONTROL21, 0x80),
+                (DAC_CONTROL23, 0x00),
+                // 0dB digital volume
+                (DAC_CONTROL4, 0x00),
+                (DAC_CONTROL5, 0x00),
+                // enable LOUT1/ROUT1 and LOUT2/ROUT2
+                (DAC_POWER, 0x3C),
+            ])
+            .await?;
+
+        self.regs
+            .write_all(&[
+                (ADC_POWER, 0xFF),
+                // PGA +24dB on both channels
+                (ADC_CONTROL1, 0x88),
+                // LINPUT1/RINPUT1
+                (ADC_CONTROL2, 0x00),
+                (ADC_CONTROL3, 0x02),
+                // I²S Philips format
+                (ADC_CONTROL4, word_length << 2),
+                // single speed, 256fs
+                (ADC_CONTROL5, 0x02),
+                // 0dB digital volume
+                (ADC_CONTROL8, 0x00),
+                (ADC_CONTROL9, 0x00),
+                // power on ADC with LIN/RIN, MICBIAS off
+                (ADC_POWER, 0x09),
+            ])
+            .await?;
+
+        self.regs
+            .write_all(&[
+                // restart the state machine
+                (CHIP_POWER, 0xF0),
+                (CHIP_POWER, 0x00),
+                (ADC_POWER, 0x00),
+                (DAC_CONTROL3, 0x00),
+            ])
+            .await
+    }
+
+    async fn set_volume(&mut self, volume: u8) -> Result<(), Self::Error> {
+        // 0x00 is -45dB and 0x1E is 0dB, in 1.5dB steps
+        let reg = (volume.min(100) as u32 * 0x1E / 100) as u8;
+        for out in DAC_CONTROL24..=DAC_CONTROL27 {
+            self.regs.write(out, reg).await?;
+        }
+        Ok(())
+    }
+
+    async fn set_mute(&mut self, mute: bool) -> Result<(), Self::Error> {
+        self.regs
+            .update(DAC_CONTROL3, 0x04, if mute { 0x04 } else { 0x00 })
+            .await
+    }
+
+    async fn set_mic_gain(&mut self, db: u8) -> Result<(), Self::Error> {
+        // 0dB to 24dB in 3dB steps, left gain in the high nibble
+        let gain = (db / 3).min(8);
+        self.regs.write(ADC_CONTROL1, gain << 4 | gain).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::audio::codec::testing::Bus;
+
+    fn init(bits: BitDepth) -> Bus {
+        let mut codec = Es8388::new(Bus::new(ADDRESS), ADDRESS);
+        block_on(codec.init(16000, bits)).unwrap();
+        codec.release()
+    }
+
+    #[test]
+    fn init_sequence() {
+        let bus = init(BitDepth::Bits16);
+        #[rustfmt::skip]
+        let expected = [
+            (DAC_CONTROL3, 0x04), (CONTROL2, 0x50), (CHIP_POWER, 0x00), (MASTER_MODE, 0x00),
+            (DAC_POWER, 0xC0), (CONTROL1, 0x12), (DAC_CONTROL1, 0x18), (DAC_CONTROL2, 0x02),
+            (DAC_CONTROL16, 0x00), (DAC_CONTROL17, 0x90), (DAC_CONTROL20, 0x90),
+            (DAC_CONTROL21, 0x80), (DAC_CONTROL23, 0x00), (DAC_CONTROL4, 0x00),
+            (DAC_CONTROL5, 0x00), (DAC_POWER, 0x3C),
+            (ADC_POWER, 0xFF), (ADC_CONTROL1, 0x88), (ADC_CONTROL2, 0x00),
+            (ADC_CONTROL3, 0x02), (ADC_CONTROL4, 0x0C), (ADC_CONTROL5, 0x02),
+            (ADC_CONTROL8, 0x00), (ADC_CONTROL9, 0x00), (ADC_POWER, 0x09),
+            (CHIP_POWER, 0xF0), (CHIP_POWER, 0x00), (ADC_POWER, 0x00), (DAC_CONTROL3, 0x00),
+        ];
+        assert_eq!(bus.writes, expected);
+    }
+
+    #[test]
+    fn word_length() {
+        for (bits, dac, adc) in [
+            (BitDepth::Bits16, 0x18, 0x0C),
+            (BitDepth::Bits24, 0x00, 0x00),
+            (BitDepth::Bits32, 0x20, 0x10),
+        ] {
+            let bus = init(bits);
+            assert_eq!(bus.registers[DAC_CONTROL1 as usize], dac, "{bits:?}");
+            assert_eq!(bus.registers[ADC_CONTROL4 as usize], adc, "{bits:?}");
+        }
+    }
+
+    #[test]
+    fn volume_sets_every_output() {
+        let mut codec = Es8388::new(Bus::new(ADDRESS), ADDRESS);
+        for (volume, reg) in [(0, 0x00), (50, 0x0F), (100, 0x1E), (255, 0x1E)] {
+            codec.regs.i2c.writes.clear();
+            block_on(codec.set_volume(volume)).unwrap();
+            let outputs = [DAC_CONTROL24, 0x2F, 0x30, DAC_CONTROL27].map(|out| (out, reg));
+            assert_eq!(codec.regs.i2c.writes, outputs, "{volume}");
+        }
+    }
+
+    #[test]
+    fn mute_keeps_other_bits() {
+        let mut bus = Bus::new(ADDRESS);
+        bus.registers[DAC_CONTROL3 as usize] = 0x22;
+        let mut codec = Es8388::new(bus, ADDRESS);
+        block_on(codec.set_mute(true)).unwrap();
+        assert_eq!(codec.regs.i2c.registers[DAC_CONTROL3 as usize], 0x26);
+        block_on(codec.set_mute(false)).unwrap();
+        assert_eq!(codec.regs.i2c.registers[DAC_CONTROL3 as usize], 0x22);
+    }
+
+    #[test]
+    fn mic_gain_on_both_channels() {
+        let mut codec = Es8388::new(Bus::new(ADDRESS), ADDRESS);
+        for (db, reg) in [(0, 0x00), (10, 0x33), (24, 0x88), (40, 0x88)] {
+            block_on(codec.set_mic_gain(db)).unwrap();
+            assert_eq!(codec.regs.i2c.registers[ADC_CONTROL1 as usize], reg, "{db}");
+        }
+    }
+}
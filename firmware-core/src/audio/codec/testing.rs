@@ -0,0 +1,52 @@
+//! A mock I²C bus for the codec tests.
+
+use core::convert::Infallible;
+
+use embedded_hal_async::i2c::{ErrorType, I2c, Operation};
+
+/// The register file of a chip at `address`, logging every write to it.
+pub struct Bus {
+    pub address: u8,
+    pub registers: [u8; 256],
+    pub writes: Vec<(u8, u8)>,
+}
+
+impl Bus {
+    pub fn new(address: u8) -> Self {
+        Self {
+            address,
+            registers: [0; 256],
+            writes: Vec::new(),
+        }
+    }
+}
+
+impl ErrorType for Bus {
+    type Error = Infallible;
+}
+
+impl I2c for Bus {
+    async fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Infallible> {
+        assert_eq!(address, self.address, "wrong chip address");
+        let mut selected = None;
+        for operation in operations {
+            match operation {
+                Operation::Write(&[reg]) => selected = Some(reg),
+                Operation::Write(&[reg, value]) => {
+                    self.registers[reg as usize] = value;
+                    self.writes.push((reg, value));
+                }
+                Operation::Read([value]) => {
+                    let reg = selected.take().expect("read without selecting a register");
+                    *value = self.registers[reg as usize];
+                }
+                operation => panic!("unexpected {operation:?}"),
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,335 @@
+//! How the mono PCM of the firmware maps to the slots of I²S devices.
+
+use either::Either;
+
+/// Width of the samples on the I²S bus.
+///
+/// Data is always MSB-aligned in its slot (Philips standard), so 24-bit
+/// devices are driven with 32-bit slots and the low byte is ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitDepth {
+    Bits16,
+    Bits24,
+    Bits32,
+}
+
+impl BitDepth {
+    /// Bytes taken by one channel slot on the bus.
+    pub const fn slot_bytes(self) -> usize {
+        match self {
+            BitDepth::Bits16 => 2,
+            BitDepth::Bits24 | BitDepth::Bits32 => 4,
+        }
+    }
+
+    /// Mask of the valid bits of a slot once widened to 32 bits.
+    const fn mask(self) -> i32 {
+        match self {
+            BitDepth::Bits16 => !0xffff,
+            BitDepth::Bits24 => !0xff,
+            BitDepth::Bits32 => !0,
+        }
+    }
+}
+
+/// Which slot(s) of the stereo I²S frame carry audio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelLayout {
+    Left,
+    Right,
+    /// Both slots; inputs are mixed down to mono and outputs are duplicated.
+    Stereo,
+}
+
+/// How mono `i16` PCM maps to the raw bytes of an I²S device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleFormat {
+    pub bits: BitDepth,
+    pub channels: ChannelLayout,
+    /// Gain applied as a bit shift, positive values amplify.
+    pub gain: i8,
+}
+
+impl SampleFormat {
+    /// INMP441 MEMS microphone, L/R pin tied low.
+    pub const INMP441: Self = Self {
+        bits: BitDepth::Bits24,
+        channels: ChannelLayout::Left,
+        gain: 4,
+    };
+
+    /// SPH0645 MEMS microphone, SEL pin tied low.
+    pub const SPH0645: Self = Self {
+        bits: BitDepth::Bits24,
+        channels: ChannelLayout::Left,
+        gain: 2,
+    };
+
+    /// ES7210 ADC with its power-on interface settings: 16-bit I²S, MIC1 in
+    /// the left slot of SDOUT1 and MIC2 in the right. The chip still has to
+    /// be powered up over I²C, and its PGA gain is set there too.
+    pub const ES7210: Self = Self {
+        bits: BitDepth::Bits16,
+        channels: ChannelLayout::Left,
+        gain: 0,
+    };
+
+    /// ES8311 codec, for both its ADC and DAC: mono in the left slot of 16-bit
+    /// I²S, the word length `Es8311::init` is given.
+    pub const ES8311: Self = Self {
+        bits: BitDepth::Bits16,
+        channels: ChannelLayout::Left,
+        gain: 0,
+    };
+
+    /// MAX98357 class D amplifier as the firmware always drove it: 32-bit
+    /// slots, audio in the right one at half scale. With SD_MODE left at its
+    /// default the amplifier plays the (L+R)/2 mix.
+    pub const MAX98357: Self = Self {
+        bits: BitDepth::Bits32,
+        channels: ChannelLayout::Right,
+        gain: -1,
+    };
+
+    /// Bytes taken by one stereo I²S frame.
+    pub const fn frame_bytes(&self) -> usize {
+        self.bits.slot_bytes() * 2
+    }
+
+    /// Convert raw I²S bytes into mono PCM, trailing partial frames are ignored.
+    pub fn decode<'a>(&self, raw: &'a [u8]) -> impl Iterator<Item = i16> + 'a {
+        let this = *self;
+        raw.chunks_exact(self.frame_bytes()).map(move |frame| {
+            let (left, right) = frame.split_at(this.bits.slot_bytes());
+            let (left, right) = (this.read_slot(left), this.read_slot(right));
+            let sample = match this.channels {
+                ChannelLayout::Left => left as i64,
+                ChannelLayout::Right => right as i64,
+                ChannelLayout::Stereo => (left as i64 + right as i64) / 2,
+            };
+            (shift(sample, this.gain) >> 16).clamp(i16::MIN as _, i16::MAX as _) as i16
+        })
+    }
+
+    /// Convert mono PCM into raw I²S bytes.
+    pub fn encode<'a>(
+        &self,
+        pcm: impl IntoIterator<Item = i16> + 'a,
+    ) -> impl Iterator<Item = u8> + 'a {
+        let this = *self;
+        pcm.into_iter().flat_map(move |sample| {
+            let slot = shift((sample as i64) << 16, this.gain).clamp(i32::MIN as _, i32::MAX as _)
+                as i32
+                & this.bits.mask();
+            let frame = match this.channels {
+                ChannelLayout::Left => [slot, 0],
+                ChannelLayout::Right => [0, slot],
+                ChannelLayout::Stereo => [slot, slot],
+            };
+            frame
+                .into_iter()
+                .flat_map(move |slot| this.write_slot(slot))
+        })
+    }
+
+    /// Read a slot as a full scale 32-bit sample.
+    fn read_slot(&self, slot: &[u8]) -> i32 {
+        let sample = match *slot {
+            [a, b] => (i16::from_le_bytes([a, b]) as i32) << 16,
+            [a, b, c, d] => i32::from_le_bytes([a, b, c, d]),
+            _ => unreachable!("slot is either 2 or 4 bytes"),
+        };
+        sample & self.bits.mask()
+    }
+
+    fn write_slot(&self, slot: i32) -> impl Iterator<Item = u8> {
+        match self.bits {
+            BitDepth::Bits16 => Either::Left(((slot >> 16) as i16).to_le_bytes().into_iter()),
+            BitDepth::Bits24 | BitDepth::Bits32 => Either::Right(slot.to_le_bytes().into_iter()),
+        }
+    }
+}
+
+fn shift(sample: i64, gain: i8) -> i64 {
+    if gain >= 0 {
+        sample << gain
+    } else {
+        sample >> gain.unsigned_abs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BITS: [BitDepth; 3] = [BitDepth::Bits16, BitDepth::Bits24, BitDepth::Bits32];
+    const LAYOUTS: [ChannelLayout; 3] = [
+        ChannelLayout::Left,
+        ChannelLayout::Right,
+        ChannelLayout::Stereo,
+    ];
+
+    fn formats() -> impl Iterator<Item = SampleFormat> {
+        BITS.into_iter().flat_map(|bits| {
+            LAYOUTS.into_iter().map(move |channels| SampleFormat {
+                bits,
+                channels,
+                gain: 0,
+            })
+        })
+    }
+
+    fn encode(format: SampleFormat, pcm: &[i16]) -> Vec<u8> {
+        format.encode(pcm.iter().copied()).collect()
+    }
+
+    fn decode(format: SampleFormat, raw: &[u8]) -> Vec<i16> {
+        format.decode(raw).collect()
+    }
+
+    /// Raw frames of two 32-bit slots.
+    fn frames(slots: &[[i32; 2]]) -> Vec<u8> {
+        slots
+            .iter()
+            .flatten()
+            .flat_map(|s| s.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let pcm = [0, 1, -1, 0x1234, -0x1234, i16::MAX, i16::MIN];
+        for format in formats() {
+            let raw = encode(format, &pcm);
+            assert_eq!(raw.len(), pcm.len() * format.frame_bytes(), "{format:?}");
+            assert_eq!(decode(format, &raw), pcm, "{format:?}");
+        }
+    }
+
+    #[test]
+    fn slot_layout() {
+        let raw = |bits, channels| {
+            encode(
+                SampleFormat {
+                    bits,
+                    channels,
+                    gain: 0,
+                },
+                &[0x1234],
+            )
+        };
+        use BitDepth::*;
+        use ChannelLayout::*;
+        assert_eq!(raw(Bits16, Left), [0x34, 0x12, 0, 0]);
+        assert_eq!(raw(Bits16, Right), [0, 0, 0x34, 0x12]);
+        assert_eq!(raw(Bits16, Stereo), [0x34, 0x12, 0x34, 0x12]);
+        for bits in [Bits24, Bits32] {
+            assert_eq!(raw(bits, Left), [0, 0, 0x34, 0x12, 0, 0, 0, 0]);
+            assert_eq!(raw(bits, Right), [0, 0, 0, 0, 0, 0, 0x34, 0x12]);
+            assert_eq!(raw(bits, Stereo), [0, 0, 0x34, 0x12, 0, 0, 0x34, 0x12]);
+        }
+    }
+
+    #[test]
+    fn decode_slots() {
+        let raw = frames(&[[0x1234_5678, -0x0765_4321], [0x0000_ffff, 0x0001_0000]]);
+        let format = |bits, channels| SampleFormat {
+            bits,
+            channels,
+            gain: 0,
+        };
+        use BitDepth::*;
+        use ChannelLayout::*;
+        for bits in [Bits24, Bits32] {
+            assert_eq!(decode(format(bits, Left), &raw), [0x1234, 0]);
+            assert_eq!(decode(format(bits, Right), &raw), [-0x0766, 1]);
+            assert_eq!(decode(format(bits, Stereo), &raw), [0x0567, 0]);
+        }
+        // the same bytes as 16-bit slots, four frames of them
+        assert_eq!(decode(format(Bits16, Left), &raw), [0x5678, -0x4321, -1, 0]);
+        assert_eq!(decode(format(Bits16, Right), &raw), [0x1234, -0x0766, 0, 1]);
+        assert_eq!(decode(format(Bits16, Stereo), &raw), [0x3456, -9540, -1, 0]);
+    }
+
+    #[test]
+    fn low_bits_ignored() {
+        // only the top 24 bits of a 24-bit slot are audio
+        let raw = frames(&[[0x0000_00ff, 0]]);
+        let format = SampleFormat {
+            bits: BitDepth::Bits24,
+            channels: ChannelLayout::Left,
+            gain: 16,
+        };
+        assert_eq!(decode(format, &raw), [0]);
+        let format = SampleFormat {
+            bits: BitDepth::Bits32,
+            ..format
+        };
+        assert_eq!(decode(format, &raw), [0xff]);
+    }
+
+    #[test]
+    fn partial_frames_ignored() {
+        for format in formats() {
+            let raw = encode(format, &[100, 200]);
+            let cut = &raw[..raw.len() - 1];
+            assert_eq!(decode(format, cut), [100], "{format:?}");
+        }
+    }
+
+    #[test]
+    fn gain() {
+        for format in formats() {
+            let louder = SampleFormat { gain: 2, ..format };
+            let quieter = SampleFormat { gain: -2, ..format };
+            let raw = encode(format, &[1000, -1000, 10000, -10000]);
+            assert_eq!(decode(louder, &raw), [4000, -4000, i16::MAX, i16::MIN]);
+            assert_eq!(decode(quieter, &raw), [250, -250, 2500, -2500]);
+
+            // amplified on the way out, clipping at full scale
+            let raw = encode(louder, &[1000, -1000, 10000, -10000]);
+            let expected = [4000, -4000, i16::MAX, i16::MIN];
+            assert_eq!(decode(format, &raw), expected, "{format:?}");
+            let raw = encode(quieter, &[1000, -1000]);
+            assert_eq!(decode(format, &raw), [250, -250], "{format:?}");
+        }
+    }
+
+    #[test]
+    fn inmp441_matches_old_shift() {
+        // the firmware used to take the left slot shifted down by 12
+        let mut state = 1u32;
+        let slots = (0..1000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                // a 24-bit sample, either at any level or near full scale
+                [(state & !0xff) as i32 >> (state % 12), state as i32]
+            })
+            .collect::<Vec<_>>();
+        let old = slots
+            .iter()
+            .map(|[left, _]| (left >> 12).clamp(i16::MIN as _, i16::MAX as _) as i16)
+            .collect::<Vec<_>>();
+        assert_eq!(decode(SampleFormat::INMP441, &frames(&slots)), old);
+    }
+
+    #[test]
+    fn max98357_matches_old_layout() {
+        // the firmware used to send [0, sample * 32112] frames, 2% under
+        // half scale
+        let pcm = [0, 1, -1, 1000, -1000, i16::MAX, i16::MIN];
+        let raw = encode(SampleFormat::MAX98357, &pcm);
+        for (frame, &sample) in raw.chunks_exact(8).zip(&pcm) {
+            let left = i32::from_le_bytes(frame[..4].try_into().unwrap());
+            let right = i32::from_le_bytes(frame[4..].try_into().unwrap());
+            let old = sample as i64 * 32112;
+            assert_eq!(left, 0);
+            assert!(
+                (right as i64 - old).abs() <= old.abs() / 40,
+                "{sample}: {right} vs {old}"
+            );
+        }
+    }
+}
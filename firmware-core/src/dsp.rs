@@ -0,0 +1,35 @@
+//! Portable signal processing for the audio pipeline.
+//!
+//! Nothing in here touches the hardware, everything works on mono `i16` PCM.
+
+pub mod aec;
+pub mod agc;
+pub mod filter;
+pub mod mfcc;
+pub mod ns;
+pub mod preprocess;
+pub mod resample;
+#[cfg(test)]
+mod testing;
+pub mod vad;
+pub mod wake;
+
+/// Integer square root, rounded down.
+fn isqrt(x: u64) -> u32 {
+    let mut root = 0u64;
+    let mut bit = 1u64 << (u64::BITS - 2);
+    let mut x = x;
+    while bit > x {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if x >= root + bit {
+            x -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+    root as u32
+}
@@ -0,0 +1,314 @@
+//! Acoustic echo cancellation.
+//!
+//! The speaker output is used as the reference signal. A [`DelayEstimator`]
+//! finds the bulk delay between playing a sample and hearing it on the mic,
+//! and an [`Nlms`] adaptive filter models the room response after that delay.
+
+extern crate alloc;
+use alloc::{vec, vec::Vec};
+
+const SCALE: f32 = 32768.0;
+/// Keeps the NLMS step bounded while the reference is silent.
+const REGULARIZATION: f32 = 1e-3;
+/// Geigel double-talk threshold, assumes at least 6dB of speaker to mic loss.
+const DOUBLE_TALK: f32 = 0.5;
+
+/// Normalized least mean squares adaptive FIR filter.
+pub struct Nlms {
+    weights: Vec<f32>,
+    /// Reference history stored twice, so the window is always contiguous.
+    history: Vec<f32>,
+    pos: usize,
+    energy: f32,
+    step: f32,
+}
+
+impl Nlms {
+    pub fn new(taps: usize, step: f32) -> Self {
+        Self {
+            weights: vec![0.0; taps],
+            history: vec![0.0; taps * 2],
+            pos: 0,
+            energy: 0.0,
+            step,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.weights.fill(0.0);
+    }
+
+    /// Push the next reference sample and remove its estimated echo from
+    /// `mic`, returning the residual.
+    pub fn process(&mut self, reference: f32, mic: f32, adapt: bool) -> f32 {
+        let taps = self.weights.len();
+        self.pos = self.pos.checked_sub(1).unwrap_or(taps - 1);
+        let oldest = self.history[self.pos];
+        self.energy = (self.energy + reference * reference - oldest * oldest).max(0.0);
+        self.history[self.pos] = reference;
+        self.history[self.pos + taps] = reference;
+
+        let window = &self.history[self.pos..self.pos + taps];
+        let estimate: f32 = self.weights.iter().zip(window).map(|(w, x)| w * x).sum();
+        let error = mic - estimate;
+        if adapt {
+            let gain = self.step * error / (self.energy + REGULARIZATION);
+            for (w, x) in self.weights.iter_mut().zip(window) {
+                *w += gain * x;
+            }
+        }
+        error
+    }
+}
+
+/// Estimates how many samples the mic lags behind the reference, by cross
+/// correlating the decimated envelopes of both signals.
+pub struct DelayEstimator {
+    decimation: usize,
+    count: usize,
+    envelope: (f32, f32),
+    mean: (f32, f32),
+    /// Reference envelope history stored twice, see [`Nlms::history`].
+    history: Vec<f32>,
+    pos: usize,
+    correlation: Vec<f32>,
+}
+
+impl DelayEstimator {
+    /// Smoothing of the envelope means and of the correlation.
+    const MEAN_ALPHA: f32 = 0.01;
+    const CORRELATION_ALPHA: f32 = 0.02;
+
+    pub fn new(max_delay: usize, decimation: usize) -> Self {
+        let lags = max_delay / decimation + 1;
+        Self {
+            decimation,
+            count: 0,
+            envelope: (0.0, 0.0),
+            mean: (0.0, 0.0),
+            history: vec![0.0; lags * 2],
+            pos: 0,
+            correlation: vec![0.0; lags],
+        }
+    }
+
+    /// Feed one sample of both signals, returns whether the estimate may
+    /// have changed.
+    pub fn push(&mut self, reference: f32, mic: f32) -> bool {
+        self.envelope.0 += reference.abs();
+        self.envelope.1 += mic.abs();
+        self.count += 1;
+        if self.count < self.decimation {
+            return false;
+        }
+
+        let (reference, mic) = self.envelope;
+        self.envelope = (0.0, 0.0);
+        self.count = 0;
+        self.mean.0 += Self::MEAN_ALPHA * (reference - self.mean.0);
+        self.mean.1 += Self::MEAN_ALPHA * (mic - self.mean.1);
+        let (reference, mic) = (reference - self.mean.0, mic - self.mean.1);
+
+        let lags = self.correlation.len();
+        self.pos = self.pos.checked_sub(1).unwrap_or(lags - 1);
+        self.history[self.pos] = reference;
+        self.history[self.pos + lags] = reference;
+        let window = &self.history[self.pos..self.pos + lags];
+        for (c, r) in self.correlation.iter_mut().zip(window) {
+            *c += Self::CORRELATION_ALPHA * (mic * r - *c);
+        }
+        true
+    }
+
+    /// Current estimate of the delay, in samples.
+    pub fn delay(&self) -> usize {
+        let lag = self
+            .correlation
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map_or(0, |(lag, _)| lag);
+        lag * self.decimation
+    }
+}
+
+/// Echo canceller combining a bulk delay line with an NLMS filter.
+pub struct EchoCanceller {
+    filter: Nlms,
+    estimator: DelayEstimator,
+    /// Reference samples waiting out the bulk delay.
+    delay_line: Vec<f32>,
+    pos: usize,
+    bulk_delay: usize,
+    /// A new delay estimate and for how many updates it has held.
+    candidate: (usize, u32),
+    /// Decaying peak of the delayed reference, for double-talk detection.
+    peak: f32,
+    /// Samples left before adapting again after double-talk.
+    hold: u32,
+}
+
+impl EchoCanceller {
+    const STEP: f32 = 0.1;
+    const DECIMATION: usize = 16;
+    const PEAK_DECAY: f32 = 0.9995;
+    /// Estimator updates a new delay has to hold before the filter moves to
+    /// it, so a passing correlation peak doesn't throw away a converged
+    /// filter.
+    const DELAY_HOLD: u32 = 250;
+    /// Samples adaptation stays off after double-talk was last seen, 30ms
+    /// at 16kHz, as speech doesn't peak on every sample.
+    const DOUBLE_TALK_HOLD: u32 = 480;
+
+    /// `taps` is the length of the modeled room response and `max_delay` the
+    /// longest bulk delay to look for, both in samples.
+    pub fn new(taps: usize, max_delay: usize) -> Self {
+        Self {
+            filter: Nlms::new(taps, Self::STEP),
+            estimator: DelayEstimator::new(max_delay, Self::DECIMATION),
+            delay_line: vec![0.0; max_delay + 1],
+            pos: 0,
+            bulk_delay: 0,
+            candidate: (0, 0),
+            peak: 0.0,
+            hold: 0,
+        }
+    }
+
+    pub fn bulk_delay(&self) -> usize {
+        self.bulk_delay
+    }
+
+    /// Cancel the echo of `reference` (the sample just sent to the speaker)
+    /// from the `mic` sample captured at the same time.
+    pub fn process(&mut self, reference: i16, mic: i16) -> i16 {
+        let (reference, mic) = (reference as f32 / SCALE, mic as f32 / SCALE);
+        if self.estimator.push(reference, mic) {
+            self.track_delay();
+        }
+
+        let len = self.delay_line.len();
+        self.delay_line[self.pos] = reference;
+        let delayed = self.delay_line[(self.pos + len - self.bulk_delay) % len];
+        self.pos = (self.pos + 1) % len;
+
+        self.peak = delayed.abs().max(self.peak * Self::PEAK_DECAY);
+        if mic.abs() >= DOUBLE_TALK * self.peak {
+            self.hold = Self::DOUBLE_TALK_HOLD;
+        } else {
+            self.hold = self.hold.saturating_sub(1);
+        }
+        let residual = self.filter.process(delayed, mic, self.hold == 0);
+        (residual * SCALE).clamp(i16::MIN as _, i16::MAX as _) as i16
+    }
+
+    /// Keep the estimated delay inside the filter window, starting the
+    /// window a quarter before it to absorb jitter.
+    fn track_delay(&mut self) {
+        let taps = self.filter.weights.len();
+        let target = self
+            .estimator
+            .delay()
+            .saturating_sub(taps / 4)
+            .min(self.delay_line.len() - 1);
+        if target.abs_diff(self.bulk_delay) <= taps / 4 {
+            self.candidate.1 = 0;
+            return;
+        }
+        if target.abs_diff(self.candidate.0) > taps / 4 {
+            self.candidate = (target, 0);
+        }
+        self.candidate.1 += 1;
+        if self.candidate.1 >= Self::DELAY_HOLD {
+            self.bulk_delay = target;
+            self.candidate.1 = 0;
+            self.filter.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::testing::{noise, power_db};
+
+    const RATE: usize = 16000;
+
+    /// The reference through a room: two taps after `delay` samples, 7dB
+    /// down at most.
+    fn echo(reference: &[i16], delay: usize) -> Vec<i16> {
+        (0..reference.len())
+            .map(|i| {
+                let tap = |d: usize| i.checked_sub(d).map_or(0.0, |i| reference[i] as f32);
+                (0.3 * tap(delay) - 0.15 * tap(delay + 3)) as i16
+            })
+            .collect()
+    }
+
+    fn cancel(aec: &mut EchoCanceller, reference: &[i16], mic: &[i16]) -> Vec<i16> {
+        reference
+            .iter()
+            .zip(mic)
+            .map(|(&r, &m)| aec.process(r, m))
+            .collect()
+    }
+
+    /// Echo return loss enhancement over the last second, in dB.
+    fn erle(mic: &[i16], residual: &[i16]) -> f32 {
+        let last = mic.len() - RATE..;
+        power_db(&mic[last.clone()]) - power_db(&residual[last])
+    }
+
+    #[test]
+    fn delayed_echo() {
+        let reference = noise(1, RATE * 6, 8000.0);
+        let mic = echo(&reference, 300);
+        let mut aec = EchoCanceller::new(256, 4000);
+        let residual = cancel(&mut aec, &reference, &mic);
+        let delay = aec.bulk_delay();
+        assert!((delay..delay + 256).contains(&300), "bulk delay {delay}");
+        let erle = erle(&mic, &residual);
+        assert!(erle > 60.0, "ERLE {erle}dB");
+    }
+
+    #[test]
+    fn double_talk() {
+        let reference = noise(1, RATE * 7, 8000.0);
+        let near = noise(2, RATE, 8000.0);
+        let mut mic = echo(&reference, 300);
+        let mut aec = EchoCanceller::new(256, 4000);
+        cancel(&mut aec, &reference[..RATE * 5], &mic[..RATE * 5]);
+
+        // both ends talking for a second
+        let talk = RATE * 5..RATE * 6;
+        for (m, n) in mic[talk.clone()].iter_mut().zip(&near) {
+            *m = m.saturating_add(*n);
+        }
+        let residual = cancel(&mut aec, &reference[talk.clone()], &mic[talk]);
+        let leak: Vec<_> = residual.iter().zip(&near).map(|(r, n)| r - n).collect();
+        let kept = power_db(&near) - power_db(&leak);
+        assert!(kept > 30.0, "near end {kept}dB above the residual echo");
+
+        // the filter must not have diverged
+        let after = RATE * 6..;
+        let residual = cancel(&mut aec, &reference[after.clone()], &mic[after.clone()]);
+        let erle = erle(&mic[after], &residual);
+        assert!(erle > 40.0, "ERLE {erle}dB");
+    }
+
+    #[test]
+    fn delay_change() {
+        let reference = noise(1, RATE * 12, 8000.0);
+        let (before, after) = reference.split_at(RATE * 5);
+        let mut aec = EchoCanceller::new(256, 4000);
+        cancel(&mut aec, before, &echo(before, 300));
+
+        // the echo path moves, e.g. the speaker buffer grew
+        let mic = echo(&reference, 1200);
+        let residual = cancel(&mut aec, after, &mic[RATE * 5..]);
+        let delay = aec.bulk_delay();
+        assert!((delay..delay + 256).contains(&1200), "bulk delay {delay}");
+        let erle = erle(&mic[RATE * 5..], &residual);
+        assert!(erle > 60.0, "ERLE {erle}dB");
+    }
+}
@@ -0,0 +1,206 @@
+//! Automatic gain control.
+//!
+//! The RMS level of every block is measured and the gain steered towards the
+//! one bringing it to the target level: quickly down on loud input, slowly
+//! up on quiet input. Blocks under the gate are considered silence and leave
+//! the gain alone, so background noise isn't pumped up between sentences.
+
+use super::isqrt;
+
+/// Fractional bits of the gain.
+const GAIN_BITS: u32 = 12;
+const UNITY: i32 = 1 << GAIN_BITS;
+
+#[derive(Debug, Clone, Copy)]
+pub struct AgcConfig {
+    /// RMS level the output is brought to.
+    pub target_dbfs: f32,
+    /// Most the input is ever amplified by.
+    pub max_gain_db: f32,
+    /// Blocks quieter than this don't change the gain.
+    pub gate_dbfs: f32,
+}
+
+impl Default for AgcConfig {
+    fn default() -> Self {
+        Self {
+            target_dbfs: -18.0,
+            max_gain_db: 20.0,
+            gate_dbfs: -45.0,
+        }
+    }
+}
+
+pub struct Agc {
+    target: u32,
+    gate: u32,
+    max_gain: i32,
+    /// Current gain and the one at the start of the block, interpolated
+    /// between over the block to avoid zipper noise.
+    gain: i32,
+    previous_gain: i32,
+    block: usize,
+    count: usize,
+    energy: u64,
+}
+
+impl Agc {
+    /// Shifts of the gain update towards the wanted gain, per 10ms block.
+    const ATTACK_SHIFT: u32 = 1;
+    const RELEASE_SHIFT: u32 = 5;
+
+    pub fn new(sample_rate: u32, config: AgcConfig) -> Self {
+        let linear = |db: f32| libm::powf(10.0, db / 20.0);
+        Self {
+            target: (linear(config.target_dbfs) * 32768.0) as u32,
+            gate: (linear(config.gate_dbfs) * 32768.0) as u32,
+            max_gain: (linear(config.max_gain_db) * UNITY as f32) as i32,
+            gain: UNITY,
+            previous_gain: UNITY,
+            block: (sample_rate / 100) as usize,
+            count: 0,
+            energy: 0,
+        }
+    }
+
+    /// Current gain, in dB.
+    pub fn gain_db(&self) -> f32 {
+        20.0 * libm::log10f(self.gain as f32 / UNITY as f32)
+    }
+
+    pub fn process(&mut self, sample: i16) -> i16 {
+        // in i64, a gain over 30dB takes more than 16 bits
+        let (gain, previous_gain) = (self.gain as i64, self.previous_gain as i64);
+        let gain = previous_gain + (gain - previous_gain) * self.count as i64 / self.block as i64;
+        self.energy += (sample as i64 * sample as i64) as u64;
+        self.count += 1;
+        if self.count == self.block {
+            self.update();
+        }
+        let out = (sample as i64 * gain) >> GAIN_BITS;
+        out.clamp(i16::MIN as i64, i16::MAX as i64) as i16
+    }
+
+    fn update(&mut self) {
+        let rms = isqrt(self.energy / self.count as u64);
+        self.energy = 0;
+        self.count = 0;
+        self.previous_gain = self.gain;
+        if rms < self.gate {
+            return;
+        }
+
+        let wanted = ((self.target as i64) << GAIN_BITS) / rms.max(1) as i64;
+        let wanted = wanted.min(self.max_gain as i64) as i32;
+        if wanted < self.gain {
+            self.gain += (wanted - self.gain) >> Self::ATTACK_SHIFT;
+        } else {
+            self.gain += (wanted - self.gain) >> Self::RELEASE_SHIFT;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::testing::{power_db, tone, wav};
+
+    const RATE: u32 = 16000;
+
+    fn run(agc: &mut Agc, pcm: &[i16]) -> Vec<i16> {
+        pcm.iter().map(|&s| agc.process(s)).collect()
+    }
+
+    /// Amplitude of a sine wave at `dbfs` RMS.
+    fn amplitude(dbfs: f32) -> f32 {
+        libm::powf(10.0, (dbfs + 3.01) / 20.0) * 32768.0
+    }
+
+    #[test]
+    fn settles_to_target() {
+        let config = AgcConfig {
+            max_gain_db: 30.0,
+            ..AgcConfig::default()
+        };
+        for level in [-40.0, -30.0, -18.0, -6.0] {
+            let mut agc = Agc::new(RATE, config);
+            let input = tone(440.0, RATE, 3 * RATE as usize, amplitude(level));
+            let output = run(&mut agc, &input);
+            let settled = power_db(&output[2 * RATE as usize..]);
+            assert!(
+                (settled - -18.0).abs() < 1.0,
+                "{level}dBFS settled at {settled}"
+            );
+            assert!((agc.gain_db() - (-18.0 - level)).abs() < 1.0);
+        }
+    }
+
+    #[test]
+    fn quick_attack() {
+        let mut agc = Agc::new(RATE, AgcConfig::default());
+        let input = tone(440.0, RATE, RATE as usize, amplitude(-6.0));
+        let output = run(&mut agc, &input);
+        // 100ms in, the gain is mostly down
+        let level = power_db(&output[1600..3200]);
+        assert!(level < -16.0, "{level}dBFS");
+    }
+
+    #[test]
+    fn gain_is_bounded() {
+        let mut agc = Agc::new(RATE, AgcConfig::default());
+        let input = tone(440.0, RATE, 3 * RATE as usize, amplitude(-44.0));
+        run(&mut agc, &input);
+        assert!((agc.gain_db() - 20.0).abs() < 0.1, "{}dB", agc.gain_db());
+    }
+
+    #[test]
+    fn silence_holds_gain() {
+        let mut agc = Agc::new(RATE, AgcConfig::default());
+        run(
+            &mut agc,
+            &tone(440.0, RATE, 3 * RATE as usize, amplitude(-30.0)),
+        );
+        let gain = agc.gain_db();
+        run(
+            &mut agc,
+            &tone(440.0, RATE, 3 * RATE as usize, amplitude(-60.0)),
+        );
+        assert_eq!(agc.gain_db(), gain);
+    }
+
+    #[test]
+    fn high_gain_clips_without_wrapping() {
+        let config = AgcConfig {
+            max_gain_db: 60.0,
+            gate_dbfs: -90.0,
+            ..AgcConfig::default()
+        };
+        let mut agc = Agc::new(RATE, config);
+        run(
+            &mut agc,
+            &tone(440.0, RATE, 5 * RATE as usize, amplitude(-75.0)),
+        );
+        assert!(agc.gain_db() > 50.0, "{}dB", agc.gain_db());
+
+        // a sudden loud sound, amplified before the gain comes down
+        let loud = tone(440.0, RATE, 160, 30000.0);
+        let output = run(&mut agc, &loud);
+        for (x, y) in loud.iter().zip(&output) {
+            assert_eq!(x.signum(), y.signum(), "{x} came out as {y}");
+        }
+    }
+
+    #[test]
+    fn quiet_speech() {
+        let speech = wav("speech").iter().map(|&s| s / 10).collect::<Vec<_>>();
+        let mut agc = Agc::new(RATE, AgcConfig::default());
+        // twice, the first time to settle
+        run(&mut agc, &speech);
+        let output = run(&mut agc, &speech);
+        let words = 2000..22400;
+        let before = power_db(&speech[words.clone()]);
+        let after = power_db(&output[words]);
+        assert!(after - before > 10.0, "{before}dBFS became {after}dBFS");
+        assert!(after < -12.0, "{after}dBFS");
+    }
+}
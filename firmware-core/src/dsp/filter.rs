@@ -0,0 +1,114 @@
+//! Fixed-point IIR filters.
+
+use core::f32::consts::PI;
+
+/// Fractional bits of the biquad coefficients.
+const COEFF_BITS: u32 = 28;
+/// Fractional bits kept in the output history, so that rounding errors
+/// don't build up in the feedback path.
+const STATE_BITS: u32 = 8;
+
+/// Second order Butterworth high-pass, removing DC offset and low rumble
+/// (handling noise, fans) below the speech band.
+pub struct HighPass {
+    /// `b0, b1, b2, a1, a2`, normalized so that `a0 == 1`.
+    coeffs: [i32; 5],
+    x: [i32; 2],
+    y: [i32; 2],
+}
+
+impl HighPass {
+    pub fn new(sample_rate: u32, cutoff_hz: u32) -> Self {
+        let w0 = 2.0 * PI * cutoff_hz as f32 / sample_rate as f32;
+        let alpha = libm::sinf(w0) / core::f32::consts::SQRT_2;
+        let cos = libm::cosf(w0);
+        let a0 = 1.0 + alpha;
+        let q = |c: f32| libm::roundf(c / a0 * (1 << COEFF_BITS) as f32) as i32;
+        Self {
+            coeffs: [
+                q((1.0 + cos) / 2.0),
+                q(-(1.0 + cos)),
+                q((1.0 + cos) / 2.0),
+                q(-2.0 * cos),
+                q(1.0 - alpha),
+            ],
+            x: [0; 2],
+            y: [0; 2],
+        }
+    }
+
+    pub fn process(&mut self, sample: i16) -> i16 {
+        let [b0, b1, b2, a1, a2] = self.coeffs.map(i64::from);
+        let [x1, x2] = self.x.map(i64::from);
+        let [y1, y2] = self.y.map(i64::from);
+        let x = sample as i64;
+        let acc = ((b0 * x + b1 * x1 + b2 * x2) << STATE_BITS) - a1 * y1 - a2 * y2;
+        let y = (acc >> COEFF_BITS) as i32;
+        self.x = [sample as i32, self.x[0]];
+        self.y = [y, self.y[0]];
+        let rounded = (y + (1 << (STATE_BITS - 1))) >> STATE_BITS;
+        rounded.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::testing::{power_db, tone, wav};
+
+    const RATE: u32 = 16000;
+
+    fn filter(pcm: &[i16]) -> Vec<i16> {
+        let mut high_pass = HighPass::new(RATE, 100);
+        pcm.iter().map(|&s| high_pass.process(s)).collect()
+    }
+
+    /// Change in level of a tone, once the filter settled.
+    fn response_db(freq: f32) -> f32 {
+        let input = tone(freq, RATE, RATE as usize, 10000.0);
+        let output = filter(&input);
+        power_db(&output[RATE as usize / 2..]) - power_db(&input[RATE as usize / 2..])
+    }
+
+    #[test]
+    fn dc_rejection() {
+        let output = filter(&[8000; 16000]);
+        assert!(
+            output[1600..].iter().all(|s| s.abs() <= 1),
+            "{:?}",
+            &output[1600..1610]
+        );
+    }
+
+    #[test]
+    fn frequency_response() {
+        // -3dB at the cutoff, 12dB per octave below
+        assert!(
+            (response_db(100.0) + 3.0).abs() < 0.5,
+            "{}",
+            response_db(100.0)
+        );
+        assert!(response_db(50.0) < -11.0, "{}", response_db(50.0));
+        assert!(response_db(25.0) < -23.0, "{}", response_db(25.0));
+        for freq in [300.0, 1000.0, 4000.0, 7000.0] {
+            assert!(
+                response_db(freq).abs() < 0.5,
+                "{freq}Hz: {}",
+                response_db(freq)
+            );
+        }
+    }
+
+    #[test]
+    fn speech_with_offset() {
+        let speech = wav("speech");
+        let offset = speech
+            .iter()
+            .map(|&s| s.saturating_add(3000))
+            .collect::<Vec<_>>();
+        let output = filter(&offset);
+        let mean = output.iter().map(|&s| s as i64).sum::<i64>() / output.len() as i64;
+        assert!(mean.abs() < 10, "mean {mean}");
+        assert!((power_db(&output) - power_db(&speech)).abs() < 0.5);
+    }
+}
@@ -0,0 +1,262 @@
+//! Mel-frequency cepstral coefficients.
+
+extern crate alloc;
+use alloc::{vec, vec::Vec};
+use core::f32::consts::PI;
+
+/// Cepstral coefficients per frame, `c0` (log energy) included.
+pub const COEFFS: usize = 13;
+pub type Features = [f32; COEFFS];
+
+const FFT_SIZE: usize = 512;
+const MEL_BANDS: usize = 26;
+
+/// Turns PCM into MFCC frames of 25ms every 10ms.
+pub struct Mfcc {
+    frame_len: usize,
+    hop: usize,
+    window: Vec<f32>,
+    /// Per mel band, the first FFT bin and its triangular weights.
+    filters: Vec<(usize, Vec<f32>)>,
+    dct: Vec<[f32; MEL_BANDS]>,
+    fft: Fft,
+    pending: Vec<f32>,
+}
+
+impl Mfcc {
+    pub fn new(sample_rate: u32) -> Self {
+        let frame_len = (sample_rate as usize * 25 / 1000).min(FFT_SIZE);
+        let hop = sample_rate as usize / 100;
+        let window = (0..frame_len)
+            .map(|i| 0.54 - 0.46 * libm::cosf(2.0 * PI * i as f32 / (frame_len - 1) as f32))
+            .collect();
+
+        let mel = |hz: f32| 2595.0 * libm::log10f(1.0 + hz / 700.0);
+        let hz = |mel: f32| 700.0 * (libm::powf(10.0, mel / 2595.0) - 1.0);
+        let top = mel(sample_rate as f32 / 2.0);
+        let bin = |i: usize| {
+            let f = hz(top * i as f32 / (MEL_BANDS + 1) as f32);
+            libm::floorf((FFT_SIZE + 1) as f32 * f / sample_rate as f32) as usize
+        };
+        let filters = (0..MEL_BANDS)
+            .map(|band| {
+                let (lo, mid, hi) = (bin(band), bin(band + 1), bin(band + 2));
+                let weights = (lo..hi)
+                    .map(|k| {
+                        if k < mid {
+                            (k - lo) as f32 / (mid - lo).max(1) as f32
+                        } else {
+                            (hi - k) as f32 / (hi - mid).max(1) as f32
+                        }
+                    })
+                    .collect();
+                (lo, weights)
+            })
+            .collect();
+
+        let dct = (0..COEFFS)
+            .map(|n| {
+                core::array::from_fn(|m| {
+                    libm::cosf(PI * n as f32 * (m as f32 + 0.5) / MEL_BANDS as f32)
+                })
+            })
+            .collect();
+
+        Self {
+            frame_len,
+            hop,
+            window,
+            filters,
+            dct,
+            fft: Fft::new(FFT_SIZE),
+            pending: Vec::with_capacity(frame_len + hop),
+        }
+    }
+
+    /// Feed PCM, calling `f` for every complete frame of features.
+    pub fn push(&mut self, pcm: &[i16], mut f: impl FnMut(&Features)) {
+        for &sample in pcm {
+            self.pending.push(sample as f32 / 32768.0);
+            if self.pending.len() == self.frame_len {
+                f(&self.frame());
+                self.pending.drain(..self.hop);
+            }
+        }
+    }
+
+    /// Drop samples still waiting for a complete frame.
+    pub fn reset(&mut self) {
+        self.pending.clear();
+    }
+
+    fn frame(&mut self) -> Features {
+        let (re, im) = self.fft.buffers();
+        re.fill(0.0);
+        im.fill(0.0);
+        // pre-emphasis, then windowing
+        let mut prev = 0.0;
+        for ((out, &x), w) in re.iter_mut().zip(&self.pending).zip(&self.window) {
+            *out = (x - 0.97 * prev) * w;
+            prev = x;
+        }
+        self.fft.run();
+
+        let (re, im) = self.fft.buffers();
+        let mut energies = [0.0; MEL_BANDS];
+        for (energy, (lo, weights)) in energies.iter_mut().zip(&self.filters) {
+            let power = weights
+                .iter()
+                .zip(&re[*lo..])
+                .zip(&im[*lo..])
+                .map(|((w, re), im)| w * (re * re + im * im))
+                .sum::<f32>();
+            *energy = libm::logf(power.max(1e-10));
+        }
+        core::array::from_fn(|n| self.dct[n].iter().zip(&energies).map(|(c, e)| c * e).sum())
+    }
+}
+
+/// In-place iterative radix-2 complex FFT.
+struct Fft {
+    re: Vec<f32>,
+    im: Vec<f32>,
+    twiddles: Vec<(f32, f32)>,
+}
+
+impl Fft {
+    fn new(size: usize) -> Self {
+        debug_assert!(size.is_power_of_two());
+        let twiddles = (0..size / 2)
+            .map(|k| {
+                let angle = -2.0 * PI * k as f32 / size as f32;
+                (libm::cosf(angle), libm::sinf(angle))
+            })
+            .collect();
+        Self {
+            re: vec![0.0; size],
+            im: vec![0.0; size],
+            twiddles,
+        }
+    }
+
+    fn buffers(&mut self) -> (&mut [f32], &mut [f32]) {
+        (&mut self.re, &mut self.im)
+    }
+
+    fn run(&mut self) {
+        let n = self.re.len();
+        let bits = n.trailing_zeros();
+        for i in 0..n {
+            let j = i.reverse_bits() >> (usize::BITS - bits);
+            if i < j {
+                self.re.swap(i, j);
+                self.im.swap(i, j);
+            }
+        }
+
+        let mut len = 2;
+        while len <= n {
+            let stride = n / len;
+            for start in (0..n).step_by(len) {
+                for k in 0..len / 2 {
+                    let (wr, wi) = self.twiddles[k * stride];
+                    let (a, b) = (start + k, start + k + len / 2);
+                    let tr = self.re[b] * wr - self.im[b] * wi;
+                    let ti = self.re[b] * wi + self.im[b] * wr;
+                    self.re[b] = self.re[a] - tr;
+                    self.im[b] = self.im[a] - ti;
+                    self.re[a] += tr;
+                    self.im[a] += ti;
+                }
+            }
+            len *= 2;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::testing::{noise, wav};
+
+    const RATE: u32 = 16000;
+
+    fn features(mfcc: &mut Mfcc, pcm: &[i16]) -> Vec<Features> {
+        let mut frames = Vec::new();
+        mfcc.push(pcm, |f| frames.push(*f));
+        frames
+    }
+
+    #[test]
+    fn framing() {
+        let speech = wav("speech");
+        let whole = features(&mut Mfcc::new(RATE), &speech);
+        // 25ms frames every 10ms
+        assert_eq!(whole.len(), (speech.len() - 400) / 160 + 1);
+
+        let mut mfcc = Mfcc::new(RATE);
+        let chunked = speech
+            .chunks(333)
+            .flat_map(|chunk| features(&mut mfcc, chunk))
+            .collect::<Vec<_>>();
+        assert_eq!(chunked, whole);
+    }
+
+    #[test]
+    fn reset_drops_pending() {
+        let speech = wav("speech");
+        let mut mfcc = Mfcc::new(RATE);
+        features(&mut mfcc, &noise(1, 300, 10000.0));
+        mfcc.reset();
+        assert_eq!(
+            features(&mut mfcc, &speech),
+            features(&mut Mfcc::new(RATE), &speech)
+        );
+    }
+
+    #[test]
+    fn loudness_only_moves_c0() {
+        let speech = wav("speech");
+        let quiet = speech.iter().map(|&s| s / 10).collect::<Vec<_>>();
+        let loud = features(&mut Mfcc::new(RATE), &speech);
+        let quiet = features(&mut Mfcc::new(RATE), &quiet);
+        // every band loses 20dB, 26 bands of natural log power
+        let drop = MEL_BANDS as f32 * libm::logf(100.0);
+        let mut voiced = 0;
+        for (loud, quiet) in loud.iter().zip(&quiet).filter(|(loud, _)| loud[0] > -100.0) {
+            voiced += 1;
+            assert!(
+                (loud[0] - quiet[0] - drop).abs() < 2.0,
+                "{loud:?} {quiet:?}"
+            );
+            for (a, b) in loud[1..].iter().zip(&quiet[1..]) {
+                assert!((a - b).abs() < 1.0, "{loud:?} {quiet:?}");
+            }
+        }
+        assert!(voiced > 80, "{voiced} voiced frames");
+    }
+
+    #[test]
+    fn fft_matches_dft() {
+        let input = noise(3, 64, 32767.0)
+            .into_iter()
+            .map(|s| s as f32 / 32768.0)
+            .collect::<Vec<_>>();
+        let mut fft = Fft::new(64);
+        let (re, im) = fft.buffers();
+        re.copy_from_slice(&input);
+        im.fill(0.0);
+        fft.run();
+
+        for k in 0..64 {
+            let (mut dft_re, mut dft_im) = (0.0, 0.0);
+            for (n, &x) in input.iter().enumerate() {
+                let angle = -2.0 * PI * (k * n) as f32 / 64.0;
+                dft_re += x * libm::cosf(angle);
+                dft_im += x * libm::sinf(angle);
+            }
+            assert!((fft.re[k] - dft_re).abs() < 1e-3, "bin {k}");
+            assert!((fft.im[k] - dft_im).abs() < 1e-3, "bin {k}");
+        }
+    }
+}
@@ -0,0 +1,289 @@
+//! Noise suppression by spectral subtraction.
+//!
+//! The signal is cut into half-overlapping frames with a square root Hann
+//! window, so that analysis and synthesis windows together add back up to
+//! one. In each frame, the power of the stationary noise estimated in every
+//! frequency bin is subtracted, and the frame is resynthesized.
+
+extern crate alloc;
+use alloc::{vec, vec::Vec};
+use core::f32::consts::PI;
+
+use super::isqrt;
+
+const FRAME: usize = 256;
+const HOP: usize = FRAME / 2;
+const BINS: usize = FRAME / 2 + 1;
+const Q15: i32 = 1 << 15;
+
+#[derive(Debug, Clone, Copy)]
+pub struct NsConfig {
+    /// Most a bin is ever attenuated by, higher values remove more noise but
+    /// leave more "musical" artifacts.
+    pub max_attenuation_db: f32,
+    /// How many times the noise estimate is subtracted.
+    pub over_subtraction: f32,
+}
+
+impl Default for NsConfig {
+    fn default() -> Self {
+        Self {
+            max_attenuation_db: 15.0,
+            over_subtraction: 2.0,
+        }
+    }
+}
+
+pub struct NoiseSuppressor {
+    /// Q15 square root Hann window.
+    window: Vec<i32>,
+    fft: Fft,
+    /// Last `FRAME` input samples.
+    input: Vec<i32>,
+    /// Overlap-add accumulator.
+    output: Vec<i32>,
+    /// Processed samples handed out while the next hop is collected.
+    ready: Vec<i16>,
+    pos: usize,
+    /// Noise power per bin.
+    noise: Vec<u64>,
+    /// Q15 gain applied to each bin in the previous frame.
+    gains: Vec<i32>,
+    /// Q15 lowest gain.
+    floor: i32,
+    /// Q4 over-subtraction factor.
+    over_subtraction: u64,
+    frames: u32,
+}
+
+impl NoiseSuppressor {
+    /// Frames averaged for the initial noise estimate.
+    const LEARN_FRAMES: u32 = 16;
+    /// Bins louder than this many times the noise are likely speech.
+    const SPEECH_RATIO: u64 = 4;
+    /// Shift of the noise update on bins that are likely noise.
+    const NOISE_SHIFT: u32 = 4;
+    /// Shift of the noise update on bins that are likely speech, slow enough
+    /// that speech barely leaks into the estimate but a louder background
+    /// is still picked up eventually.
+    const SPEECH_SHIFT: u32 = 9;
+
+    pub fn new(config: NsConfig) -> Self {
+        let window = (0..FRAME)
+            .map(|i| {
+                let hann = 0.5 - 0.5 * libm::cosf(2.0 * PI * i as f32 / FRAME as f32);
+                libm::roundf(libm::sqrtf(hann) * Q15 as f32) as i32
+            })
+            .collect();
+        let floor = libm::powf(10.0, -config.max_attenuation_db / 20.0);
+        Self {
+            window,
+            fft: Fft::new(FRAME),
+            input: vec![0; FRAME],
+            output: vec![0; FRAME],
+            ready: vec![0; HOP],
+            pos: 0,
+            noise: vec![0; BINS],
+            gains: vec![Q15; BINS],
+            floor: (floor * Q15 as f32) as i32,
+            over_subtraction: (config.over_subtraction * 16.0) as u64,
+            frames: 0,
+        }
+    }
+
+    /// Push the next sample, returning a processed one from `FRAME` samples
+    /// earlier.
+    pub fn process(&mut self, sample: i16) -> i16 {
+        let out = self.ready[self.pos];
+        self.input[FRAME - HOP + self.pos] = sample as i32;
+        self.pos += 1;
+        if self.pos == HOP {
+            self.pos = 0;
+            self.frame();
+            self.input.copy_within(HOP.., 0);
+        }
+        out
+    }
+
+    fn frame(&mut self) {
+        let (re, im) = self.fft.buffers();
+        for ((re, &x), &w) in re.iter_mut().zip(&self.input).zip(&self.window) {
+            *re = (x * w) >> 15;
+        }
+        im.fill(0);
+        self.fft.run();
+
+        let learning = self.frames < Self::LEARN_FRAMES;
+        self.frames = self.frames.saturating_add(1);
+        let (re, im) = self.fft.buffers();
+        for k in 0..BINS {
+            let (r, i) = (re[k] as i64, im[k] as i64);
+            let power = (r * r + i * i) as u64;
+            let noise = &mut self.noise[k];
+            if learning {
+                *noise = (*noise * (self.frames - 1) as u64 + power) / self.frames as u64;
+            } else if power < *noise {
+                *noise -= (*noise - power) >> Self::NOISE_SHIFT;
+            } else if power < *noise * Self::SPEECH_RATIO {
+                *noise += (power - *noise) >> Self::NOISE_SHIFT;
+            } else {
+                *noise += (power - *noise) >> Self::SPEECH_SHIFT;
+            }
+
+            let subtracted = (*noise * self.over_subtraction) >> 4;
+            let gain = if power > subtracted {
+                // magnitude gain sqrt(1 - a * N / P), in Q15
+                let shift = (u64::BITS - power.leading_zeros()).saturating_sub(33);
+                let (left, power) = ((power - subtracted) >> shift, power >> shift);
+                isqrt((left << 30) / power) as i32
+            } else {
+                0
+            };
+            // average with the previous frame against musical noise
+            let gain = ((gain + self.gains[k]) / 2).max(self.floor);
+            self.gains[k] = gain;
+            re[k] = ((re[k] as i64 * gain as i64) >> 15) as i32;
+            im[k] = ((im[k] as i64 * gain as i64) >> 15) as i32;
+            if k != 0 && k != FRAME / 2 {
+                re[FRAME - k] = re[k];
+                im[FRAME - k] = -im[k];
+            }
+        }
+
+        // inverse FFT through conjugation, forward scaling by 1/N
+        im.iter_mut().for_each(|i| *i = -*i);
+        self.fft.run();
+        let (re, _) = self.fft.buffers();
+        for ((out, &x), &w) in self.output.iter_mut().zip(re.iter()).zip(&self.window) {
+            *out += ((x as i64 * w as i64) >> (15 + FRAME.trailing_zeros())) as i32;
+        }
+
+        for (ready, &x) in self.ready.iter_mut().zip(&self.output) {
+            *ready = x.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        }
+        self.output.copy_within(HOP.., 0);
+        self.output[FRAME - HOP..].fill(0);
+    }
+}
+
+/// In-place iterative radix-2 complex FFT over integers, without scaling.
+/// Outputs grow by up to `log2(size)` bits over the inputs.
+struct Fft {
+    re: Vec<i32>,
+    im: Vec<i32>,
+    /// Q30 twiddle factors.
+    twiddles: Vec<(i32, i32)>,
+}
+
+impl Fft {
+    fn new(size: usize) -> Self {
+        debug_assert!(size.is_power_of_two());
+        let q30 = (1 << 30) as f32;
+        let twiddles = (0..size / 2)
+            .map(|k| {
+                let angle = -2.0 * PI * k as f32 / size as f32;
+                let q = |x: f32| libm::roundf(x * q30) as i32;
+                (q(libm::cosf(angle)), q(libm::sinf(angle)))
+            })
+            .collect();
+        Self {
+            re: vec![0; size],
+            im: vec![0; size],
+            twiddles,
+        }
+    }
+
+    fn buffers(&mut self) -> (&mut [i32], &mut [i32]) {
+        (&mut self.re, &mut self.im)
+    }
+
+    fn run(&mut self) {
+        let n = self.re.len();
+        let bits = n.trailing_zeros();
+        for i in 0..n {
+            let j = i.reverse_bits() >> (usize::BITS - bits);
+            if i < j {
+                self.re.swap(i, j);
+                self.im.swap(i, j);
+            }
+        }
+
+        let mut len = 2;
+        while len <= n {
+            let stride = n / len;
+            for start in (0..n).step_by(len) {
+                for k in 0..len / 2 {
+                    let (wr, wi) = self.twiddles[k * stride];
+                    let (wr, wi) = (wr as i64, wi as i64);
+                    let (a, b) = (start + k, start + k + len / 2);
+                    let (br, bi) = (self.re[b] as i64, self.im[b] as i64);
+                    let tr = ((br * wr - bi * wi) >> 30) as i32;
+                    let ti = ((br * wi + bi * wr) >> 30) as i32;
+                    self.re[b] = self.re[a] - tr;
+                    self.im[b] = self.im[a] - ti;
+                    self.re[a] += tr;
+                    self.im[a] += ti;
+                }
+            }
+            len *= 2;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::testing::{noise, power_db, tone, wav};
+
+    const RATE: usize = 16000;
+
+    /// The suppressor's output, lined up with its input.
+    fn suppress(pcm: &[i16]) -> Vec<i16> {
+        let mut ns = NoiseSuppressor::new(NsConfig::default());
+        let mut out = pcm.iter().map(|&s| ns.process(s)).collect::<Vec<_>>();
+        out.drain(..FRAME);
+        out
+    }
+
+    /// `signal` over `background`, starting a second in.
+    fn mix(background: &[i16], signal: &[i16]) -> Vec<i16> {
+        let mut pcm = background.to_vec();
+        for (x, s) in pcm[RATE..].iter_mut().zip(signal) {
+            *x = x.saturating_add(*s);
+        }
+        pcm
+    }
+
+    #[test]
+    fn tone_bursts_in_noise() {
+        // 200ms beeps every 400ms after a second of noise
+        let beeps = tone(1000.0, RATE as u32, 2 * RATE, 10000.0)
+            .chunks(RATE / 5)
+            .enumerate()
+            .flat_map(|(i, chunk)| chunk.iter().map(move |&s| if i % 2 == 0 { s } else { 0 }))
+            .collect::<Vec<_>>();
+        let hiss = noise(1, 3 * RATE, 1000.0);
+        let output = suppress(&mix(&hiss, &beeps));
+
+        let reduction = power_db(&output[RATE / 2..RATE]) - power_db(&hiss[RATE / 2..RATE]);
+        let tone_change =
+            power_db(&output[RATE..output.len()]) - power_db(&beeps[..output.len() - RATE]);
+        assert!(reduction < -10.0, "noise reduced by {reduction}dB");
+        assert!(tone_change.abs() < 1.0, "tone changed by {tone_change}dB");
+    }
+
+    #[test]
+    fn speech_in_noise() {
+        let speech = wav("speech");
+        let hiss = noise(2, RATE + speech.len(), 300.0);
+        let output = suppress(&mix(&hiss, &speech));
+
+        // speech runs from 0.12s to 1.4s after the second of noise
+        let words = RATE / 8..RATE * 14 / 10;
+        let change =
+            power_db(&output[RATE + words.start..RATE + words.end]) - power_db(&speech[words]);
+        let reduction = power_db(&output[RATE / 2..RATE]) - power_db(&hiss[RATE / 2..RATE]);
+        assert!(change.abs() < 1.0, "speech changed by {change}dB");
+        assert!(reduction < -10.0, "noise reduced by {reduction}dB");
+    }
+}
@@ -0,0 +1,95 @@
+//! Mic preprocessing ahead of the encoder.
+
+use super::{
+    agc::{Agc, AgcConfig},
+    filter::HighPass,
+    ns::{NoiseSuppressor, NsConfig},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct PreprocessConfig {
+    /// Cutoff of the high-pass filter, in Hz.
+    pub high_pass_hz: Option<u32>,
+    pub noise_suppression: Option<NsConfig>,
+    pub agc: Option<AgcConfig>,
+}
+
+impl Default for PreprocessConfig {
+    fn default() -> Self {
+        Self {
+            high_pass_hz: Some(100),
+            noise_suppression: Some(NsConfig::default()),
+            agc: Some(AgcConfig::default()),
+        }
+    }
+}
+
+/// High-pass filter, noise suppression and gain control, in that order, each
+/// stage being optional.
+pub struct Preprocessor {
+    high_pass: Option<HighPass>,
+    noise_suppression: Option<NoiseSuppressor>,
+    agc: Option<Agc>,
+}
+
+impl Preprocessor {
+    pub fn new(sample_rate: u32, config: PreprocessConfig) -> Self {
+        Self {
+            high_pass: config.high_pass_hz.map(|hz| HighPass::new(sample_rate, hz)),
+            noise_suppression: config.noise_suppression.map(NoiseSuppressor::new),
+            agc: config.agc.map(|config| Agc::new(sample_rate, config)),
+        }
+    }
+
+    pub fn process(&mut self, mut sample: i16) -> i16 {
+        if let Some(high_pass) = &mut self.high_pass {
+            sample = high_pass.process(sample);
+        }
+        if let Some(ns) = &mut self.noise_suppression {
+            sample = ns.process(sample);
+        }
+        if let Some(agc) = &mut self.agc {
+            sample = agc.process(sample);
+        }
+        sample
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::testing::{noise, power_db, wav};
+
+    const RATE: usize = 16000;
+
+    #[test]
+    fn speech_in_noise() {
+        let speech = wav("speech");
+        // two seconds of hiss with a DC offset, the speech quietly over it
+        let background = noise(3, 2 * RATE + speech.len(), 100.0)
+            .into_iter()
+            .map(|s| s + 2000)
+            .collect::<Vec<_>>();
+        let mut input = background.clone();
+        for (x, s) in input[2 * RATE..].iter_mut().zip(&speech) {
+            *x = x.saturating_add(s / 8);
+        }
+
+        let mut preprocessor = Preprocessor::new(RATE as u32, PreprocessConfig::default());
+        let output = input
+            .iter()
+            .map(|&s| preprocessor.process(s))
+            .collect::<Vec<_>>();
+
+        let mean = output[RATE..].iter().map(|&s| s as i64).sum::<i64>() / RATE as i64;
+        assert!(mean.abs() < 20, "mean {mean}");
+        // the words brought up towards -18dBFS, the noise before them down
+        let words = 2 * RATE + RATE / 8..2 * RATE + RATE * 14 / 10;
+        let level = power_db(&output[words]);
+        assert!((-24.0..-14.0).contains(&level), "speech at {level}dBFS");
+        // the offset's step at power on nudges the gain up a little
+        let hiss = power_db(&noise(3, RATE, 100.0));
+        let residual = power_db(&output[RATE..2 * RATE]);
+        assert!(residual < hiss - 8.0, "noise at {residual}dBFS");
+    }
+}
@@ -0,0 +1,226 @@
+//! Sample rate conversion.
+//!
+//! A Kaiser windowed sinc low-pass is tabulated at [`PHASES`] points per
+//! input sample. Each output sample is computed at its exact rational
+//! position between input samples, with the filter coefficients linearly
+//! interpolated between the two nearest phases, so any pair of rates works
+//! with a small table.
+
+extern crate alloc;
+use alloc::vec::Vec;
+use core::f32::consts::PI;
+
+/// Filter phases tabulated per input sample.
+const PHASES: usize = 64;
+/// Filter length, in samples at the lower of the two rates.
+const TAPS: usize = 48;
+/// Kaiser window shape. The 16 bit coefficients limit the stopband to about
+/// 70dB anyway.
+const BETA: f32 = 8.0;
+/// Cutoff relative to the lower Nyquist frequency, placing the transition
+/// band just below it.
+const CUTOFF: f32 = 0.88;
+
+pub struct Resampler {
+    /// Input samples consumed per `up` output samples.
+    down: u32,
+    up: u32,
+    /// Offset of the next output sample into the filter, in units of
+    /// `1 / up` input samples.
+    time: u32,
+    /// Q15 filter, `PHASES` entries per input sample plus a trailing zero.
+    table: Vec<i16>,
+    taps: usize,
+    /// Input history stored twice, so the window is always contiguous.
+    history: Vec<i16>,
+    pos: usize,
+}
+
+impl Resampler {
+    pub fn new(from: u32, to: u32) -> Self {
+        let gcd = gcd(from, to);
+        let (down, up) = (from / gcd, to / gcd);
+
+        // when decimating, the filter spans more input samples
+        let taps = TAPS * from as usize / from.min(to) as usize;
+        // cutoff in cycles per input sample
+        let cutoff = CUTOFF * 0.5 * from.min(to) as f32 / from as f32;
+        let center = taps as f32 / 2.0;
+        let table = (0..=taps * PHASES)
+            .map(|i| {
+                let t = i as f32 / PHASES as f32 - center;
+                let window =
+                    bessel_i0(BETA * libm::sqrtf((1.0 - (t / center) * (t / center)).max(0.0)))
+                        / bessel_i0(BETA);
+                let h = 2.0 * cutoff * sinc(2.0 * cutoff * t) * window;
+                libm::roundf(h * 32768.0).clamp(i16::MIN as f32, i16::MAX as f32) as i16
+            })
+            .collect();
+
+        Self {
+            down,
+            up,
+            time: 0,
+            table,
+            taps,
+            history: alloc::vec![0; taps * 2],
+            pos: 0,
+        }
+    }
+
+    /// Feed the next input sample, calling `f` with every output sample it
+    /// completes.
+    pub fn push(&mut self, sample: i16, mut f: impl FnMut(i16)) {
+        self.pos = self.pos.checked_sub(1).unwrap_or(self.taps - 1);
+        self.history[self.pos] = sample;
+        self.history[self.pos + self.taps] = sample;
+
+        while self.time < self.up {
+            f(self.output());
+            self.time += self.down;
+        }
+        self.time -= self.up;
+    }
+
+    /// Feed a slice of input samples, see [`Self::push`].
+    pub fn process(&mut self, pcm: &[i16], mut f: impl FnMut(i16)) {
+        for &sample in pcm {
+            self.push(sample, &mut f);
+        }
+    }
+
+    fn output(&self) -> i16 {
+        let scaled = self.time as u64 * PHASES as u64;
+        let first = (scaled / self.up as u64) as usize;
+        let frac = ((scaled % self.up as u64) << 15) / self.up as u64;
+        let frac = frac as i32;
+
+        let window = &self.history[self.pos..self.pos + self.taps];
+        let mut acc = 0i64;
+        for (j, &x) in window.iter().enumerate() {
+            let i = j * PHASES + first;
+            let (a, b) = (self.table[i] as i32, self.table[i + 1] as i32);
+            let c = a + (((b - a) * frac) >> 15);
+            acc += x as i64 * c as i64;
+        }
+        (acc >> 15).clamp(i16::MIN as i64, i16::MAX as i64) as i16
+    }
+}
+
+fn gcd(mut a: u32, mut b: u32) -> u32 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+fn sinc(x: f32) -> f32 {
+    if x == 0.0 {
+        1.0
+    } else {
+        libm::sinf(PI * x) / (PI * x)
+    }
+}
+
+/// Zeroth order modified Bessel function of the first kind.
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    for k in 1..32 {
+        term *= (x / (2.0 * k as f32)) * (x / (2.0 * k as f32));
+        sum += term;
+        if term < sum * 1e-8 {
+            break;
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::testing::{noise, power_db, tone};
+
+    const RATES: [u32; 3] = [24000, 44100, 48000];
+
+    fn resample(from: u32, to: u32, pcm: &[i16]) -> Vec<i16> {
+        let mut out = Vec::new();
+        Resampler::new(from, to).process(pcm, |s| out.push(s));
+        out
+    }
+
+    /// Gain of a tone through 48kHz to 16kHz, past the filter's start up.
+    fn gain_db(freq: f32) -> f32 {
+        let input = tone(freq, 48000, 48000, 16384.0);
+        let output = resample(48000, 16000, &input);
+        power_db(&output[1600..]) - power_db(&input[4800..])
+    }
+
+    #[test]
+    fn passband_is_flat() {
+        for freq in [100.0, 1000.0, 3000.0, 5000.0, 6000.0] {
+            let gain = gain_db(freq);
+            assert!(gain.abs() < 0.1, "{freq}Hz: {gain:.2}dB");
+        }
+    }
+
+    #[test]
+    fn transition_band() {
+        let gain = gain_db(7000.0);
+        assert!((-8.0..-3.0).contains(&gain), "{gain:.2}dB");
+    }
+
+    #[test]
+    fn no_aliasing() {
+        for freq in [10000.0, 12000.0, 16000.0, 20000.0, 23000.0] {
+            let gain = gain_db(freq);
+            assert!(gain < -50.0, "{freq}Hz: {gain:.2}dB");
+        }
+    }
+
+    #[test]
+    fn output_length() {
+        for rate in RATES {
+            for (from, to) in [(16000, rate), (rate, 16000)] {
+                let output = resample(from, to, &noise(1, from as usize * 3, 1000.0));
+                assert_eq!(output.len(), to as usize * 3, "{from}Hz to {to}Hz");
+            }
+        }
+    }
+
+    #[test]
+    fn chunks_match_one_go() {
+        for rate in RATES {
+            for (from, to) in [(16000, rate), (rate, 16000)] {
+                let input = noise(2, from as usize, 10000.0);
+                let mut resampler = Resampler::new(from, to);
+                let mut chunked = Vec::new();
+                for chunk in input.chunks(317) {
+                    resampler.process(chunk, |s| chunked.push(s));
+                }
+                assert_eq!(chunked, resample(from, to, &input), "{from}Hz to {to}Hz");
+            }
+        }
+    }
+
+    /// A tone keeps its pitch: as many zero crossings per second at either
+    /// rate.
+    #[test]
+    fn keeps_the_pitch() {
+        let crossings = |pcm: &[i16]| pcm.windows(2).filter(|w| (w[0] < 0) != (w[1] < 0)).count();
+        for rate in RATES {
+            for (from, to) in [(16000, rate), (rate, 16000)] {
+                let output = resample(from, to, &tone(1000.0, from, from as usize * 2, 16384.0));
+                // skip the filter's start up
+                let second = &output[to as usize / 2..to as usize * 3 / 2];
+                let count = crossings(second);
+                assert!(
+                    (1999..=2001).contains(&count),
+                    "{from}Hz to {to}Hz: {count}"
+                );
+                let level = power_db(second) - power_db(&tone(1000.0, to, to as usize, 16384.0));
+                assert!(level.abs() < 0.1, "{from}Hz to {to}Hz: {level:.2}dB");
+            }
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Signals for the DSP tests.
+//!
+//! `fixtures/speech.wav` is a recorded voice prompt, the firmware's
+//! `assets/wificonfig.p3` decoded at 16kHz.
+
+/// Uniform white noise peaking at `amplitude`, reproducible from `seed`.
+pub fn noise(seed: u32, len: usize, amplitude: f32) -> Vec<i16> {
+    let mut state = seed.max(1);
+    (0..len)
+        .map(|_| {
+            // xorshift32
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let uniform = state as f32 / u32::MAX as f32 * 2.0 - 1.0;
+            (uniform * amplitude) as i16
+        })
+        .collect()
+}
+
+/// A sine wave of `amplitude`, starting at phase zero.
+pub fn tone(freq: f32, sample_rate: u32, len: usize, amplitude: f32) -> Vec<i16> {
+    (0..len)
+        .map(|i| {
+            let phase = 2.0 * core::f32::consts::PI * freq * i as f32 / sample_rate as f32;
+            (libm::sinf(phase) * amplitude) as i16
+        })
+        .collect()
+}
+
+/// Mean power relative to a full scale square wave, in dB.
+pub fn power_db(pcm: &[i16]) -> f32 {
+    let power = pcm.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / pcm.len() as f64;
+    10.0 * libm::log10f((power / (32768.0f64 * 32768.0)) as f32 + 1e-20)
+}
+
+/// Mono 16 bit samples of `fixtures/<name>.wav`.
+pub fn wav(name: &str) -> Vec<i16> {
+    let path = format!("{}/fixtures/{name}.wav", env!("CARGO_MANIFEST_DIR"));
+    let mut reader = hound::WavReader::open(path).unwrap();
+    assert_eq!(reader.spec().channels, 1);
+    reader.samples().map(Result::unwrap).collect()
+}
+
+/// Low passed noise, to stand in for rumble that isn't rejected as hiss.
+pub fn rumble(seed: u32, len: usize, amplitude: f32) -> Vec<i16> {
+    let mut smooth = 0.0;
+    noise(seed, len, amplitude)
+        .into_iter()
+        .map(|s| {
+            smooth += 0.1 * (s as f32 - smooth);
+            smooth as i16
+        })
+        .collect()
+}
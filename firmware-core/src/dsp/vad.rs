@@ -0,0 +1,221 @@
+//! Voice activity detection.
+//!
+//! Frames are classified by their energy above a tracked noise floor, with
+//! the zero-crossing rate rejecting hiss and fan noise that is loud but not
+//! voiced. Onset and hangover times debounce the decision into
+//! [`VadEvent`]s.
+
+const SCALE: f32 = 32768.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VadEvent {
+    SpeechStart,
+    SpeechEnd,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct VadConfig {
+    /// How far above the noise floor a frame must be to count as speech.
+    pub threshold_db: f32,
+    /// Frames quieter than this are never speech, whatever the noise floor.
+    pub min_level_dbfs: f32,
+    /// Frames crossing zero more often than this fraction of samples are
+    /// treated as noise.
+    pub max_zero_crossing: f32,
+    /// Speech must last this long before [`VadEvent::SpeechStart`].
+    pub onset_ms: u32,
+    /// Silence must last this long before [`VadEvent::SpeechEnd`].
+    pub hangover_ms: u32,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        Self {
+            threshold_db: 9.0,
+            min_level_dbfs: -55.0,
+            max_zero_crossing: 0.35,
+            onset_ms: 100,
+            hangover_ms: 800,
+        }
+    }
+}
+
+pub struct VoiceActivityDetector {
+    config: VadConfig,
+    sample_rate: u32,
+    noise_db: f32,
+    speech: bool,
+    /// Time the current frame-level decision has disagreed with `speech`.
+    pending_ms: u32,
+}
+
+impl VoiceActivityDetector {
+    /// How fast the noise floor rises, in dB per second. It rises in speech
+    /// too, so steady noise taken for speech ends up as the floor, while the
+    /// pauses between words keep pulling it back down.
+    const NOISE_RISE_DB: f32 = 3.0;
+
+    pub fn new(sample_rate: u32, config: VadConfig) -> Self {
+        Self {
+            config,
+            sample_rate,
+            // taken from the first frame
+            noise_db: f32::INFINITY,
+            speech: false,
+            pending_ms: 0,
+        }
+    }
+
+    pub fn is_speech(&self) -> bool {
+        self.speech
+    }
+
+    /// Current noise floor estimate in dBFS.
+    pub fn noise_floor(&self) -> f32 {
+        self.noise_db
+    }
+
+    /// Classify the next frame, returning an event when the speech state flips.
+    pub fn process(&mut self, frame: &[i16]) -> Option<VadEvent> {
+        if frame.is_empty() {
+            return None;
+        }
+        let frame_ms = frame.len() as u32 * 1000 / self.sample_rate;
+        let level = level_dbfs(frame);
+        let voiced = zero_crossing_rate(frame) <= self.config.max_zero_crossing;
+        let active = voiced
+            && level >= self.config.min_level_dbfs
+            && level >= self.noise_db + self.config.threshold_db;
+
+        if level < self.noise_db {
+            self.noise_db = level.max(self.config.min_level_dbfs - self.config.threshold_db);
+        } else {
+            self.noise_db += Self::NOISE_RISE_DB * frame_ms as f32 / 1000.0;
+        }
+
+        if active == self.speech {
+            self.pending_ms = 0;
+            return None;
+        }
+        self.pending_ms += frame_ms;
+        let needed = if self.speech {
+            self.config.hangover_ms
+        } else {
+            self.config.onset_ms
+        };
+        if self.pending_ms < needed {
+            return None;
+        }
+
+        self.pending_ms = 0;
+        self.speech = active;
+        if active {
+            Some(VadEvent::SpeechStart)
+        } else {
+            Some(VadEvent::SpeechEnd)
+        }
+    }
+}
+
+/// RMS level of a frame relative to full scale.
+pub fn level_dbfs(frame: &[i16]) -> f32 {
+    let power = frame
+        .iter()
+        .map(|&s| (s as f32 / SCALE) * (s as f32 / SCALE))
+        .sum::<f32>()
+        / frame.len().max(1) as f32;
+    10.0 * libm::log10f(power.max(1e-10))
+}
+
+/// Fraction of adjacent sample pairs that change sign.
+pub fn zero_crossing_rate(frame: &[i16]) -> f32 {
+    let crossings = frame
+        .windows(2)
+        .filter(|w| (w[0] >= 0) != (w[1] >= 0))
+        .count();
+    crossings as f32 / frame.len().saturating_sub(1).max(1) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::testing::{noise, power_db, rumble, wav};
+
+    const RATE: u32 = 16000;
+    const FRAME: usize = 960;
+
+    /// Events with the time in seconds of the frame that ended in them.
+    fn events(pcm: &[i16], config: VadConfig) -> Vec<(VadEvent, f32)> {
+        let mut vad = VoiceActivityDetector::new(RATE, config);
+        pcm.chunks_exact(FRAME)
+            .enumerate()
+            .filter_map(|(i, frame)| {
+                let end = ((i + 1) * FRAME) as f32 / RATE as f32;
+                vad.process(frame).map(|event| (event, end))
+            })
+            .collect()
+    }
+
+    /// The speech fixture between two seconds of `background` on either
+    /// side, mixed over it.
+    fn in_background(background: Vec<i16>) -> Vec<i16> {
+        let mut pcm = background;
+        let start = 2 * RATE as usize;
+        for (p, s) in pcm[start..].iter_mut().zip(wav("speech")) {
+            *p = p.saturating_add(s);
+        }
+        pcm
+    }
+
+    /// Checks the fixture is heard from its first word to the end of its
+    /// last plus the hangover.
+    fn assert_utterance(events: &[(VadEvent, f32)]) {
+        let [(VadEvent::SpeechStart, start), (VadEvent::SpeechEnd, end)] = events else {
+            panic!("expected a single utterance, got {events:?}");
+        };
+        // speech runs from 2.12s to 3.4s
+        assert!((2.2..2.45).contains(start), "started at {start}s");
+        assert!((4.1..4.35).contains(end), "ended at {end}s");
+    }
+
+    #[test]
+    fn speech_over_rumble() {
+        let background = rumble(1, 6 * RATE as usize, 3000.0);
+        assert!((-40.0..-36.0).contains(&power_db(&background)));
+        assert_utterance(&events(&in_background(background), VadConfig::default()));
+    }
+
+    #[test]
+    fn speech_over_hiss() {
+        let background = noise(1, 6 * RATE as usize, 1000.0);
+        assert!((-37.0..-33.0).contains(&power_db(&background)));
+        assert_utterance(&events(&in_background(background), VadConfig::default()));
+    }
+
+    #[test]
+    fn hiss_is_not_speech() {
+        let hiss = noise(2, 6 * RATE as usize, 5000.0);
+        assert_eq!(events(&hiss, VadConfig::default()), []);
+    }
+
+    #[test]
+    fn too_quiet() {
+        let speech: Vec<_> = in_background(vec![0; 6 * RATE as usize])
+            .iter()
+            .map(|s| s / 300)
+            .collect();
+        assert_eq!(events(&speech, VadConfig::default()), []);
+    }
+
+    #[test]
+    fn noise_comes_on() {
+        // a fan starts after two seconds of quiet
+        let mut pcm = vec![0; 2 * RATE as usize];
+        pcm.extend(rumble(1, 10 * RATE as usize, 3000.0));
+        let events = events(&pcm, VadConfig::default());
+        let [(VadEvent::SpeechStart, _), (VadEvent::SpeechEnd, end)] = events[..] else {
+            panic!("expected the noise to be let go, got {events:?}");
+        };
+        assert!(end < 10.0, "noise taken for speech until {end}s");
+    }
+}
@@ -0,0 +1,280 @@
+//! Wake word detection.
+
+extern crate alloc;
+use alloc::{collections::VecDeque, vec, vec::Vec};
+
+use super::mfcc::{Features, Mfcc};
+
+/// One row of the DTW matrix: the best path ending at each input frame.
+type Row = Vec<Path>;
+
+#[derive(Clone, Copy)]
+struct Path {
+    cost: f32,
+    steps: u32,
+    /// Whether the last step advanced only one of template and input.
+    skewed: bool,
+}
+
+impl Path {
+    const START: Self = Self {
+        cost: 0.0,
+        steps: 0,
+        skewed: false,
+    };
+    const NONE: Self = Self {
+        cost: f32::INFINITY,
+        steps: 0,
+        skewed: false,
+    };
+}
+
+/// Listens to the mic for a phrase that should start a conversation.
+pub trait WakeWordDetector {
+    /// Feed the next mono PCM frame, returning the wake word once heard.
+    fn process(&mut self, pcm: &[i16]) -> Option<&'static str>;
+
+    /// Forget any audio heard so far.
+    fn reset(&mut self) {}
+}
+
+/// A recorded utterance of a wake word.
+pub struct Template {
+    pub word: &'static str,
+    frames: Vec<Features>,
+}
+
+impl Template {
+    pub fn new(word: &'static str, sample_rate: u32, pcm: &[i16]) -> Self {
+        let mut frames = Vec::new();
+        Mfcc::new(sample_rate).push(pcm, |f| frames.push(*f));
+        Self { word, frames }
+    }
+}
+
+/// Reference detector matching MFCC frames against recorded templates with
+/// dynamic time warping.
+pub struct TemplateDetector {
+    mfcc: Mfcc,
+    templates: Vec<Template>,
+    history: VecDeque<Features>,
+    max_history: usize,
+    /// DTW rows, kept around to avoid allocating on every check.
+    scratch: (Row, Row),
+    /// Average per-frame distance below which a template matches.
+    threshold: f32,
+    since_check: usize,
+    /// Frames left before another detection is allowed.
+    cooldown: usize,
+}
+
+impl TemplateDetector {
+    /// Frames between two matching attempts.
+    const CHECK_EVERY: usize = 10;
+    /// Frames after a detection that are left out of matching.
+    const COOLDOWN: usize = 100;
+    /// Minimum log energy (`c0`) of the loudest frame in the window, skips
+    /// matching silence and steady background noise.
+    const MIN_ENERGY: f32 = -130.0;
+
+    pub fn new(sample_rate: u32, templates: Vec<Template>, threshold: f32) -> Self {
+        let longest = templates.iter().map(|t| t.frames.len()).max().unwrap_or(0);
+        let max_history = longest * 3 / 2;
+        Self {
+            mfcc: Mfcc::new(sample_rate),
+            templates,
+            history: VecDeque::with_capacity(max_history),
+            max_history,
+            scratch: (vec![Path::NONE; max_history], vec![Path::NONE; max_history]),
+            threshold,
+            since_check: 0,
+            cooldown: 0,
+        }
+    }
+
+    fn detect(&mut self) -> Option<&'static str> {
+        let history = self.history.make_contiguous();
+        let energy = history.iter().map(|f| f[0]).fold(f32::MIN, f32::max);
+        if energy < Self::MIN_ENERGY {
+            return None;
+        }
+        let scratch = &mut self.scratch;
+        self.templates
+            .iter()
+            .map(|t| (t.word, dtw(&t.frames, history, scratch)))
+            .filter(|&(_, distance)| distance < self.threshold)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(word, _)| word)
+    }
+}
+
+impl WakeWordDetector for TemplateDetector {
+    fn process(&mut self, pcm: &[i16]) -> Option<&'static str> {
+        self.mfcc.push(pcm, |f| {
+            // what follows the wake word isn't another one
+            if self.cooldown > 0 {
+                self.cooldown -= 1;
+                return;
+            }
+            if self.history.len() == self.max_history {
+                self.history.pop_front();
+            }
+            self.history.push_back(*f);
+            self.since_check += 1;
+        });
+
+        if self.since_check < Self::CHECK_EVERY {
+            return None;
+        }
+        self.since_check = 0;
+
+        let word = self.detect()?;
+        self.history.clear();
+        self.cooldown = Self::COOLDOWN;
+        Some(word)
+    }
+
+    fn reset(&mut self) {
+        self.mfcc.reset();
+        self.history.clear();
+        self.since_check = 0;
+        self.cooldown = 0;
+    }
+}
+
+/// Subsequence DTW: the best alignment of the whole `template` against any
+/// span of `input`, as average distance per step. Steps advancing only one
+/// side can't follow each other, which keeps the span between half and twice
+/// the template's length, or a few frames could stand in for all of it. `c0`
+/// is left out so that loudness doesn't matter.
+fn dtw(template: &[Features], input: &[Features], (prev, cur): &mut (Row, Row)) -> f32 {
+    if template.is_empty() || input.is_empty() {
+        return f32::MAX;
+    }
+    let distance = |a: &Features, b: &Features| {
+        a[1..]
+            .iter()
+            .zip(&b[1..])
+            .map(|(x, y)| (x - y) * (x - y))
+            .sum::<f32>()
+    };
+    let step = |path: Path, skewed: bool| {
+        if skewed && path.skewed {
+            Path::NONE
+        } else {
+            Path { skewed, ..path }
+        }
+    };
+
+    prev.resize(input.len(), Path::NONE);
+    cur.resize(input.len(), Path::NONE);
+    for (i, t) in template.iter().enumerate() {
+        for (j, x) in input.iter().enumerate() {
+            let best = match (i, j) {
+                (0, _) => Path::START,
+                (_, 0) => step(prev[0], true),
+                _ => [
+                    step(prev[j - 1], false),
+                    step(prev[j], true),
+                    step(cur[j - 1], true),
+                ]
+                .into_iter()
+                .min_by(|a, b| a.cost.total_cmp(&b.cost))
+                .unwrap(),
+            };
+            cur[j] = Path {
+                cost: best.cost + libm::sqrtf(distance(t, x)),
+                steps: best.steps + 1,
+                ..best
+            };
+        }
+        core::mem::swap(prev, cur);
+    }
+    prev.iter()
+        .map(|path| path.cost / path.steps as f32)
+        .min_by(f32::total_cmp)
+        .unwrap_or(f32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::testing::{noise, wav};
+
+    const RATE: u32 = 16000;
+    const FRAME: usize = 320;
+    const THRESHOLD: f32 = 30.0;
+
+    /// The first two words of the fixture, from 0.1s to 0.8s.
+    fn detector() -> TemplateDetector {
+        let template = Template::new("word", RATE, &wav("speech")[1600..12800]);
+        TemplateDetector::new(RATE, vec![template], THRESHOLD)
+    }
+
+    /// `speech` at half volume over a second of noise on either side.
+    fn in_noise(speech: &[i16]) -> Vec<i16> {
+        let mut pcm = noise(7, speech.len() + 2 * RATE as usize, 300.0);
+        for (p, s) in pcm[RATE as usize..].iter_mut().zip(speech) {
+            *p = p.saturating_add(s / 2);
+        }
+        pcm
+    }
+
+    /// Detections with the time in seconds of the frame that ended in them.
+    fn detections(detector: &mut TemplateDetector, pcm: &[i16]) -> Vec<(&'static str, f32)> {
+        pcm.chunks_exact(FRAME)
+            .enumerate()
+            .filter_map(|(i, frame)| {
+                let end = ((i + 1) * FRAME) as f32 / RATE as f32;
+                detector.process(frame).map(|word| (word, end))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn hears_the_word() {
+        let pcm = in_noise(&wav("speech"));
+        let heard = detections(&mut detector(), &pcm);
+        let [("word", at)] = heard[..] else {
+            panic!("expected a single detection, got {heard:?}");
+        };
+        // the words end 1.8s in
+        assert!((1.6..2.0).contains(&at), "heard at {at}s");
+    }
+
+    #[test]
+    fn other_words() {
+        let pcm = in_noise(&wav("speech")[12800..]);
+        assert_eq!(detections(&mut detector(), &pcm), []);
+    }
+
+    #[test]
+    fn noise_only() {
+        for amplitude in [30.0, 300.0, 3000.0, 30000.0] {
+            let pcm = noise(11, 3 * RATE as usize, amplitude);
+            assert_eq!(detections(&mut detector(), &pcm), [], "at {amplitude}");
+        }
+    }
+
+    #[test]
+    fn reset_ends_cooldown() {
+        let speech = &wav("speech")[..12800];
+        let twice = [speech, speech].concat();
+        assert_eq!(detections(&mut detector(), &twice).len(), 1);
+
+        let mut detector = detector();
+        assert_eq!(detections(&mut detector, speech).len(), 1);
+        detector.reset();
+        assert_eq!(detections(&mut detector, speech).len(), 1);
+    }
+
+    #[test]
+    fn reset_forgets_audio() {
+        let speech = &wav("speech")[..12800];
+        let (head, tail) = speech.split_at(6400 + FRAME / 2);
+        let mut detector = detector();
+        detector.process(head);
+        detector.reset();
+        assert_eq!(detections(&mut detector, tail), []);
+    }
+}
@@ -0,0 +1,38 @@
+//! The parts of the firmware that don't touch the hardware: sample formats,
+//! signal processing, the audio containers, packet buffers and SD card
+//! storage.
+//!
+//! Unlike the firmware, this builds for the host, so `cargo test` runs here.
+
+#![cfg_attr(not(test), no_std)]
+
+use core::future::Future;
+
+use dsp::vad::VadEvent;
+use pool::Frame;
+
+pub mod audio;
+pub mod dsp;
+pub mod ogg;
+pub mod p3;
+pub mod pool;
+#[cfg(feature = "sdcard")]
+pub mod sdcard;
+
+/// What the mic produced next.
+#[derive(Debug)]
+pub enum Recording {
+    /// An encoded Opus frame.
+    Audio(Frame),
+    /// The mic's voice activity detector changed state.
+    Voice(VadEvent),
+    /// The mic's wake word detector heard its phrase.
+    WakeWord(&'static str),
+}
+
+pub trait Audio {
+    type Error;
+
+    fn play(&mut self, data: &[u8]) -> impl Future<Output = Result<(), Self::Error>>;
+    fn record(&mut self) -> impl Future<Output = Result<Recording, Self::Error>>;
+}
@@ -0,0 +1,784 @@
+//! Ogg Opus files (RFC 7845), for exchanging audio with standard tools.
+//!
+//! The container logic is free of IO: [`OpusDemuxer`] takes parsed
+//! [`Page`]s and queues the Opus packets they complete, [`OggMuxer`] packs
+//! packets into pages in a byte buffer. [`OggReader`] and [`OggWriter`]
+//! drive them over `embedded_io_async` streams. Only a single logical stream
+//! is handled; pages of other streams are ignored.
+
+extern crate alloc;
+use alloc::{collections::VecDeque, string::String, vec::Vec};
+use core::convert::Infallible;
+
+use embedded_io_async::{Read, ReadExactError, Write};
+use log::warn;
+
+const CAPTURE: &[u8; 4] = b"OggS";
+const PAGE_HEADER_LEN: usize = 27;
+const MAX_SEGMENTS: usize = 255;
+/// Page body size the muxer closes pages at, unless a packet continues.
+const PAGE_TARGET: usize = 4096;
+
+const FLAG_CONTINUED: u8 = 0x01;
+const FLAG_BOS: u8 = 0x02;
+const FLAG_EOS: u8 = 0x04;
+/// Granule position of pages on which no packet ends.
+const NO_GRANULE: u64 = u64::MAX;
+
+/// Opus granule positions count samples at 48kHz.
+const OPUS_RATE: u32 = 48000;
+
+#[derive(Debug)]
+pub enum OggError<E = Infallible> {
+    Io(E),
+    /// The stream ended in the middle of a page.
+    Truncated,
+    /// No `OggS` capture pattern where a page should start.
+    NoCapture,
+    UnsupportedVersion(u8),
+    Crc,
+    /// The stream doesn't start with valid `OpusHead` and `OpusTags`
+    /// packets.
+    NotOpus,
+    /// An audio packet isn't valid Opus.
+    Invalid(opus::Error),
+}
+
+impl<E> From<ReadExactError<E>> for OggError<E> {
+    fn from(e: ReadExactError<E>) -> Self {
+        match e {
+            ReadExactError::UnexpectedEof => Self::Truncated,
+            ReadExactError::Other(e) => Self::Io(e),
+        }
+    }
+}
+
+/// The identification header, the first packet of an Ogg Opus stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpusHead {
+    pub channels: u8,
+    /// Samples at 48kHz to drop from the start of the decoded audio, usually
+    /// the encoder lookahead.
+    pub pre_skip: u16,
+    /// Rate of the original audio, informational only.
+    pub input_sample_rate: u32,
+    /// Q8 gain in dB to apply to the decoded audio.
+    pub output_gain: i16,
+    pub mapping_family: u8,
+    /// Stream count, coupled stream count and channel mapping, present for
+    /// mapping families other than 0.
+    pub mapping: Vec<u8>,
+}
+
+impl OpusHead {
+    const MAGIC: &[u8; 8] = b"OpusHead";
+
+    /// Header for mono or stereo audio with no channel mapping.
+    pub fn new(channels: u8, input_sample_rate: u32, pre_skip: u16) -> Self {
+        Self {
+            channels,
+            pre_skip,
+            input_sample_rate,
+            output_gain: 0,
+            mapping_family: 0,
+            mapping: Vec::new(),
+        }
+    }
+
+    pub fn parse(packet: &[u8]) -> Option<Self> {
+        let rest = packet.strip_prefix(Self::MAGIC)?;
+        let (fixed, mapping) = rest.split_first_chunk::<11>()?;
+        // only the major version in the upper nibble is incompatible
+        if fixed[0] >> 4 != 0 || fixed[1] == 0 {
+            return None;
+        }
+        let head = Self {
+            channels: fixed[1],
+            pre_skip: u16::from_le_bytes([fixed[2], fixed[3]]),
+            input_sample_rate: u32::from_le_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]),
+            output_gain: i16::from_le_bytes([fixed[8], fixed[9]]),
+            mapping_family: fixed[10],
+            mapping: match fixed[10] {
+                0 => Vec::new(),
+                _ => mapping.get(..2 + fixed[1] as usize)?.to_vec(),
+            },
+        };
+        Some(head)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(19 + self.mapping.len());
+        out.extend_from_slice(Self::MAGIC);
+        out.extend_from_slice(&[1, self.channels]);
+        out.extend_from_slice(&self.pre_skip.to_le_bytes());
+        out.extend_from_slice(&self.input_sample_rate.to_le_bytes());
+        out.extend_from_slice(&self.output_gain.to_le_bytes());
+        out.push(self.mapping_family);
+        out.extend_from_slice(&self.mapping);
+        out
+    }
+}
+
+/// The comment header, the second packet of an Ogg Opus stream.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpusTags {
+    pub vendor: String,
+    /// `NAME=value` user comments.
+    pub comments: Vec<String>,
+}
+
+impl OpusTags {
+    const MAGIC: &[u8; 8] = b"OpusTags";
+
+    pub fn parse(packet: &[u8]) -> Option<Self> {
+        fn string<'a>(data: &mut &'a [u8]) -> Option<&'a str> {
+            let (len, rest) = data.split_first_chunk::<4>()?;
+            let len = u32::from_le_bytes(*len) as usize;
+            let s = rest.get(..len)?;
+            *data = &rest[len..];
+            core::str::from_utf8(s).ok()
+        }
+
+        let mut data = packet.strip_prefix(Self::MAGIC)?;
+        let vendor = string(&mut data)?.into();
+        let (count, rest) = data.split_first_chunk::<4>()?;
+        data = rest;
+        let comments = (0..u32::from_le_bytes(*count))
+            .map(|_| string(&mut data).map(Into::into))
+            .collect::<Option<_>>()?;
+        Some(Self { vendor, comments })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        fn string(out: &mut Vec<u8>, s: &str) {
+            out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+            out.extend_from_slice(s.as_bytes());
+        }
+
+        let mut out = Vec::from(Self::MAGIC.as_slice());
+        string(&mut out, &self.vendor);
+        out.extend_from_slice(&(self.comments.len() as u32).to_le_bytes());
+        for comment in &self.comments {
+            string(&mut out, comment);
+        }
+        out
+    }
+}
+
+/// One parsed Ogg page.
+#[derive(Debug, Clone, Copy)]
+pub struct Page<'a> {
+    pub flags: u8,
+    /// Samples at 48kHz decoded up to the last packet ending on this page,
+    /// pre-skip included.
+    pub granule: u64,
+    pub serial: u32,
+    pub sequence: u32,
+    /// Lacing values, the packet segment lengths.
+    pub lacing: &'a [u8],
+    pub body: &'a [u8],
+}
+
+impl<'a> Page<'a> {
+    /// Parse the page at the start of `data`, returning it with its length.
+    pub fn parse<E>(data: &'a [u8]) -> Result<(Self, usize), OggError<E>> {
+        let header = data.get(..PAGE_HEADER_LEN).ok_or(OggError::Truncated)?;
+        if !header.starts_with(CAPTURE) {
+            return Err(OggError::NoCapture);
+        }
+        if header[4] != 0 {
+            return Err(OggError::UnsupportedVersion(header[4]));
+        }
+        let segments = header[26] as usize;
+        let lacing = data
+            .get(PAGE_HEADER_LEN..PAGE_HEADER_LEN + segments)
+            .ok_or(OggError::Truncated)?;
+        let len = PAGE_HEADER_LEN + segments + body_len(lacing);
+        let page = data.get(..len).ok_or(OggError::Truncated)?;
+
+        let stored = u32::from_le_bytes(page[22..26].try_into().unwrap());
+        if crc(&page[..22], &[0; 4], &page[26..]) != stored {
+            return Err(OggError::Crc);
+        }
+        let page = Self {
+            flags: page[5],
+            granule: u64::from_le_bytes(page[6..14].try_into().unwrap()),
+            serial: u32::from_le_bytes(page[14..18].try_into().unwrap()),
+            sequence: u32::from_le_bytes(page[18..22].try_into().unwrap()),
+            lacing,
+            body: &page[PAGE_HEADER_LEN + segments..],
+        };
+        Ok((page, len))
+    }
+}
+
+fn body_len(lacing: &[u8]) -> usize {
+    lacing.iter().map(|&len| len as usize).sum()
+}
+
+/// An audio packet with the samples to drop from its decoded output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpusPacket {
+    pub data: Vec<u8>,
+    /// Samples at 48kHz to drop from the start, for the pre-skip.
+    pub trim_start: u32,
+    /// Samples at 48kHz to drop from the end, for the last packet of a
+    /// stream not ending on a frame boundary.
+    pub trim_end: u32,
+}
+
+enum Stage {
+    Head,
+    Tags,
+    Audio,
+}
+
+/// Turns pages into Opus packets.
+pub struct OpusDemuxer {
+    stage: Stage,
+    serial: Option<u32>,
+    next_sequence: u32,
+    head: Option<OpusHead>,
+    tags: Option<OpusTags>,
+    /// Packet continued on the next page.
+    partial: Vec<u8>,
+    packets: VecDeque<OpusPacket>,
+    /// Samples at 48kHz in the packets so far.
+    decoded: u64,
+    pre_skip: u32,
+    ended: bool,
+}
+
+impl OpusDemuxer {
+    pub fn new() -> Self {
+        Self {
+            stage: Stage::Head,
+            serial: None,
+            next_sequence: 0,
+            head: None,
+            tags: None,
+            partial: Vec::new(),
+            packets: VecDeque::new(),
+            decoded: 0,
+            pre_skip: 0,
+            ended: false,
+        }
+    }
+
+    pub fn head(&self) -> Option<&OpusHead> {
+        self.head.as_ref()
+    }
+
+    pub fn tags(&self) -> Option<&OpusTags> {
+        self.tags.as_ref()
+    }
+
+    /// Whether the last page of the stream was seen.
+    pub fn ended(&self) -> bool {
+        self.ended
+    }
+
+    /// Next complete audio packet.
+    pub fn pop(&mut self) -> Option<OpusPacket> {
+        self.packets.pop_front()
+    }
+
+    pub fn push_page<E>(&mut self, page: &Page) -> Result<(), OggError<E>> {
+        match self.serial {
+            None if page.flags & FLAG_BOS != 0 => {
+                self.serial = Some(page.serial);
+                self.next_sequence = page.sequence;
+            }
+            None => return Err(OggError::NotOpus),
+            Some(serial) if serial != page.serial || self.ended => return Ok(()),
+            Some(_) => (),
+        }
+
+        let gap = page.sequence != self.next_sequence;
+        if gap {
+            warn!(
+                "Ogg page {} missing, dropping {} bytes",
+                self.next_sequence,
+                self.partial.len()
+            );
+        }
+        self.next_sequence = page.sequence.wrapping_add(1);
+        // only the next page can finish a packet, and only as a continuation
+        let continued = page.flags & FLAG_CONTINUED != 0;
+        if gap || !continued {
+            self.partial.clear();
+        }
+        // a continued packet whose start was lost can't be used either, but
+        // the packets after it on the page can
+        let mut lost = continued && (gap || self.partial.is_empty());
+
+        let queued = self.packets.len();
+        let mut offset = 0;
+        for &len in page.lacing {
+            let segment = &page.body[offset..offset + len as usize];
+            offset += len as usize;
+            if !lost {
+                self.partial.extend_from_slice(segment);
+            }
+            if len < 255 {
+                if !lost {
+                    let packet = core::mem::take(&mut self.partial);
+                    self.packet(packet)?;
+                }
+                lost = false;
+            }
+        }
+
+        if page.flags & FLAG_EOS != 0 {
+            self.ended = true;
+            // the final granule position cuts the last packet short
+            let excess = self.decoded.saturating_sub(page.granule);
+            if let Some(last) = self.packets.range_mut(queued..).last() {
+                last.trim_end = excess.min(u32::MAX as u64) as u32;
+            }
+        }
+        Ok(())
+    }
+
+    fn packet<E>(&mut self, data: Vec<u8>) -> Result<(), OggError<E>> {
+        match self.stage {
+            Stage::Head => {
+                let head = OpusHead::parse(&data).ok_or(OggError::NotOpus)?;
+                self.pre_skip = head.pre_skip as u32;
+                self.head = Some(head);
+                self.stage = Stage::Tags;
+            }
+            Stage::Tags => {
+                self.tags = Some(OpusTags::parse(&data).ok_or(OggError::NotOpus)?);
+                self.stage = Stage::Audio;
+            }
+            Stage::Audio => {
+                let samples = if data.is_empty() {
+                    0
+                } else {
+                    opus::packet::get_nb_samples(&data, OPUS_RATE).map_err(OggError::Invalid)?
+                        as u32
+                };
+                let trim_start = self.pre_skip.min(samples);
+                self.pre_skip -= trim_start;
+                self.decoded += samples as u64;
+                self.packets.push_back(OpusPacket {
+                    data,
+                    trim_start,
+                    trim_end: 0,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for OpusDemuxer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads Opus packets from an Ogg stream.
+pub struct OggReader<R> {
+    reader: R,
+    page: Vec<u8>,
+    demuxer: OpusDemuxer,
+}
+
+impl<R: Read> OggReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            page: Vec::new(),
+            demuxer: OpusDemuxer::new(),
+        }
+    }
+
+    /// Identification header, known once the first packet was read.
+    pub fn head(&self) -> Option<&OpusHead> {
+        self.demuxer.head()
+    }
+
+    pub fn tags(&self) -> Option<&OpusTags> {
+        self.demuxer.tags()
+    }
+
+    /// Next audio packet, `None` at the end of the stream.
+    pub async fn next(&mut self) -> Result<Option<OpusPacket>, OggError<R::Error>> {
+        loop {
+            if let Some(packet) = self.demuxer.pop() {
+                return Ok(Some(packet));
+            }
+            if self.demuxer.ended() || !self.read_page().await? {
+                return match self.demuxer.tags() {
+                    Some(_) => Ok(None),
+                    None => Err(OggError::NotOpus),
+                };
+            }
+        }
+    }
+
+    /// Read and demux one page, `false` at the end of the stream.
+    async fn read_page(&mut self) -> Result<bool, OggError<R::Error>> {
+        let mut header = [0; PAGE_HEADER_LEN];
+        let read = self.reader.read(&mut header[..1]).await;
+        if read.map_err(OggError::Io)? == 0 {
+            return Ok(false);
+        }
+        self.reader.read_exact(&mut header[1..]).await?;
+
+        // the lacing values, then the body they add up to
+        self.page.clear();
+        self.page.extend_from_slice(&header);
+        self.page.resize(PAGE_HEADER_LEN + header[26] as usize, 0);
+        self.reader
+            .read_exact(&mut self.page[PAGE_HEADER_LEN..])
+            .await?;
+        let start = self.page.len();
+        self.page
+            .resize(start + body_len(&self.page[PAGE_HEADER_LEN..]), 0);
+        self.reader.read_exact(&mut self.page[start..]).await?;
+
+        let (page, _) = Page::parse(&self.page)?;
+        self.demuxer.push_page(&page)?;
+        Ok(true)
+    }
+}
+
+/// Packs Opus packets into Ogg pages, collected in [`Self::pages`].
+pub struct OggMuxer {
+    serial: u32,
+    sequence: u32,
+    /// Samples at 48kHz in the packets pushed so far.
+    granule: u64,
+    /// Flags of the page being filled.
+    flags: u8,
+    /// Whether a packet ends on the page being filled.
+    complete: bool,
+    lacing: Vec<u8>,
+    body: Vec<u8>,
+    out: Vec<u8>,
+}
+
+impl OggMuxer {
+    /// Start a stream, queueing its `OpusHead` and `OpusTags` pages.
+    pub fn new(serial: u32, head: &OpusHead, tags: &OpusTags) -> Self {
+        let mut muxer = Self {
+            serial,
+            sequence: 0,
+            granule: 0,
+            flags: FLAG_BOS,
+            complete: false,
+            lacing: Vec::new(),
+            body: Vec::new(),
+            out: Vec::new(),
+        };
+        // both headers sit on pages of their own
+        muxer.push_raw(&head.to_bytes());
+        muxer.flush_page();
+        muxer.push_raw(&tags.to_bytes());
+        muxer.flush_page();
+        muxer
+    }
+
+    pub fn push(&mut self, packet: &[u8]) -> Result<(), opus::Error> {
+        let samples = if packet.is_empty() {
+            0
+        } else {
+            opus::packet::get_nb_samples(packet, OPUS_RATE)?
+        };
+        let segments = packet.len() / 255 + 1;
+        if !self.body.is_empty()
+            && (self.body.len() + packet.len() > PAGE_TARGET
+                || self.lacing.len() + segments > MAX_SEGMENTS)
+        {
+            self.flush_page();
+        }
+        self.granule += samples as u64;
+        self.push_raw(packet);
+        Ok(())
+    }
+
+    /// Close the stream, queueing its last page.
+    pub fn finish(&mut self) {
+        self.flags |= FLAG_EOS;
+        self.flush_page();
+    }
+
+    /// Complete pages not yet consumed.
+    pub fn pages(&self) -> &[u8] {
+        &self.out
+    }
+
+    pub fn clear(&mut self) {
+        self.out.clear();
+    }
+
+    /// Add a packet to the current page, spilling it onto new pages if its
+    /// lacing values don't fit.
+    fn push_raw(&mut self, mut packet: &[u8]) {
+        loop {
+            let free = MAX_SEGMENTS - self.lacing.len();
+            let whole = packet.len() / 255;
+            if whole < free {
+                self.lacing.resize(self.lacing.len() + whole, 255);
+                self.lacing.push((packet.len() % 255) as u8);
+                self.body.extend_from_slice(packet);
+                self.complete = true;
+                return;
+            }
+            let (head, rest) = packet.split_at(free * 255);
+            self.lacing.resize(MAX_SEGMENTS, 255);
+            self.body.extend_from_slice(head);
+            self.flush_page();
+            self.flags |= FLAG_CONTINUED;
+            packet = rest;
+        }
+    }
+
+    fn flush_page(&mut self) {
+        let granule = if self.complete || self.flags & FLAG_EOS != 0 {
+            self.granule
+        } else {
+            NO_GRANULE
+        };
+        let mut header = [0; PAGE_HEADER_LEN];
+        header[..4].copy_from_slice(CAPTURE);
+        header[5] = self.flags;
+        header[6..14].copy_from_slice(&granule.to_le_bytes());
+        header[14..18].copy_from_slice(&self.serial.to_le_bytes());
+        header[18..22].copy_from_slice(&self.sequence.to_le_bytes());
+        header[26] = self.lacing.len() as u8;
+        let crc = crc(&header, &self.lacing, &self.body);
+        header[22..26].copy_from_slice(&crc.to_le_bytes());
+
+        self.out.extend_from_slice(&header);
+        self.out.extend_from_slice(&self.lacing);
+        self.out.extend_from_slice(&self.body);
+        self.lacing.clear();
+        self.body.clear();
+        self.sequence += 1;
+        self.flags = 0;
+        self.complete = false;
+    }
+}
+
+/// Writes Opus packets as an Ogg stream.
+pub struct OggWriter<W> {
+    writer: W,
+    muxer: OggMuxer,
+}
+
+impl<W: Write> OggWriter<W> {
+    pub fn new(writer: W, serial: u32, head: &OpusHead, tags: &OpusTags) -> Self {
+        Self {
+            writer,
+            muxer: OggMuxer::new(serial, head, tags),
+        }
+    }
+
+    pub async fn write(&mut self, packet: &[u8]) -> Result<(), OggError<W::Error>> {
+        self.muxer.push(packet).map_err(OggError::Invalid)?;
+        self.write_pages().await
+    }
+
+    /// Write the last page and flush, giving the writer back.
+    pub async fn finish(mut self) -> Result<W, OggError<W::Error>> {
+        self.muxer.finish();
+        self.write_pages().await?;
+        self.writer.flush().await.map_err(OggError::Io)?;
+        Ok(self.writer)
+    }
+
+    async fn write_pages(&mut self) -> Result<(), OggError<W::Error>> {
+        if self.muxer.pages().is_empty() {
+            return Ok(());
+        }
+        self.writer
+            .write_all(self.muxer.pages())
+            .await
+            .map_err(OggError::Io)?;
+        self.muxer.clear();
+        Ok(())
+    }
+}
+
+/// Ogg CRC-32: polynomial 0x04c11db7, no reflection, zero initial value, over
+/// a page split in three parts.
+fn crc(header: &[u8], lacing: &[u8], body: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut r = (i as u32) << 24;
+            let mut bit = 0;
+            while bit < 8 {
+                r = match r & 0x8000_0000 {
+                    0 => r << 1,
+                    _ => (r << 1) ^ 0x04c1_1db7,
+                };
+                bit += 1;
+            }
+            table[i] = r;
+            i += 1;
+        }
+        table
+    };
+
+    [header, lacing, body]
+        .into_iter()
+        .flatten()
+        .fold(0, |crc, &b| {
+            (crc << 8) ^ TABLE[((crc >> 24) as u8 ^ b) as usize]
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// TOC of a single 20ms CELT frame, 960 samples at 48kHz.
+    const TOC: u8 = 0xf8;
+    const SAMPLES: u32 = 960;
+
+    /// Packets of the given sizes, each filled with its index.
+    fn packets(sizes: &[usize]) -> Vec<Vec<u8>> {
+        sizes
+            .iter()
+            .enumerate()
+            .map(|(i, &len)| {
+                let mut packet = vec![i as u8; len];
+                packet[0] = TOC;
+                packet
+            })
+            .collect()
+    }
+
+    fn mux(packets: &[Vec<u8>], pre_skip: u16) -> Vec<u8> {
+        let head = OpusHead::new(1, 16000, pre_skip);
+        let tags = OpusTags {
+            vendor: "test".into(),
+            comments: vec!["TITLE=round trip".into()],
+        };
+        let mut muxer = OggMuxer::new(7, &head, &tags);
+        for packet in packets {
+            muxer.push(packet).unwrap();
+        }
+        muxer.finish();
+        muxer.pages().to_vec()
+    }
+
+    fn pages(mut stream: &[u8]) -> Vec<&[u8]> {
+        let mut pages = Vec::new();
+        while !stream.is_empty() {
+            let (_, len) = Page::parse::<Infallible>(stream).unwrap();
+            let (page, rest) = stream.split_at(len);
+            pages.push(page);
+            stream = rest;
+        }
+        pages
+    }
+
+    fn demux<'a>(pages: impl IntoIterator<Item = &'a [u8]>) -> Vec<OpusPacket> {
+        let mut demuxer = OpusDemuxer::new();
+        let mut out = Vec::new();
+        for page in pages {
+            let (page, _) = Page::parse::<Infallible>(page).unwrap();
+            demuxer.push_page::<Infallible>(&page).unwrap();
+            out.extend(core::iter::from_fn(|| demuxer.pop()));
+        }
+        assert!(demuxer.ended());
+        assert_eq!(demuxer.head().unwrap().pre_skip, 312);
+        assert_eq!(demuxer.tags().unwrap().comments, ["TITLE=round trip"]);
+        out
+    }
+
+    fn data(packets: &[OpusPacket]) -> Vec<&[u8]> {
+        packets.iter().map(|p| &p.data[..]).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        // packets ending on segment boundaries need a 0 lacing value, those
+        // of 255 * 255 bytes and more spill onto continued pages
+        let sizes = [
+            1, 254, 255, 256, 510, 765, 4000, 65025, 65026, 130050, 70000, 3,
+        ];
+        let sent = packets(&sizes);
+        let stream = mux(&sent, 312);
+        let received = demux(pages(&stream));
+        assert_eq!(data(&received), sent);
+        assert_eq!(received[0].trim_start, 312);
+        assert!(received[1..].iter().all(|p| p.trim_start == 0));
+        assert!(received.iter().all(|p| p.trim_end == 0));
+    }
+
+    #[test]
+    fn pre_skip_spans_packets() {
+        let sent = packets(&[10; 4]);
+        let head = OpusHead::new(1, 16000, 2000);
+        let mut muxer = OggMuxer::new(7, &head, &OpusTags::default());
+        for packet in &sent {
+            muxer.push(packet).unwrap();
+        }
+        muxer.finish();
+
+        let mut demuxer = OpusDemuxer::new();
+        for page in pages(muxer.pages()) {
+            let (page, _) = Page::parse::<Infallible>(page).unwrap();
+            demuxer.push_page::<Infallible>(&page).unwrap();
+        }
+        let trims = core::iter::from_fn(|| demuxer.pop())
+            .map(|p| p.trim_start)
+            .collect::<Vec<_>>();
+        assert_eq!(trims, [SAMPLES, SAMPLES, 2000 - 2 * SAMPLES, 0]);
+    }
+
+    #[test]
+    fn end_trimming() {
+        let sent = packets(&[10; 5]);
+        let stream = mux(&sent, 312);
+        let mut pages = pages(&stream)
+            .into_iter()
+            .map(<[u8]>::to_vec)
+            .collect::<Vec<_>>();
+        // the last packet only has 100 samples of audio left
+        let last = pages.last_mut().unwrap();
+        let granule = 4 * SAMPLES as u64 + 100;
+        last[6..14].copy_from_slice(&granule.to_le_bytes());
+        last[22..26].fill(0);
+        let crc = crc(&last[..PAGE_HEADER_LEN], &[], &last[PAGE_HEADER_LEN..]);
+        last[22..26].copy_from_slice(&crc.to_le_bytes());
+
+        let received = demux(pages.iter().map(Vec::as_slice));
+        assert_eq!(data(&received), sent);
+        let trims = received.iter().map(|p| p.trim_end).collect::<Vec<_>>();
+        assert_eq!(trims, [0, 0, 0, 0, SAMPLES - 100]);
+    }
+
+    #[test]
+    fn page_loss() {
+        // four packets a page, none continued
+        let sent = packets(&[1000; 12]);
+        let stream = mux(&sent, 312);
+        let mut pages = pages(&stream);
+        assert_eq!(pages.len(), 2 + 3);
+        pages.remove(3);
+        let received = demux(pages);
+        assert_eq!(data(&received), [&sent[..4], &sent[8..]].concat());
+    }
+
+    #[test]
+    fn continued_page_loss() {
+        // the second packet fills a page and ends on the next, before the
+        // third packet
+        let sent = packets(&[100, 65025 + 50, 100, 200]);
+        let stream = mux(&sent, 312);
+        let mut pages = pages(&stream);
+        assert_eq!(pages.len(), 2 + 3);
+        pages.remove(3);
+        let received = demux(pages);
+        assert_eq!(data(&received), [&sent[..1], &sent[2..]].concat());
+    }
+}
@@ -0,0 +1,469 @@
+//! The P3 clip format: Opus packets, each behind a 4 byte header of a type
+//! byte, a reserved byte and the big endian packet length.
+
+use core::convert::Infallible;
+
+use bytes::BytesMut;
+use embassy_time::Duration;
+use embedded_io_async::{Read, ReadExactError, Seek, SeekFrom, Write};
+
+pub const HEADER_LEN: usize = 4;
+/// Type byte of Opus packets, the only kind there is so far.
+pub const TYPE_OPUS: u8 = 0;
+/// Opus packet durations are counted at 48kHz, whatever the decoded rate.
+const OPUS_RATE: u32 = 48000;
+
+#[derive(Debug)]
+pub enum P3Error<E> {
+    Io(E),
+    /// The stream ended in the middle of a packet.
+    Truncated,
+    UnknownType(u8),
+    /// The packet is larger than the buffer given to
+    /// [`P3Reader::next_into`], and was skipped, or than the 65535 bytes
+    /// [`P3Writer::write`] can store, and wasn't written.
+    TooLarge(usize),
+    /// The packet isn't valid Opus.
+    Invalid(opus::Error),
+}
+
+impl<E> From<ReadExactError<E>> for P3Error<E> {
+    fn from(e: ReadExactError<E>) -> Self {
+        match e {
+            ReadExactError::UnexpectedEof => Self::Truncated,
+            ReadExactError::Other(e) => Self::Io(e),
+        }
+    }
+}
+
+pub struct P3Reader<R> {
+    reader: R,
+    /// Samples at [`OPUS_RATE`] read so far.
+    position: u64,
+}
+
+impl<R: Read> P3Reader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            position: 0,
+        }
+    }
+
+    /// Playback time of the packets read or skipped so far.
+    pub fn position(&self) -> Duration {
+        samples_to_duration(self.position)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    pub async fn next(&mut self) -> Result<Option<BytesMut>, P3Error<R::Error>> {
+        let Some(len) = self.header().await? else {
+            return Ok(None);
+        };
+        let mut buf = BytesMut::zeroed(len);
+        self.reader.read_exact(&mut buf).await?;
+        self.advance(&buf)?;
+        Ok(Some(buf))
+    }
+
+    /// Like [`Self::next`], reading the packet into `buf` instead of
+    /// allocating.
+    pub async fn next_into<'b>(
+        &mut self,
+        buf: &'b mut [u8],
+    ) -> Result<Option<&'b [u8]>, P3Error<R::Error>> {
+        let Some(len) = self.header().await? else {
+            return Ok(None);
+        };
+        if len > buf.len() {
+            self.skip_payload(len).await?;
+            return Err(P3Error::TooLarge(len));
+        }
+        let packet = &mut buf[..len];
+        self.reader.read_exact(packet).await?;
+        self.advance(packet)?;
+        Ok(Some(packet))
+    }
+
+    /// Skip the next packet without keeping it, returning its duration.
+    pub async fn skip(&mut self) -> Result<Option<Duration>, P3Error<R::Error>> {
+        let Some(len) = self.header().await? else {
+            return Ok(None);
+        };
+        let samples = self.skip_payload(len).await?;
+        Ok(Some(samples_to_duration(samples)))
+    }
+
+    /// Skip whole packets until [`Self::position`] reaches `target`,
+    /// returning `false` if the stream ends first.
+    pub async fn skip_to(&mut self, target: Duration) -> Result<bool, P3Error<R::Error>> {
+        while self.position() < target {
+            if self.skip().await?.is_none() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Length of the next packet, `None` at the end of the stream.
+    async fn header(&mut self) -> Result<Option<usize>, P3Error<R::Error>> {
+        let mut header = [0; HEADER_LEN];
+        let read = self.reader.read(&mut header[..1]).await;
+        if read.map_err(P3Error::Io)? == 0 {
+            return Ok(None);
+        }
+        self.reader.read_exact(&mut header[1..]).await?;
+        if header[0] != TYPE_OPUS {
+            return Err(P3Error::UnknownType(header[0]));
+        }
+        Ok(Some(u16::from_be_bytes([header[2], header[3]]) as usize))
+    }
+
+    /// Step over a packet of `len` bytes, still counting its duration.
+    async fn skip_payload(&mut self, len: usize) -> Result<u64, P3Error<R::Error>> {
+        // the TOC byte and frame count are all the duration depends on
+        let mut toc = [0; 2];
+        let toc = &mut toc[..len.min(2)];
+        self.reader.read_exact(toc).await?;
+
+        let mut len = len - toc.len();
+        let mut scratch = [0; 64];
+        while len > 0 {
+            let n = len.min(scratch.len());
+            self.reader.read_exact(&mut scratch[..n]).await?;
+            len -= n;
+        }
+        self.advance(toc)
+    }
+
+    fn advance(&mut self, packet: &[u8]) -> Result<u64, P3Error<R::Error>> {
+        let samples = count_samples(packet).map_err(P3Error::Invalid)?;
+        self.position += samples;
+        Ok(samples)
+    }
+}
+
+impl<R: Read + Seek> P3Reader<R> {
+    pub async fn rewind(&mut self) -> Result<(), P3Error<R::Error>> {
+        self.reader.rewind().await.map_err(P3Error::Io)?;
+        self.position = 0;
+        Ok(())
+    }
+
+    /// Move to the first packet starting at or after `target`, going back to
+    /// the start of the stream if it's behind. Returns `false` if the stream
+    /// is shorter than `target`.
+    pub async fn seek(&mut self, target: Duration) -> Result<bool, P3Error<R::Error>> {
+        if target < self.position() {
+            self.rewind().await?;
+        }
+        self.skip_to(target).await
+    }
+
+    /// Duration of the whole stream, leaving the reader where it is. Assumes
+    /// the reader was created at the start of the stream.
+    pub async fn duration(&mut self) -> Result<Duration, P3Error<R::Error>> {
+        let offset = self.reader.stream_position().await.map_err(P3Error::Io)?;
+        let position = self.position;
+        while self.skip().await?.is_some() {}
+        let duration = self.position();
+
+        self.reader
+            .seek(SeekFrom::Start(offset))
+            .await
+            .map_err(P3Error::Io)?;
+        self.position = position;
+        Ok(duration)
+    }
+}
+
+impl<'a> P3Reader<&'a [u8]> {
+    /// Like [`Self::next`], borrowing the packet instead of copying it.
+    pub fn next_slice(&mut self) -> Result<Option<&'a [u8]>, P3Error<Infallible>> {
+        if self.reader.is_empty() {
+            return Ok(None);
+        }
+
+        let Some((header, rest)) = self.reader.split_first_chunk::<HEADER_LEN>() else {
+            return Err(P3Error::Truncated);
+        };
+        if header[0] != TYPE_OPUS {
+            return Err(P3Error::UnknownType(header[0]));
+        }
+        let len = u16::from_be_bytes([header[2], header[3]]) as usize;
+        if rest.len() < len {
+            return Err(P3Error::Truncated);
+        }
+        let (packet, rest) = rest.split_at(len);
+        self.position += count_samples(packet).map_err(P3Error::Invalid)?;
+        self.reader = rest;
+        Ok(Some(packet))
+    }
+}
+
+/// Duration of an in-memory clip.
+pub fn duration(clip: &[u8]) -> Result<Duration, P3Error<Infallible>> {
+    let mut p3 = P3Reader::new(clip);
+    while p3.next_slice()?.is_some() {}
+    Ok(p3.position())
+}
+
+fn count_samples(packet: &[u8]) -> Result<u64, opus::Error> {
+    // an empty packet stands for a lost one, it carries no duration
+    if packet.is_empty() {
+        return Ok(0);
+    }
+    Ok(opus::packet::get_nb_samples(packet, OPUS_RATE)? as u64)
+}
+
+fn samples_to_duration(samples: u64) -> Duration {
+    Duration::from_micros(samples * 1_000_000 / OPUS_RATE as u64)
+}
+
+pub struct P3Writer<W> {
+    writer: W,
+}
+
+impl<W: Write> P3Writer<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Write one Opus packet behind its header.
+    pub async fn write(&mut self, packet: &[u8]) -> Result<(), P3Error<W::Error>> {
+        let len = u16::try_from(packet.len()).map_err(|_| P3Error::TooLarge(packet.len()))?;
+        let [hi, lo] = len.to_be_bytes();
+        self.writer
+            .write_all(&[TYPE_OPUS, 0, hi, lo])
+            .await
+            .map_err(P3Error::Io)?;
+        self.writer.write_all(packet).await.map_err(P3Error::Io)
+    }
+
+    pub async fn flush(&mut self) -> Result<(), W::Error> {
+        self.writer.flush().await
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+    use embedded_io_async::ErrorType;
+
+    use super::*;
+
+    /// A 20ms packet of silence.
+    const PACKET: [u8; 3] = [0xf8, 0xff, 0xfe];
+    /// TOC byte of a single 60ms SILK frame.
+    const TOC_60MS: u8 = 0x18;
+
+    /// A clip held in memory, handing out at most `chunk` bytes a read like
+    /// a socket or a file would.
+    struct Chunked<'a> {
+        clip: &'a [u8],
+        pos: usize,
+        chunk: usize,
+    }
+
+    impl<'a> Chunked<'a> {
+        fn new(clip: &'a [u8], chunk: usize) -> Self {
+            Self {
+                clip,
+                pos: 0,
+                chunk,
+            }
+        }
+    }
+
+    impl ErrorType for Chunked<'_> {
+        type Error = Infallible;
+    }
+
+    impl Read for Chunked<'_> {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+            let n = buf.len().min(self.chunk).min(self.clip.len() - self.pos);
+            buf[..n].copy_from_slice(&self.clip[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        }
+    }
+
+    impl Seek for Chunked<'_> {
+        async fn seek(&mut self, pos: SeekFrom) -> Result<u64, Infallible> {
+            self.pos = match pos {
+                SeekFrom::Start(n) => n as usize,
+                SeekFrom::End(n) => (self.clip.len() as i64 + n) as usize,
+                SeekFrom::Current(n) => (self.pos as i64 + n) as usize,
+            };
+            Ok(self.pos as u64)
+        }
+    }
+
+    fn clip(packets: &[&[u8]]) -> Vec<u8> {
+        let mut clip = vec![0; 1024];
+        let mut writer = P3Writer::new(&mut clip[..]);
+        for packet in packets {
+            block_on(writer.write(packet)).unwrap();
+        }
+        let len = 1024 - writer.into_inner().len();
+        clip.truncate(len);
+        clip
+    }
+
+    /// A 60ms packet longer than the buffer skipping reads through.
+    fn long_packet() -> Vec<u8> {
+        let mut packet = vec![0; 100];
+        packet[0] = TOC_60MS;
+        packet
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut clip = [0; 64];
+        let mut writer = P3Writer::new(&mut clip[..]);
+        block_on(writer.write(&PACKET)).unwrap();
+        block_on(writer.write(&PACKET)).unwrap();
+        let len = 64 - writer.into_inner().len();
+        assert_eq!(len, 2 * (HEADER_LEN + PACKET.len()));
+
+        let mut reader = P3Reader::new(&clip[..len]);
+        assert_eq!(reader.next_slice().unwrap(), Some(&PACKET[..]));
+        assert_eq!(reader.next_slice().unwrap(), Some(&PACKET[..]));
+        assert_eq!(reader.next_slice().unwrap(), None);
+        assert_eq!(duration(&clip[..len]).unwrap(), Duration::from_millis(40));
+    }
+
+    #[test]
+    fn too_large_to_write() {
+        let mut clip = [0; 16];
+        let mut writer = P3Writer::new(&mut clip[..]);
+        let packet = vec![0; 65536];
+        let result = block_on(writer.write(&packet));
+        assert!(matches!(result, Err(P3Error::TooLarge(65536))));
+        // nothing was written
+        assert_eq!(writer.into_inner().len(), 16);
+    }
+
+    #[test]
+    fn streams_in_chunks() {
+        let long = long_packet();
+        let clip = clip(&[&PACKET, &long, &[], &PACKET]);
+        for chunk in [1, 3, 1024] {
+            let mut reader = P3Reader::new(Chunked::new(&clip, chunk));
+            assert_eq!(block_on(reader.next()).unwrap().unwrap(), &PACKET[..]);
+            assert_eq!(block_on(reader.next()).unwrap().unwrap(), &long[..]);
+            // a lost packet, with no duration
+            assert_eq!(block_on(reader.next()).unwrap().unwrap(), &[][..]);
+            let mut buf = [0; 16];
+            assert_eq!(
+                block_on(reader.next_into(&mut buf)).unwrap(),
+                Some(&PACKET[..])
+            );
+            assert!(block_on(reader.next()).unwrap().is_none());
+            assert_eq!(reader.position(), Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn unknown_type() {
+        let mut clip = clip(&[&PACKET]);
+        clip[0] = 1;
+        let mut reader = P3Reader::new(Chunked::new(&clip, 2));
+        assert!(matches!(
+            block_on(reader.next()),
+            Err(P3Error::UnknownType(1))
+        ));
+        let mut reader = P3Reader::new(&clip[..]);
+        assert!(matches!(reader.next_slice(), Err(P3Error::UnknownType(1))));
+    }
+
+    #[test]
+    fn truncated() {
+        let clip = clip(&[&PACKET, &PACKET]);
+        // in the second packet's payload, then in its header
+        for len in [clip.len() - 1, HEADER_LEN + PACKET.len() + 2] {
+            let clip = &clip[..len];
+            let mut reader = P3Reader::new(Chunked::new(clip, 2));
+            assert!(block_on(reader.next()).unwrap().is_some());
+            assert!(matches!(block_on(reader.next()), Err(P3Error::Truncated)));
+
+            let mut reader = P3Reader::new(clip);
+            assert!(reader.next_slice().unwrap().is_some());
+            assert!(matches!(reader.next_slice(), Err(P3Error::Truncated)));
+        }
+    }
+
+    #[test]
+    fn next_into_too_small() {
+        let clip = clip(&[&long_packet(), &PACKET]);
+        let mut reader = P3Reader::new(Chunked::new(&clip, 7));
+        let mut buf = [0; 16];
+        assert!(matches!(
+            block_on(reader.next_into(&mut buf)),
+            Err(P3Error::TooLarge(100))
+        ));
+        // skipped, but still counted
+        assert_eq!(reader.position(), Duration::from_millis(60));
+        assert_eq!(
+            block_on(reader.next_into(&mut buf)).unwrap(),
+            Some(&PACKET[..])
+        );
+        assert_eq!(reader.position(), Duration::from_millis(80));
+    }
+
+    #[test]
+    fn skip_seek_and_rewind() {
+        let long = long_packet();
+        let clip = clip(&[&PACKET, &PACKET, &long, &PACKET]);
+        let mut reader = P3Reader::new(Chunked::new(&clip, 5));
+
+        // stops at the first packet starting at or after the target
+        assert!(block_on(reader.skip_to(Duration::from_millis(30))).unwrap());
+        assert_eq!(reader.position(), Duration::from_millis(40));
+        assert_eq!(block_on(reader.next()).unwrap().unwrap(), &long[..]);
+
+        // behind, so back to the start
+        assert!(block_on(reader.seek(Duration::from_millis(20))).unwrap());
+        assert_eq!(reader.position(), Duration::from_millis(20));
+        assert_eq!(block_on(reader.next()).unwrap().unwrap(), &PACKET[..]);
+
+        // past the end
+        assert!(!block_on(reader.seek(Duration::from_secs(1))).unwrap());
+        assert_eq!(reader.position(), Duration::from_millis(120));
+        assert!(!block_on(reader.skip_to(Duration::from_secs(1))).unwrap());
+
+        block_on(reader.rewind()).unwrap();
+        assert_eq!(reader.position(), Duration::from_ticks(0));
+        assert_eq!(
+            block_on(reader.skip()).unwrap(),
+            Some(Duration::from_millis(20))
+        );
+    }
+
+    #[test]
+    fn duration_of_a_known_clip() {
+        // the wifi config prompt, 31 packets of 60ms
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/wificonfig.p3");
+        let clip = std::fs::read(path).unwrap();
+        let expected = Duration::from_millis(1860);
+        assert_eq!(duration(&clip).unwrap(), expected);
+
+        let mut reader = P3Reader::new(Chunked::new(&clip, 64));
+        let first = block_on(reader.next()).unwrap().unwrap();
+        assert_eq!(block_on(reader.duration()).unwrap(), expected);
+        // left where it was
+        assert_eq!(reader.position(), Duration::from_millis(60));
+        let second = block_on(reader.next()).unwrap().unwrap();
+        assert_eq!(
+            &second[..],
+            &clip[2 * HEADER_LEN + first.len()..][..second.len()]
+        );
+    }
+}
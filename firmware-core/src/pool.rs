@@ -0,0 +1,195 @@
+//! Fixed pools of Opus packet buffers.
+//!
+//! Packets travel between the audio tasks and the robot many times a
+//! second. Taking their buffers from a static slab instead of the heap keeps
+//! the heap from fragmenting; a [`Frame`] gives its slot back to the pool
+//! when dropped.
+
+use core::{
+    cell::{Cell, UnsafeCell},
+    fmt::{self, Debug},
+    ops::{Deref, DerefMut},
+};
+
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+
+/// Largest packet a frame holds, enough for 60ms of mono Opus at the
+/// bitrates used here.
+pub const FRAME_CAPACITY: usize = 512;
+
+type Slot = UnsafeCell<[u8; FRAME_CAPACITY]>;
+
+/// `N` frame buffers, at most 32.
+pub struct FramePool<const N: usize> {
+    slots: [Slot; N],
+    /// Bit set of the slots not handed out.
+    free: Mutex<CriticalSectionRawMutex, Cell<u32>>,
+}
+
+// Slots are only reached through the `Frame` owning them.
+unsafe impl<const N: usize> Sync for FramePool<N> {}
+
+impl<const N: usize> FramePool<N> {
+    pub const fn new() -> Self {
+        assert!(N <= 32, "a frame pool holds at most 32 frames");
+        Self {
+            slots: [const { UnsafeCell::new([0; FRAME_CAPACITY]) }; N],
+            free: Mutex::new(Cell::new(match N {
+                32 => u32::MAX,
+                _ => (1 << N) - 1,
+            })),
+        }
+    }
+
+    /// Take an empty frame, `None` if all are in use.
+    pub fn alloc(&'static self) -> Option<Frame> {
+        let slot = self.free.lock(|free| {
+            let slot = free.get().trailing_zeros();
+            (slot < N as u32).then(|| {
+                free.set(free.get() & !(1 << slot));
+                slot
+            })
+        })?;
+        Some(Frame {
+            // SAFETY: the slot was just marked as used, nothing else has it
+            buf: unsafe { &mut *self.slots[slot as usize].get() },
+            len: 0,
+            slot,
+            free: &self.free,
+        })
+    }
+
+    /// Take a frame holding a copy of `data`, `None` if all are in use or
+    /// `data` doesn't fit.
+    pub fn alloc_from(&'static self, data: &[u8]) -> Option<Frame> {
+        if data.len() > FRAME_CAPACITY {
+            return None;
+        }
+        let mut frame = self.alloc()?;
+        frame.buf[..data.len()].copy_from_slice(data);
+        frame.len = data.len();
+        Some(frame)
+    }
+
+    /// Frames left to hand out.
+    pub fn available(&self) -> usize {
+        self.free.lock(|free| free.get().count_ones() as usize)
+    }
+}
+
+impl<const N: usize> Default for FramePool<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A packet buffer borrowed from a [`FramePool`].
+pub struct Frame {
+    buf: &'static mut [u8; FRAME_CAPACITY],
+    len: usize,
+    slot: u32,
+    free: &'static Mutex<CriticalSectionRawMutex, Cell<u32>>,
+}
+
+impl Frame {
+    /// The whole buffer, to be filled before [`Self::set_len`].
+    pub fn spare(&mut self) -> &mut [u8] {
+        &mut self.buf[..]
+    }
+
+    pub fn set_len(&mut self, len: usize) {
+        assert!(len <= FRAME_CAPACITY);
+        self.len = len;
+    }
+}
+
+impl Deref for Frame {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl DerefMut for Frame {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.buf[..self.len]
+    }
+}
+
+impl Drop for Frame {
+    fn drop(&mut self) {
+        self.free
+            .lock(|free| free.set(free.get() | (1 << self.slot)));
+    }
+}
+
+impl Debug for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Frame")
+            .field("slot", &self.slot)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exhaustion() {
+        static POOL: FramePool<3> = FramePool::new();
+        let frames: Vec<_> = (0..3).map(|_| POOL.alloc().unwrap()).collect();
+        assert_eq!(POOL.available(), 0);
+        assert!(POOL.alloc().is_none());
+        assert!(POOL.alloc_from(&[1, 2, 3]).is_none());
+        drop(frames);
+        assert_eq!(POOL.available(), 3);
+    }
+
+    #[test]
+    fn slot_returns_on_drop() {
+        static POOL: FramePool<2> = FramePool::new();
+        let first = POOL.alloc_from(&[1, 2, 3]).unwrap();
+        let second = POOL.alloc().unwrap();
+        assert!(POOL.alloc().is_none());
+
+        drop(first);
+        assert_eq!(POOL.available(), 1);
+        // the same slot, handed out empty
+        let mut again = POOL.alloc().unwrap();
+        assert_eq!(again.slot, 0);
+        assert!(again.is_empty());
+        again.spare()[..2].copy_from_slice(&[4, 5]);
+        again.set_len(2);
+        assert_eq!(&again[..], &[4, 5]);
+        assert!(second.is_empty());
+    }
+
+    #[test]
+    fn alloc_from_too_much() {
+        static POOL: FramePool<1> = FramePool::new();
+        assert!(POOL.alloc_from(&[0; FRAME_CAPACITY + 1]).is_none());
+        // nothing was taken
+        assert_eq!(POOL.available(), 1);
+        let frame = POOL.alloc_from(&[7; FRAME_CAPACITY]).unwrap();
+        assert_eq!(frame.len(), FRAME_CAPACITY);
+        assert!(frame.iter().all(|&b| b == 7));
+    }
+
+    #[test]
+    fn thirty_two_frames() {
+        static POOL: FramePool<32> = FramePool::new();
+        assert_eq!(POOL.available(), 32);
+        let mut frames: Vec<_> = (0..32).map(|_| POOL.alloc().unwrap()).collect();
+        assert!(POOL.alloc().is_none());
+
+        // the top bit of the mask comes back too
+        let last = frames.pop().unwrap();
+        assert_eq!(last.slot, 31);
+        drop(last);
+        assert_eq!(POOL.available(), 1);
+        assert_eq!(POOL.alloc().unwrap().slot, 31);
+    }
+}
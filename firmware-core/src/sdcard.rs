@@ -0,0 +1,595 @@
+//! Conversation recordings on an SD card.
+//!
+//! A FAT formatted card holds a `REC` directory. Every session gets the next
+//! free number `n` and two files there, `S<n>U` for the mic frames sent up
+//! and `S<n>D` for the speech coming down, as P3 or Ogg Opus. Any `.P3` or
+//! `.OGG` file in the directory can be played back.
+//!
+//! `embedded-sdmmc` is blocking, so every call holds up the executor for
+//! the few SPI transfers it takes.
+
+extern crate alloc;
+use alloc::{format, string::String, vec::Vec};
+use core::{convert::Infallible, fmt::Debug};
+
+use embedded_io_async::{ErrorKind, ErrorType, Read};
+use embedded_sdmmc::{
+    BlockDevice, Mode, RawDirectory, RawFile, RawVolume, TimeSource, Timestamp, VolumeIdx,
+    VolumeManager,
+};
+use log::{info, warn};
+
+use crate::{
+    ogg::{OggError, OggMuxer, OggReader, OpusHead, OpusTags},
+    p3::{P3Error, P3Reader, TYPE_OPUS},
+    pool::FRAME_CAPACITY,
+    Audio, Recording,
+};
+
+const DIR: &str = "REC";
+/// Packets recorded between flushes, bounding what's lost on power off.
+const FLUSH_EVERY: u32 = 50;
+
+#[derive(Debug)]
+pub enum StorageError<E: Debug, A = Infallible> {
+    Fs(embedded_sdmmc::Error<E>),
+    /// The clip name ends in neither `.P3` nor `.OGG`.
+    UnknownFormat,
+    /// A clip isn't valid P3 or Ogg Opus, or a packet can't be stored in
+    /// one.
+    Corrupt,
+    /// A packet isn't valid Opus.
+    Invalid(opus::Error),
+    Audio(A),
+}
+
+impl<E: Debug, A> From<embedded_sdmmc::Error<E>> for StorageError<E, A> {
+    fn from(e: embedded_sdmmc::Error<E>) -> Self {
+        Self::Fs(e)
+    }
+}
+
+impl<E: Debug, A> From<P3Error<FsError<E>>> for StorageError<E, A> {
+    fn from(e: P3Error<FsError<E>>) -> Self {
+        match e {
+            P3Error::Io(FsError(e)) => Self::Fs(e),
+            P3Error::Invalid(e) => Self::Invalid(e),
+            e => {
+                warn!("Bad P3 clip: {e:?}");
+                Self::Corrupt
+            }
+        }
+    }
+}
+
+impl<E: Debug, A> From<OggError<FsError<E>>> for StorageError<E, A> {
+    fn from(e: OggError<FsError<E>>) -> Self {
+        match e {
+            OggError::Io(FsError(e)) => Self::Fs(e),
+            OggError::Invalid(e) => Self::Invalid(e),
+            e => {
+                warn!("Bad Ogg clip: {e:?}");
+                Self::Corrupt
+            }
+        }
+    }
+}
+
+/// Filesystem error seen through `embedded_io_async`.
+#[derive(Debug)]
+pub struct FsError<E: Debug>(pub embedded_sdmmc::Error<E>);
+
+impl<E: Debug> embedded_io_async::Error for FsError<E> {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    P3,
+    Ogg,
+}
+
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Format::P3 => "P3",
+            Format::Ogg => "OGG",
+        }
+    }
+
+    fn of(name: &str) -> Option<Self> {
+        let (_, extension) = name.rsplit_once('.')?;
+        [Format::P3, Format::Ogg]
+            .into_iter()
+            .find(|format| extension.eq_ignore_ascii_case(format.extension()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Mic audio sent to the server.
+    Uplink,
+    /// Speech received from the server.
+    Downlink,
+}
+
+/// Timestamps for cards on a board without a real time clock: every file is
+/// dated 2025-01-01.
+pub struct FixedTime;
+
+impl TimeSource for FixedTime {
+    fn get_timestamp(&self) -> Timestamp {
+        Timestamp {
+            year_since_1970: 55,
+            zero_indexed_month: 0,
+            zero_indexed_day: 0,
+            hours: 0,
+            minutes: 0,
+            seconds: 0,
+        }
+    }
+}
+
+struct Track {
+    file: RawFile,
+    /// Page state of Ogg tracks.
+    muxer: Option<OggMuxer>,
+}
+
+/// A session being recorded, see [`Storage::start_session`].
+pub struct Session {
+    id: u16,
+    uplink: Track,
+    downlink: Track,
+    /// Packets recorded since the files were last flushed.
+    unflushed: u32,
+}
+
+impl Session {
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+}
+
+/// The recordings directory of a mounted card.
+pub struct Storage<D: BlockDevice, T: TimeSource> {
+    volumes: VolumeManager<D, T>,
+    volume: RawVolume,
+    dir: RawDirectory,
+}
+
+impl<D: BlockDevice, T: TimeSource> Storage<D, T> {
+    /// Open the first FAT volume of `device`, creating the recordings
+    /// directory if needed.
+    pub fn mount(device: D, time: T) -> Result<Self, StorageError<D::Error>> {
+        let mut volumes = VolumeManager::new(device, time);
+        let volume = volumes.open_raw_volume(VolumeIdx(0))?;
+        let root = volumes.open_root_dir(volume)?;
+        match volumes.make_dir_in_dir(root, DIR) {
+            Ok(()) | Err(embedded_sdmmc::Error::DirAlreadyExists) => (),
+            Err(e) => return Err(e.into()),
+        }
+        let dir = volumes.open_dir(root, DIR);
+        volumes.close_dir(root)?;
+        Ok(Self {
+            dir: dir?,
+            volumes,
+            volume,
+        })
+    }
+
+    /// Names of the files in the recordings directory.
+    pub fn clips(&mut self) -> Result<Vec<String>, StorageError<D::Error>> {
+        let mut clips = Vec::new();
+        self.volumes.iterate_dir(self.dir, |entry| {
+            if !entry.attributes.is_directory() {
+                clips.push(format!("{}", entry.name));
+            }
+        })?;
+        Ok(clips)
+    }
+
+    /// Create the files of a new session, numbered after the last one on the
+    /// card.
+    pub fn start_session(&mut self, format: Format) -> Result<Session, StorageError<D::Error>> {
+        let id = self
+            .clips()?
+            .iter()
+            .filter_map(|name| session_id(name))
+            .max()
+            .map_or(0, |id| id + 1);
+        info!("Recording session {id} to the SD card");
+        Ok(Session {
+            id,
+            uplink: self.create_track(id, Direction::Uplink, format)?,
+            downlink: self.create_track(id, Direction::Downlink, format)?,
+            unflushed: 0,
+        })
+    }
+
+    pub fn record(
+        &mut self,
+        session: &mut Session,
+        direction: Direction,
+        packet: &[u8],
+    ) -> Result<(), StorageError<D::Error>> {
+        let track = match direction {
+            Direction::Uplink => &mut session.uplink,
+            Direction::Downlink => &mut session.downlink,
+        };
+        match &mut track.muxer {
+            None => {
+                let len = u16::try_from(packet.len()).map_err(|_| StorageError::Corrupt)?;
+                let [hi, lo] = len.to_be_bytes();
+                self.volumes.write(track.file, &[TYPE_OPUS, 0, hi, lo])?;
+                self.volumes.write(track.file, packet)?;
+            }
+            Some(muxer) => {
+                muxer.push(packet).map_err(StorageError::Invalid)?;
+                self.volumes.write(track.file, muxer.pages())?;
+                muxer.clear();
+            }
+        }
+
+        session.unflushed += 1;
+        if session.unflushed == FLUSH_EVERY {
+            session.unflushed = 0;
+            self.volumes.flush_file(session.uplink.file)?;
+            self.volumes.flush_file(session.downlink.file)?;
+        }
+        Ok(())
+    }
+
+    /// Complete and close the files of a session.
+    pub fn finish(&mut self, session: Session) -> Result<(), StorageError<D::Error>> {
+        for mut track in [session.uplink, session.downlink] {
+            if let Some(muxer) = &mut track.muxer {
+                muxer.finish();
+                self.volumes.write(track.file, muxer.pages())?;
+            }
+            self.volumes.close_file(track.file)?;
+        }
+        Ok(())
+    }
+
+    /// Play the clip `name` from the recordings directory.
+    pub async fn play<A: Audio>(
+        &mut self,
+        name: &str,
+        audio: &mut A,
+    ) -> Result<(), StorageError<D::Error, A::Error>> {
+        let format = Format::of(name).ok_or(StorageError::UnknownFormat)?;
+        let file = self
+            .volumes
+            .open_file_in_dir(self.dir, name, Mode::ReadOnly)?;
+        let result = self.play_file(file, format, audio).await;
+        self.volumes.close_file(file)?;
+        result
+    }
+
+    /// Close the directory and volume, giving the card back.
+    pub fn unmount(mut self) -> Result<D, StorageError<D::Error>> {
+        self.volumes.close_dir(self.dir)?;
+        self.volumes.close_volume(self.volume)?;
+        let (device, _) = self.volumes.free();
+        Ok(device)
+    }
+
+    async fn play_file<A: Audio>(
+        &mut self,
+        file: RawFile,
+        format: Format,
+        audio: &mut A,
+    ) -> Result<(), StorageError<D::Error, A::Error>> {
+        let reader = FileReader {
+            volumes: &mut self.volumes,
+            file,
+        };
+        match format {
+            Format::P3 => {
+                let mut p3 = P3Reader::new(reader);
+                let mut buf = [0; FRAME_CAPACITY];
+                loop {
+                    match p3.next_into(&mut buf).await {
+                        Ok(Some(packet)) => {
+                            audio.play(packet).await.map_err(StorageError::Audio)?
+                        }
+                        Ok(None) => break,
+                        Err(P3Error::TooLarge(len)) => warn!("Skipping {len} byte packet"),
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+            }
+            Format::Ogg => {
+                let mut ogg = OggReader::new(reader);
+                while let Some(packet) = ogg.next().await? {
+                    audio
+                        .play(&packet.data)
+                        .await
+                        .map_err(StorageError::Audio)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn create_track(
+        &mut self,
+        id: u16,
+        direction: Direction,
+        format: Format,
+    ) -> Result<Track, StorageError<D::Error>> {
+        let suffix = match direction {
+            Direction::Uplink => 'U',
+            Direction::Downlink => 'D',
+        };
+        let name = format!("S{id:04}{suffix}.{}", format.extension());
+        let file = self
+            .volumes
+            .open_file_in_dir(self.dir, name.as_str(), Mode::ReadWriteCreate)?;
+        let muxer = (format == Format::Ogg).then(|| {
+            let serial = (id as u32) << 1 | (direction == Direction::Downlink) as u32;
+            let tags = OpusTags {
+                vendor: "xiaozhi".into(),
+                comments: Vec::new(),
+            };
+            OggMuxer::new(serial, &OpusHead::new(1, 16000, 0), &tags)
+        });
+        Ok(Track { file, muxer })
+    }
+}
+
+/// Number of a session file name like `S0012U.P3`.
+fn session_id(name: &str) -> Option<u16> {
+    let digits = name.strip_prefix('S')?.get(..4)?;
+    digits.parse().ok()
+}
+
+struct FileReader<'a, D: BlockDevice, T: TimeSource> {
+    volumes: &'a mut VolumeManager<D, T>,
+    file: RawFile,
+}
+
+impl<D: BlockDevice, T: TimeSource> ErrorType for FileReader<'_, D, T> {
+    type Error = FsError<D::Error>;
+}
+
+impl<D: BlockDevice, T: TimeSource> Read for FileReader<'_, D, T> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.volumes.read(self.file, buf).map_err(FsError)
+    }
+}
+
+/// An [`Audio`] that records every packet passing through it to a
+/// [`Storage`], mic frames as uplink and played packets as downlink.
+pub struct Recorder<A, D: BlockDevice, T: TimeSource> {
+    audio: A,
+    storage: Option<Storage<D, T>>,
+    session: Option<Session>,
+}
+
+impl<A: Audio, D: BlockDevice, T: TimeSource> Recorder<A, D, T> {
+    /// Start a session on `storage`, or only pass audio through if there's
+    /// no card.
+    pub fn new(audio: A, storage: Option<Storage<D, T>>, format: Format) -> Self {
+        let mut recorder = Self {
+            audio,
+            storage,
+            session: None,
+        };
+        if let Some(storage) = &mut recorder.storage {
+            match storage.start_session(format) {
+                Ok(session) => recorder.session = Some(session),
+                Err(e) => warn!("Not recording: {e:?}"),
+            }
+        }
+        recorder
+    }
+
+    pub fn storage(&mut self) -> Option<&mut Storage<D, T>> {
+        self.storage.as_mut()
+    }
+
+    /// Close the session's files, recording stops.
+    pub fn finish(&mut self) {
+        if let (Some(storage), Some(session)) = (&mut self.storage, self.session.take()) {
+            if let Err(e) = storage.finish(session) {
+                warn!("Failed to close the recording: {e:?}");
+            }
+        }
+    }
+
+    fn log(&mut self, direction: Direction, packet: &[u8]) {
+        let (Some(storage), Some(session)) = (&mut self.storage, &mut self.session) else {
+            return;
+        };
+        if let Err(e) = storage.record(session, direction, packet) {
+            warn!("Recording stopped: {e:?}");
+            self.finish();
+        }
+    }
+}
+
+impl<A: Audio, D: BlockDevice, T: TimeSource> Audio for Recorder<A, D, T> {
+    type Error = A::Error;
+
+    async fn play(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.log(Direction::Downlink, data);
+        self.audio.play(data).await
+    }
+
+    async fn record(&mut self) -> Result<Recording, Self::Error> {
+        let recording = self.audio.record().await?;
+        if let Recording::Audio(frame) = &recording {
+            self.log(Direction::Uplink, frame);
+        }
+        Ok(recording)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::RefCell;
+
+    use embassy_futures::block_on;
+    use embedded_sdmmc::{Block, BlockCount, BlockIdx};
+
+    use super::*;
+
+    const BLOCKS: u32 = 16384;
+
+    /// A card in memory, holding one FAT16 partition.
+    struct RamDisk(RefCell<Vec<Block>>);
+
+    impl RamDisk {
+        fn formatted() -> Self {
+            let mut blocks = alloc::vec![Block::new(); BLOCKS as usize];
+            let sectors = BLOCKS - 1;
+            let (reserved, fat_sectors, root_sectors) = (1, 32, 32);
+
+            // MBR with a single FAT16 partition from block 1
+            let mbr = &mut blocks[0].contents;
+            mbr[446 + 4] = 0x06;
+            mbr[446 + 8..446 + 12].copy_from_slice(&1u32.to_le_bytes());
+            mbr[446 + 12..446 + 16].copy_from_slice(&sectors.to_le_bytes());
+            mbr[510..].copy_from_slice(&[0x55, 0xaa]);
+
+            let boot = &mut blocks[1].contents;
+            boot[..3].copy_from_slice(&[0xeb, 0x3c, 0x90]);
+            boot[3..11].copy_from_slice(b"MSWIN4.1");
+            boot[11..13].copy_from_slice(&512u16.to_le_bytes());
+            boot[13] = 2; // sectors per cluster
+            boot[14..16].copy_from_slice(&(reserved as u16).to_le_bytes());
+            boot[16] = 2; // FATs
+            boot[17..19].copy_from_slice(&(root_sectors as u16 * 16).to_le_bytes());
+            boot[19..21].copy_from_slice(&(sectors as u16).to_le_bytes());
+            boot[21] = 0xf8;
+            boot[22..24].copy_from_slice(&(fat_sectors as u16).to_le_bytes());
+            boot[28..32].copy_from_slice(&1u32.to_le_bytes());
+            boot[38] = 0x29;
+            boot[43..54].copy_from_slice(b"NO NAME    ");
+            boot[54..62].copy_from_slice(b"FAT16   ");
+            boot[510..].copy_from_slice(&[0x55, 0xaa]);
+
+            for fat in 0..2 {
+                let block = &mut blocks[1 + reserved + fat * fat_sectors].contents;
+                block[..4].copy_from_slice(&[0xf8, 0xff, 0xff, 0xff]);
+            }
+            Self(RefCell::new(blocks))
+        }
+    }
+
+    impl BlockDevice for RamDisk {
+        type Error = ();
+
+        fn read(&self, blocks: &mut [Block], start: BlockIdx, _reason: &str) -> Result<(), ()> {
+            let disk = self.0.borrow();
+            for (i, block) in blocks.iter_mut().enumerate() {
+                block.contents = disk[start.0 as usize + i].contents;
+            }
+            Ok(())
+        }
+
+        fn write(&self, blocks: &[Block], start: BlockIdx) -> Result<(), ()> {
+            let mut disk = self.0.borrow_mut();
+            for (i, block) in blocks.iter().enumerate() {
+                disk[start.0 as usize + i].contents = block.contents;
+            }
+            Ok(())
+        }
+
+        fn num_blocks(&self) -> Result<BlockCount, ()> {
+            Ok(BlockCount(BLOCKS))
+        }
+    }
+
+    /// Keeps what it's asked to play.
+    #[derive(Default)]
+    struct Sink(Vec<Vec<u8>>);
+
+    impl Audio for Sink {
+        type Error = ();
+
+        async fn play(&mut self, data: &[u8]) -> Result<(), ()> {
+            self.0.push(data.to_vec());
+            Ok(())
+        }
+
+        async fn record(&mut self) -> Result<Recording, ()> {
+            Err(())
+        }
+    }
+
+    /// 20ms CELT packets with distinct payloads.
+    fn packets() -> Vec<Vec<u8>> {
+        (0..120u8).map(|i| alloc::vec![0xf8, i, i ^ 0x55]).collect()
+    }
+
+    fn roundtrip(format: Format) {
+        let mut storage = Storage::mount(RamDisk::formatted(), FixedTime).unwrap();
+        let mut session = storage.start_session(format).unwrap();
+        assert_eq!(session.id(), 0);
+        let packets = packets();
+        for packet in &packets {
+            storage
+                .record(&mut session, Direction::Uplink, packet)
+                .unwrap();
+        }
+        storage
+            .record(&mut session, Direction::Downlink, &packets[0])
+            .unwrap();
+        storage.finish(session).unwrap();
+
+        // still there after remounting
+        let disk = storage.unmount().unwrap();
+        let mut storage = Storage::mount(disk, FixedTime).unwrap();
+        let ext = format.extension();
+        let mut clips = storage.clips().unwrap();
+        clips.sort();
+        assert_eq!(clips, [format!("S0000D.{ext}"), format!("S0000U.{ext}")]);
+
+        let mut sink = Sink::default();
+        block_on(storage.play(&format!("S0000U.{ext}"), &mut sink)).unwrap();
+        assert_eq!(sink.0, packets);
+        let mut sink = Sink::default();
+        block_on(storage.play(&format!("S0000D.{ext}"), &mut sink)).unwrap();
+        assert_eq!(sink.0, packets[..1]);
+
+        assert_eq!(storage.start_session(format).unwrap().id(), 1);
+    }
+
+    #[test]
+    fn p3_roundtrip() {
+        roundtrip(Format::P3);
+    }
+
+    #[test]
+    fn ogg_roundtrip() {
+        roundtrip(Format::Ogg);
+    }
+
+    #[test]
+    fn unknown_clip() {
+        let mut storage = Storage::mount(RamDisk::formatted(), FixedTime).unwrap();
+        let mut sink = Sink::default();
+        assert!(matches!(
+            block_on(storage.play("NOTES.TXT", &mut sink)),
+            Err(StorageError::UnknownFormat)
+        ));
+        assert!(matches!(
+            block_on(storage.play("MISSING.P3", &mut sink)),
+            Err(StorageError::Fs(embedded_sdmmc::Error::NotFound))
+        ));
+    }
+
+    #[test]
+    fn session_names() {
+        assert_eq!(session_id("S0012U.P3"), Some(12));
+        assert_eq!(session_id("S0003D.OGG"), Some(3));
+        assert_eq!(session_id("NOTES.TXT"), None);
+        assert_eq!(Format::of("s0001u.ogg"), Some(Format::Ogg));
+        assert_eq!(Format::of("CHIME"), None);
+    }
+}
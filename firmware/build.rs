@@ -1,4 +1,7 @@
+use std::{env, fmt::Write, fs, path::Path};
+
 fn main() {
+    embed_assets();
     linker_be_nice();
     // make sure linkall.x is the last linker script (otherwise might cause problems with flip-link)
     println!("cargo:rustc-link-arg=-Tlinkall.x");
@@ -38,3 +41,39 @@ fn linker_be_nice() {
         std::env::current_exe().unwrap().display()
     );
 }
+
+/// Generate the registry of `assets/*.p3` clips included by `src/assets.rs`.
+fn embed_assets() {
+    let dir = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("assets");
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed={}", dir.display());
+
+    let mut clips: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "p3"))
+        .collect();
+    clips.sort();
+
+    let mut code = String::new();
+    let mut registry = String::new();
+    for path in &clips {
+        let name = path.file_stem().unwrap().to_str().unwrap();
+        let ident = name.to_uppercase().replace(['-', ' ', '.'], "_");
+        writeln!(
+            code,
+            "pub const {ident}: &[u8] = include_bytes!({:?});",
+            path.display().to_string()
+        )
+        .unwrap();
+        writeln!(registry, "    ({name:?}, {ident}),").unwrap();
+    }
+    writeln!(
+        code,
+        "pub static ASSETS: &[(&str, &[u8])] = &[\n{registry}];"
+    )
+    .unwrap();
+
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("assets.rs");
+    fs::write(out, code).unwrap();
+}
@@ -0,0 +1,74 @@
+//! Audio clips embedded from `firmware/assets`.
+//!
+//! Every `assets/<name>.p3` file is included at build time, as a `<NAME>`
+//! constant and in [`ASSETS`] under its file stem.
+
+extern crate alloc;
+use alloc::{vec, vec::Vec};
+use core::convert::Infallible;
+
+use log::debug;
+use opus::{Channels, Decoder, FrameDuration};
+
+use crate::{
+    p3::{P3Error, P3Reader},
+    Audio,
+};
+
+include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+
+/// Look up an embedded clip by name.
+pub fn get(name: &str) -> Option<&'static [u8]> {
+    ASSETS
+        .iter()
+        .find(|(asset, _)| *asset == name)
+        .map(|(_, data)| *data)
+}
+
+/// Decode a whole clip to mono PCM, e.g. to build a wake word template.
+pub fn decode(clip: &[u8], sample_rate: u32) -> Result<Vec<i16>, AssetError<opus::Error>> {
+    let mut decoder = Decoder::new(sample_rate, Channels::Mono).map_err(AssetError::Audio)?;
+    let mut frame = vec![0; FrameDuration::Ms120.samples(sample_rate)];
+    let mut pcm = Vec::new();
+    let mut p3 = P3Reader::new(clip);
+    while let Some(packet) = p3.next_slice().map_err(AssetError::Corrupt)? {
+        let len = decoder
+            .decode(packet, &mut frame, false)
+            .map_err(AssetError::Audio)?;
+        pcm.extend_from_slice(&frame[..len]);
+    }
+    Ok(pcm)
+}
+
+#[derive(Debug)]
+pub enum AssetError<E> {
+    NotFound,
+    Corrupt(P3Error<Infallible>),
+    Audio(E),
+}
+
+/// Plays embedded clips through an [`Audio`] sink.
+pub struct AssetPlayer<'a, A> {
+    audio: &'a mut A,
+}
+
+impl<'a, A: Audio> AssetPlayer<'a, A> {
+    pub fn new(audio: &'a mut A) -> Self {
+        Self { audio }
+    }
+
+    /// Queue every packet of the clip `name` for playback.
+    pub async fn play(&mut self, name: &str) -> Result<(), AssetError<A::Error>> {
+        let clip = get(name).ok_or(AssetError::NotFound)?;
+        self.play_clip(clip).await
+    }
+
+    pub async fn play_clip(&mut self, clip: &[u8]) -> Result<(), AssetError<A::Error>> {
+        let mut p3 = P3Reader::new(clip);
+        while let Some(packet) = p3.next_slice().map_err(AssetError::Corrupt)? {
+            debug!("playing {} bytes of asset audio", packet.len());
+            self.audio.play(packet).await.map_err(AssetError::Audio)?;
+        }
+        Ok(())
+    }
+}
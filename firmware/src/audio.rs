@@ -2,17 +2,32 @@ use esp_hal::{
     dma::{DmaChannelFor, DmaPriority},
     dma_circular_buffers,
     gpio::interconnect::{PeripheralInput, PeripheralOutput},
-    i2s::master::{AnyI2s, I2s, I2sRx, I2sTx, RegisterAccess},
+    i2s::master::{AnyI2s, DataFormat, I2s, I2sRx, I2sTx, RegisterAccess},
     peripheral::Peripheral,
+    peripherals::{I2S0, I2S1},
     time::Rate,
     Async,
 };
 
+use format::{BitDepth, SampleFormat};
+
+pub use firmware_core::audio::{codec, format};
+
+/// I²S data format to configure the peripheral with for `bits`.
+const fn data_format(bits: BitDepth) -> DataFormat {
+    match bits {
+        BitDepth::Bits16 => DataFormat::Data16Channel16,
+        BitDepth::Bits24 | BitDepth::Bits32 => DataFormat::Data32Channel32,
+    }
+}
+
 pub struct I2sConfig<WS, BCLK, I2S, DMA> {
     pub i2s: I2S,
     pub dma: DMA,
     pub ws: WS,
     pub bclk: BCLK,
+    pub format: SampleFormat,
+    pub sample_rate: u32,
 }
 
 impl<WS, BCLK, I2S, DMA> I2sConfig<WS, BCLK, I2S, DMA>
@@ -32,8 +47,8 @@ where
                 let i2s = I2s::new(
                     self.i2s,
                     esp_hal::i2s::master::Standard::Philips,
-                    esp_hal::i2s::master::DataFormat::Data32Channel32,
-                    Rate::from_khz(16),
+                    data_format(self.format.bits),
+                    Rate::from_hz(self.sample_rate),
                     self.dma,
                     rx_d,
                     tx_d,
@@ -64,8 +79,8 @@ where
                 let i2s = I2s::new(
                     self.i2s,
                     esp_hal::i2s::master::Standard::Philips,
-                    esp_hal::i2s::master::DataFormat::Data32Channel32,
-                    Rate::from_khz(16),
+                    data_format(self.format.bits),
+                    Rate::from_hz(self.sample_rate),
                     self.dma,
                     rx_d,
                     tx_d,
@@ -86,3 +101,79 @@ where
         }
     }
 }
+
+/// An I²S peripheral whose receiver can run off the transmitter's WS and BCLK.
+pub trait SharedClock {
+    /// Make the receiver a slave to the transmitter's clock.
+    fn share_clock();
+}
+
+macro_rules! impl_shared_clock {
+    ($($i2s:ident),*) => {$(
+        impl SharedClock for $i2s {
+            fn share_clock() {
+                let regs = $i2s::regs();
+                regs.tx_conf().modify(|_, w| w.sig_loopback().set_bit());
+                regs.rx_conf().modify(|_, w| w.rx_slave_mod().set_bit());
+                regs.rx_conf().modify(|_, w| w.rx_update().clear_bit());
+                regs.rx_conf().modify(|_, w| w.rx_update().set_bit());
+            }
+        }
+    )*};
+}
+
+impl_shared_clock!(I2S0, I2S1);
+
+impl<WS, BCLK, I2S, DMA> I2sConfig<WS, BCLK, I2S, DMA>
+where
+    I2S: Peripheral<P: RegisterAccess + SharedClock> + 'static,
+    DMA: Peripheral<P: DmaChannelFor<AnyI2s>> + 'static,
+    WS: Peripheral<P: PeripheralOutput> + 'static,
+    BCLK: Peripheral<P: PeripheralOutput> + 'static,
+{
+    /// Build both directions on a single I²S peripheral, as used by codec chips
+    /// like the ES8311 that share BCLK and WS between their ADC and DAC.
+    ///
+    /// Only the transmitter drives WS and BCLK; the receiver follows its clock.
+    pub fn build_duplex(
+        self,
+        mclk: impl Peripheral<P: PeripheralOutput> + 'static,
+        din: impl Peripheral<P: PeripheralInput> + 'static,
+        dout: impl Peripheral<P: PeripheralOutput> + 'static,
+    ) -> (
+        (&'static mut [u8], I2sRx<'static, Async>),
+        (&'static mut [u8], I2sTx<'static, Async>),
+    ) {
+        let (i2s, rx_buf, tx_buf) = {
+            let (rx_buf, rx_d, tx_buf, tx_d) = dma_circular_buffers!(4092 * 10, 4092 * 10);
+            let i2s = I2s::new(
+                self.i2s,
+                esp_hal::i2s::master::Standard::Philips,
+                data_format(self.format.bits),
+                Rate::from_hz(self.sample_rate),
+                self.dma,
+                rx_d,
+                tx_d,
+            )
+            .with_mclk(mclk)
+            .into_async();
+            (i2s, rx_buf, tx_buf)
+        };
+        <I2S::P as SharedClock>::share_clock();
+        let i2s_rx = {
+            let mut i2s = i2s.i2s_rx.with_din(din);
+            i2s.rx_channel.set_priority(DmaPriority::Priority0);
+            i2s.build()
+        };
+        let i2s_tx = {
+            let mut i2s = i2s
+                .i2s_tx
+                .with_ws(self.ws)
+                .with_bclk(self.bclk)
+                .with_dout(dout);
+            i2s.tx_channel.set_priority(DmaPriority::Priority0);
+            i2s.build()
+        };
+        ((rx_buf, i2s_rx), (tx_buf, i2s_tx))
+    }
+}
@@ -5,6 +5,9 @@
 #![feature(inherent_str_constructors)]
 #![feature(concat_bytes)]
 
+extern crate alloc;
+
+use alloc::vec;
 use embassy_executor::Spawner;
 use embassy_net::dns::DnsSocket;
 use embassy_net::tcp::client::TcpClient;
@@ -17,10 +20,15 @@ use esp_hal::rng::Trng;
 use esp_hal::timer::timg::TimerGroup;
 use esp_println::dbg;
 use esp_println::println;
+use firmware::assets;
+use firmware::audio::format::SampleFormat;
 use firmware::audio::I2sConfig;
 use firmware::codec::I2sSimplex;
 use firmware::codec::I2sSimplexConfig;
+use firmware::dsp::wake::{Template, TemplateDetector, WakeWordDetector};
+use firmware::dsp::{preprocess::PreprocessConfig, vad::VadConfig};
 use firmware::mk_buf;
+use firmware::mk_static;
 use firmware::net::Connect;
 use firmware::net::EspTlsClient;
 use firmware::net::TlsClient;
@@ -34,6 +42,24 @@ use firmware::RobotState;
 use log::info;
 
 const TCP_BUF_SIZE: usize = 4096;
+const SAMPLE_RATE: u32 = 16000;
+/// Sent to the server when `assets/wake.p3` is heard, e.g. `Some("你好小智")`.
+/// Off until the stand in checked in there is replaced by a recording of the
+/// phrase.
+const WAKE_WORD: Option<&str> = None;
+/// Average MFCC distance to the template under which the wake word matches.
+const WAKE_THRESHOLD: f32 = 30.0;
+#[cfg(not(feature = "es8311"))]
+const MIC_FORMAT: SampleFormat = SampleFormat::INMP441;
+#[cfg(not(feature = "es8311"))]
+const SPEAKER_FORMAT: SampleFormat = SampleFormat::MAX98357;
+#[cfg(feature = "es8311")]
+const MIC_FORMAT: SampleFormat = SampleFormat::ES8311;
+#[cfg(feature = "es8311")]
+const SPEAKER_FORMAT: SampleFormat = SampleFormat::ES8311;
+/// Speaker volume set on the codec, in percent.
+#[cfg(feature = "es8311")]
+const CODEC_VOLUME: u8 = 70;
 
 #[esp_hal_embassy::main]
 async fn main(s: Spawner) {
@@ -87,33 +113,115 @@ async fn main(s: Spawner) {
         .unwrap()
         .into_buffered(1024);
 
-    let codec = {
-        let (speaker_buf, speaker_tx) = I2sConfig {
+    let wake_word = WAKE_WORD.map(|word| -> &'static mut dyn WakeWordDetector {
+        // heard at the encode rate, after resampling
+        let pcm = assets::decode(assets::WAKE, SAMPLE_RATE).unwrap();
+        let template = Template::new(word, SAMPLE_RATE, &pcm);
+        mk_static!(
+            TemplateDetector,
+            TemplateDetector::new(SAMPLE_RATE, vec![template], WAKE_THRESHOLD)
+        )
+    });
+
+    // an INMP441 microphone and a MAX98357 amplifier, each on its own I²S bus
+    #[cfg(not(feature = "es8311"))]
+    let ((mic_buf, mic_rx), (speaker_buf, speaker_tx)) = {
+        let speaker = I2sConfig {
             i2s: peripherals.I2S0,
             dma: peripherals.DMA_CH0,
             bclk: peripherals.GPIO15,
             ws: peripherals.GPIO16,
+            format: SPEAKER_FORMAT,
+            sample_rate: SAMPLE_RATE,
         }
         .build_output(peripherals.GPIO7);
-        let (mic_buf, mic_rx) = I2sConfig {
+        let mic = I2sConfig {
             i2s: peripherals.I2S1,
             dma: peripherals.DMA_CH1,
             ws: peripherals.GPIO4,
             bclk: peripherals.GPIO5,
+            format: MIC_FORMAT,
+            sample_rate: SAMPLE_RATE,
         }
         .build_input(peripherals.GPIO6);
-        I2sSimplex::new(
-            &s,
-            I2sSimplexConfig {
-                mic_rx,
-                mic_buf,
-                speaker_tx,
-                speaker_buf,
-            },
+        (mic, speaker)
+    };
+
+    // an ES8311 codec, set up over I²C and streaming both ways on one I²S bus
+    #[cfg(feature = "es8311")]
+    let ((mic_buf, mic_rx), (speaker_buf, speaker_tx)) = {
+        use esp_hal::i2c::master::{Config, I2c};
+        use firmware::audio::codec::{es8311, AudioCodec, Es8311};
+
+        let i2c = I2c::new(peripherals.I2C0, Config::default())
+            .unwrap()
+            .with_sda(peripherals.GPIO1)
+            .with_scl(peripherals.GPIO2)
+            .into_async();
+        let duplex = I2sConfig {
+            i2s: peripherals.I2S0,
+            dma: peripherals.DMA_CH0,
+            bclk: peripherals.GPIO14,
+            ws: peripherals.GPIO21,
+            format: MIC_FORMAT,
+            sample_rate: SAMPLE_RATE,
+        }
+        .build_duplex(peripherals.GPIO38, peripherals.GPIO39, peripherals.GPIO40);
+        let mut es8311 = Es8311::new(i2c, es8311::ADDRESS);
+        es8311.init(SAMPLE_RATE, MIC_FORMAT.bits).await.unwrap();
+        es8311.set_volume(CODEC_VOLUME).await.unwrap();
+        duplex
+    };
+
+    let codec = I2sSimplex::new(
+        &s,
+        I2sSimplexConfig {
+            mic_rx,
+            mic_buf,
+            mic_format: MIC_FORMAT,
+            mic_rate: SAMPLE_RATE,
+            speaker_tx,
+            speaker_buf,
+            speaker_format: SPEAKER_FORMAT,
+            speaker_rate: SAMPLE_RATE,
+            encode_rate: SAMPLE_RATE,
+            decode_rate: SAMPLE_RATE,
+            echo_cancellation: true,
+            preprocess: Some(PreprocessConfig::default()),
+            vad: VadConfig::default(),
+            multi_frame: false,
+            wake_word,
+        },
+    );
+
+    #[cfg(feature = "sdcard")]
+    let codec = {
+        use embedded_hal_bus::spi::ExclusiveDevice;
+        use esp_hal::delay::Delay;
+        use esp_hal::gpio::{Level, Output, OutputConfig};
+        use esp_hal::spi::master::{Config, Spi};
+        use esp_hal::time::Rate;
+        use firmware::sdcard::{FixedTime, Format, Recorder, Storage};
+
+        // the SPI mode init clock, still far above what the audio needs
+        let spi = Spi::new(
+            peripherals.SPI2,
+            Config::default().with_frequency(Rate::from_khz(400)),
         )
+        .unwrap()
+        .with_sck(peripherals.GPIO12)
+        .with_mosi(peripherals.GPIO11)
+        .with_miso(peripherals.GPIO13);
+        let cs = Output::new(peripherals.GPIO10, Level::High, OutputConfig::default());
+        let spi = ExclusiveDevice::new(spi, cs, Delay::new()).unwrap();
+        let card = embedded_sdmmc::SdCard::new(spi, Delay::new());
+        let storage = Storage::mount(card, FixedTime)
+            .inspect_err(|e| log::warn!("No SD card: {e:?}"))
+            .ok();
+        Recorder::new(codec, storage, Format::Ogg)
     };
 
-    let mut robot = Robot::new(conn, codec);
+    let mut robot = Robot::new(conn, codec).with_wake_word(WAKE_WORD.is_some());
     robot.set_state(RobotState::Idle).await;
     robot.main_loop().await;
 }
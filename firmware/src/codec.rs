@@ -1,8 +1,12 @@
-use bytes::{Buf, BufMut, BytesMut};
+extern crate alloc;
+use alloc::{vec, vec::Vec};
+
+use bytes::BytesMut;
 use embassy_executor::Spawner;
 use embassy_sync::{
     blocking_mutex::raw::NoopRawMutex,
-    channel::{Receiver, Sender},
+    channel::{Receiver, Sender, TrySendError},
+    pipe::Pipe,
 };
 use esp_hal::{
     dma::DmaError,
@@ -10,32 +14,104 @@ use esp_hal::{
     Async,
 };
 use log::{error, info, trace, warn};
-use opus::{Decoder, Encoder};
+use opus::{CodecState, Decoder, Encoder, FrameDuration, PacketAggregator};
+
+use crate::{
+    audio::format::SampleFormat,
+    dsp::{
+        aec::EchoCanceller,
+        preprocess::{PreprocessConfig, Preprocessor},
+        resample::Resampler,
+        vad::{VadConfig, VadEvent, VoiceActivityDetector},
+        wake::WakeWordDetector,
+    },
+    mk_ch, mk_static,
+    pool::{Frame, FramePool, FRAME_CAPACITY},
+    util::HeapMonitor,
+    Audio, Recording,
+};
 
-use crate::{mk_ch, util::BytesMutExtend, Audio};
+/// Speaker samples handed to the mic for echo cancellation, sized to cover
+/// the speaker DMA buffer plus the acoustic delay.
+type Reference = Pipe<NoopRawMutex, { 16 * 1024 }>;
+const AEC_TAPS: usize = 256;
+const AEC_MAX_DELAY: usize = 4000;
+/// Duration of the Opus frames sent and expected.
+const FRAME_DURATION: FrameDuration = FrameDuration::Ms60;
+/// Static memory for the mono Opus states, kept off the heap. Enough for a
+/// float build of libopus, which needs more than a fixed-point one.
+const ENCODER_STATE: usize = 48 * 1024;
+const DECODER_STATE: usize = 24 * 1024;
+
+/// Packets in flight each way: a full channel plus one held on either end.
+static MIC_FRAMES: FramePool<12> = FramePool::new();
+static SPEAKER_FRAMES: FramePool<12> = FramePool::new();
 
 pub struct I2sSimplex {
-    mic_rx: Receiver<'static, NoopRawMutex, BytesMut, 10>,
-    speaker_tx: Sender<'static, NoopRawMutex, BytesMut, 10>,
+    mic_rx: Receiver<'static, NoopRawMutex, Recording, 10>,
+    speaker_tx: Sender<'static, NoopRawMutex, Frame, 10>,
 }
 
 pub struct I2sSimplexConfig {
     pub mic_rx: I2sRx<'static, Async>,
     pub mic_buf: &'static mut [u8],
+    pub mic_format: SampleFormat,
+    /// Rate the mic's I²S runs at.
+    pub mic_rate: u32,
     pub speaker_tx: I2sTx<'static, Async>,
     pub speaker_buf: &'static mut [u8],
+    pub speaker_format: SampleFormat,
+    /// Rate the speaker's I²S runs at.
+    pub speaker_rate: u32,
+    /// Rate of the Opus stream sent to the server, the mic is resampled to
+    /// it if needed.
+    pub encode_rate: u32,
+    /// Rate of the Opus stream received from the server, resampled to the
+    /// speaker's if needed.
+    pub decode_rate: u32,
+    /// Cancel the speaker's echo from the mic input.
+    pub echo_cancellation: bool,
+    /// Clean up the mic input after echo cancellation.
+    pub preprocess: Option<PreprocessConfig>,
+    /// Only send speech, framed by [`Recording::Voice`] events. The robot
+    /// relies on them to know when an utterance starts and ends.
+    pub vad: VadConfig,
+    /// Encode 20ms frames and combine them into packets of the usual
+    /// duration, for finer grained loss concealment at the same packet rate.
+    pub multi_frame: bool,
+    /// Report [`Recording::WakeWord`] when the detector hears its phrase.
+    pub wake_word: Option<&'static mut dyn WakeWordDetector>,
 }
 
 impl I2sSimplex {
     pub fn new(s: &Spawner, config: I2sSimplexConfig) -> Self {
-        let (speaker_tx, speaker_rx) = mk_ch!(10);
-        let (mic_tx, mic_rx) = mk_ch!(10);
-        s.spawn(listen_task(mic_tx, config.mic_rx, config.mic_buf))
-            .unwrap();
+        let (speaker_tx, speaker_rx) = mk_ch!(10; Frame);
+        let (mic_tx, mic_rx) = mk_ch!(10; Recording);
+        let echo_cancellation = config.echo_cancellation && config.mic_rate == config.speaker_rate;
+        if config.echo_cancellation && !echo_cancellation {
+            warn!("AEC: mic and speaker rates differ, disabled");
+        }
+        let reference = echo_cancellation.then(|| &*mk_static!(Reference, Reference::new()));
+        s.spawn(listen_task(
+            mic_tx,
+            config.mic_rx,
+            config.mic_buf,
+            config.mic_format,
+            (config.mic_rate, config.encode_rate),
+            reference,
+            config.preprocess,
+            config.vad,
+            config.multi_frame,
+            config.wake_word,
+        ))
+        .unwrap();
         s.spawn(speak_task(
             speaker_rx,
             config.speaker_tx,
             config.speaker_buf,
+            config.speaker_format,
+            (config.decode_rate, config.speaker_rate),
+            reference,
         ))
         .unwrap();
 
@@ -47,48 +123,130 @@ impl Audio for I2sSimplex {
     type Error = ();
 
     async fn play(&mut self, data: &[u8]) -> Result<(), Self::Error> {
-        self.speaker_tx.send(data.into()).await;
+        match SPEAKER_FRAMES.alloc_from(data) {
+            Some(frame) => self.speaker_tx.send(frame).await,
+            None => warn!("SPEAK: dropping {} bytes packet", data.len()),
+        }
         Ok(())
     }
 
-    async fn record(&mut self) -> Result<BytesMut, Self::Error> {
+    async fn record(&mut self) -> Result<Recording, Self::Error> {
         Ok(self.mic_rx.receive().await)
     }
 }
 
 #[embassy_executor::task]
 async fn listen_task(
-    sender: Sender<'static, NoopRawMutex, BytesMut, 10>,
+    sender: Sender<'static, NoopRawMutex, Recording, 10>,
     i2s_rx: I2sRx<'static, Async>,
     rx_buf: &'static mut [u8],
+    format: SampleFormat,
+    (mic_rate, encode_rate): (u32, u32),
+    reference: Option<&'static Reference>,
+    preprocess: Option<PreprocessConfig>,
+    vad: VadConfig,
+    multi_frame: bool,
+    mut wake_word: Option<&'static mut dyn WakeWordDetector>,
 ) {
     info!("start continuous i2s mic");
-    const FRAME_SIZE: usize = 960;
+    let mut aggregator = multi_frame.then(|| {
+        let frames = FRAME_DURATION.as_micros() / FrameDuration::Ms20.as_micros();
+        PacketAggregator::new(frames as usize).unwrap()
+    });
+    let frame_size = match aggregator {
+        Some(_) => FrameDuration::Ms20.samples(encode_rate),
+        None => FRAME_DURATION.samples(encode_rate),
+    };
+    let mut packet_buf = [0; FRAME_CAPACITY];
     let mut data = BytesMut::zeroed(1024 * 10);
-    let mut out = BytesMut::zeroed(200);
-    let mut remain = BytesMut::new();
+    // allocated once, samples are drained without shrinking it
+    let mut remain = Vec::with_capacity(frame_size + data.len());
+    let mut heap = HeapMonitor::new("mic");
+    // speech is only confirmed after the onset, so the frames heard during
+    // it are held back and sent once it is
+    let frame_ms = (frame_size as u32 * 1000 / encode_rate).max(1);
+    let preroll_len = vad.onset_ms.div_ceil(frame_ms) as usize * frame_size;
+    let mut preroll = Vec::with_capacity(preroll_len);
+
+    let mut vad = VoiceActivityDetector::new(encode_rate, vad);
+    let mut aec = reference.map(|r| (r, EchoCanceller::new(AEC_TAPS, AEC_MAX_DELAY)));
+    let mut resampler = (mic_rate != encode_rate).then(|| Resampler::new(mic_rate, encode_rate));
+    let mut preprocess = preprocess.map(|config| Preprocessor::new(encode_rate, config));
 
-    let mut enc = Encoder::new(16000, opus::Channels::Mono, opus::Application::Audio).unwrap();
+    let state = mk_static!(CodecState<ENCODER_STATE>, CodecState::new());
+    let mut enc = Encoder::new_in(
+        state,
+        encode_rate,
+        opus::Channels::Mono,
+        opus::Application::Audio,
+    )
+    .unwrap();
     enc.set_complexity(3).unwrap();
     let mut transfer = i2s_rx.read_dma_circular_async(rx_buf).unwrap();
     loop {
         use esp_hal::i2s::master::Error;
         match transfer.pop(&mut data).await {
             Ok(n) => {
-                // INMP441 puts data into 24bits
-                let data = data[..n]
-                    .array_chunks::<4>()
-                    .step_by(2) // skip right channel
-                    .map(|c| i32::from_le_bytes(*c) >> 12) // shift right by 12 bits (not sure why)
-                    .map(|c| c.clamp(i16::MIN as _, i16::MAX as _) as i16);
-                remain.extend(data.flat_map(|b| b.to_le_bytes()));
-
-                while remain.len() >= FRAME_SIZE * 2 {
-                    let frame = remain.split_to(FRAME_SIZE * 2);
-                    let n = enc.encode(frame.transmute(), out.transmute_cap()).unwrap();
-                    sender.try_send(out[..n].into()).ok(); // Ignore buffer full
+                for mic in format.decode(&data[..n]) {
+                    let mic = match &mut aec {
+                        Some((reference, aec)) => aec.process(pop_reference(reference), mic),
+                        None => mic,
+                    };
+                    let mut emit = |sample| {
+                        let sample = match &mut preprocess {
+                            Some(preprocess) => preprocess.process(sample),
+                            None => sample,
+                        };
+                        remain.push(sample);
+                    };
+                    match &mut resampler {
+                        Some(resampler) => resampler.push(mic, emit),
+                        None => emit(mic),
+                    }
+                }
+
+                for frame in remain.chunks_exact(frame_size) {
+                    heap.tick();
+                    if let Some(word) = wake_word.as_mut().and_then(|w| w.process(frame)) {
+                        send_event(&sender, Recording::WakeWord(word));
+                    }
+                    let event = vad.process(frame);
+                    if let Some(event) = event {
+                        // frames still queued belong before the event
+                        if let Some(aggregator) = &mut aggregator {
+                            send_combined(&sender, aggregator, &mut packet_buf, true);
+                        }
+                        send_event(&sender, Recording::Voice(event));
+                    }
+                    if !vad.is_speech() {
+                        if preroll.len() == preroll_len {
+                            preroll.drain(..frame_size);
+                        }
+                        preroll.extend_from_slice(frame);
+                        continue;
+                    }
+                    if event == Some(VadEvent::SpeechStart) {
+                        for frame in preroll.chunks_exact(frame_size) {
+                            send_frame(
+                                &sender,
+                                &mut enc,
+                                aggregator.as_mut(),
+                                &mut packet_buf,
+                                frame,
+                            );
+                        }
+                    }
+                    preroll.clear();
+                    send_frame(
+                        &sender,
+                        &mut enc,
+                        aggregator.as_mut(),
+                        &mut packet_buf,
+                        frame,
+                    );
                 }
-                remain = BytesMut::from(remain); // try to drop `remain` to free memory
+                let used = remain.len() - remain.len() % frame_size;
+                remain.drain(..used);
             }
             Err(Error::DmaError(DmaError::Late)) => warn!("Dma late for mic"),
             Err(Error::DmaError(DmaError::BufferTooSmall)) => error!("Buffer too small for mic"),
@@ -97,42 +255,163 @@ async fn listen_task(
     }
 }
 
+/// Queue a detector's event for the robot, dropping it rather than holding up
+/// the mic if the robot falls behind.
+fn send_event(sender: &Sender<'static, NoopRawMutex, Recording, 10>, recording: Recording) {
+    if let Err(TrySendError::Full(recording)) = sender.try_send(recording) {
+        warn!("MIC: queue full, dropping {recording:?}");
+    }
+}
+
+/// Encode a frame of speech and queue it for the uplink, combined into a
+/// packet by `aggregator` if given.
+fn send_frame(
+    sender: &Sender<'static, NoopRawMutex, Recording, 10>,
+    enc: &mut Encoder,
+    aggregator: Option<&mut PacketAggregator>,
+    buf: &mut [u8],
+    frame: &[i16],
+) {
+    match aggregator {
+        Some(aggregator) => {
+            // repacketizing adds at most a byte per frame to the packets
+            // combined, which bounds a combined packet to a frame
+            let max_packet = FRAME_CAPACITY / aggregator.frames() - 1;
+            let n = enc.encode(frame, &mut buf[..max_packet]).unwrap();
+            aggregator.push(&buf[..n]).unwrap();
+            send_combined(sender, aggregator, buf, false);
+        }
+        None => {
+            let Some(mut packet) = MIC_FRAMES.alloc() else {
+                warn!("MIC: no free frame, dropping audio");
+                return;
+            };
+            let n = enc.encode(frame, packet.spare()).unwrap();
+            packet.set_len(n);
+            sender.try_send(Recording::Audio(packet)).ok(); // Ignore buffer full
+        }
+    }
+}
+
+/// Queue an encoded packet for the uplink, dropping it if that falls behind.
+fn send_packet(sender: &Sender<'static, NoopRawMutex, Recording, 10>, data: &[u8]) {
+    let Some(packet) = MIC_FRAMES.alloc_from(data) else {
+        warn!("MIC: no free frame, dropping audio");
+        return;
+    };
+    sender.try_send(Recording::Audio(packet)).ok(); // Ignore buffer full
+}
+
+/// Send the packets `aggregator` has combined, or all it holds when
+/// `flush`ing.
+fn send_combined(
+    sender: &Sender<'static, NoopRawMutex, Recording, 10>,
+    aggregator: &mut PacketAggregator,
+    buf: &mut [u8],
+    flush: bool,
+) {
+    loop {
+        let next = if flush {
+            aggregator.flush(buf)
+        } else {
+            aggregator.pop(buf)
+        };
+        match next {
+            Ok(Some(n)) => send_packet(sender, &buf[..n]),
+            Ok(None) => break,
+            Err(e) => {
+                // the packets stay queued, so they would fail again
+                warn!("MIC: dropping packets that can't be combined: {e:?}");
+                *aggregator = PacketAggregator::new(aggregator.frames()).unwrap();
+                break;
+            }
+        }
+    }
+}
+
 #[embassy_executor::task]
 async fn speak_task(
-    receiver: Receiver<'static, NoopRawMutex, BytesMut, 10>,
+    receiver: Receiver<'static, NoopRawMutex, Frame, 10>,
     i2s_tx: I2sTx<'static, Async>,
     tx_buf: &'static mut [u8],
+    format: SampleFormat,
+    (decode_rate, speaker_rate): (u32, u32),
+    reference: Option<&'static Reference>,
 ) {
     info!("start continuous i2s speaker");
     let mut transfer = i2s_tx.write_dma_circular_async(tx_buf).unwrap();
 
-    let pcm = &mut [0; 960];
+    let frame = &mut vec![0; FRAME_DURATION.samples(decode_rate)][..];
+    let mut resampler =
+        (decode_rate != speaker_rate).then(|| Resampler::new(decode_rate, speaker_rate));
+    let mut resampled = Vec::new();
+    let mut out = Vec::new();
+    let mut heap = HeapMonitor::new("speaker");
     // FIXME: need to reset decoder every time a new udp stream is received
-    let mut dec = Decoder::new(16000, opus::Channels::Mono).unwrap();
+    let state = mk_static!(CodecState<DECODER_STATE>, CodecState::new());
+    let mut dec = Decoder::new_in(state, decode_rate, opus::Channels::Mono).unwrap();
     loop {
         trace!("SPEAK: queued {} audio samples", receiver.len());
+        heap.tick();
 
-        match receiver.try_receive() {
-            Ok(data) => dec.decode(&data, pcm, false).unwrap(),
-            Err(_) => dec.decode(&[], pcm, false).unwrap(),
+        // shorter packets only fill part of the frame
+        let pcm = match receiver.try_receive() {
+            Ok(data) => dec.decode_into(&data, frame, false).unwrap(),
+            Err(_) => dec.decode_into(&[], frame, false).unwrap(),
         };
+        let pcm = match &mut resampler {
+            Some(resampler) => {
+                resampled.clear();
+                resampler.process(pcm, |sample| resampled.push(sample));
+                &resampled[..]
+            }
+            None => &pcm[..],
+        };
+
+        if let Some(reference) = reference {
+            push_reference(reference, pcm);
+        }
 
-        let volume_factor: i32 = 32112; // WARNING: 70% volume
-        let mut data: BytesMut = pcm
-            .into_iter()
-            .map(|p| {
-                let temp = *p as i64 * volume_factor as i64;
-                // clamp to i32 range
-                temp.clamp(i32::MIN as i64, i32::MAX as i64) as i32
-            })
-            .flat_map(|x| [0, x])
-            .flat_map(|x| x.to_le_bytes())
-            .collect();
+        out.clear();
+        out.extend(format.encode(pcm.iter().copied()));
 
         // Push all bytes (audio or silence) into I²S
+        let mut data = &out[..];
         while !data.is_empty() {
-            let n = transfer.push(&data).await.unwrap();
-            data.advance(n);
+            let n = transfer.push(data).await.unwrap();
+            data = &data[n..];
+        }
+    }
+}
+
+/// Hand played samples to the mic side. If it lags behind, chunks that don't
+/// fit are dropped whole so the pipe never holds half a sample.
+fn push_reference(reference: &Reference, pcm: &[i16]) {
+    for pcm in pcm.chunks(960) {
+        let mut buf = [0; 960 * 2];
+        for (b, p) in buf.chunks_exact_mut(2).zip(pcm) {
+            b.copy_from_slice(&p.to_le_bytes());
+        }
+        let mut bytes = &buf[..pcm.len() * 2];
+        if reference.free_capacity() < bytes.len() {
+            trace!("AEC: reference full, dropping {} samples", pcm.len());
+            continue;
         }
+        // a write stops at the end of the ring buffer, the rest takes another
+        while !bytes.is_empty() {
+            match reference.try_write(bytes) {
+                Ok(n) => bytes = &bytes[n..],
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+/// Next played sample, silence if the speaker hasn't produced one.
+fn pop_reference(reference: &Reference) -> i16 {
+    let mut sample = [0; 2];
+    match reference.try_read(&mut sample) {
+        Ok(2) => i16::from_le_bytes(sample),
+        _ => 0,
     }
 }
@@ -4,26 +4,38 @@
 #![feature(type_alias_impl_trait)]
 #![no_std]
 
-use core::{fmt::Debug, future::Future};
+use core::{convert::Infallible, fmt::Debug, future::Future};
 
-use bytes::{Bytes, BytesMut};
+use assets::{AssetError, AssetPlayer};
+
+use bytes::Bytes;
+use dsp::vad::VadEvent;
 use embassy_futures::select::select;
-use esp_println::{dbg, println};
-use log::{debug, info};
-use p3::P3Reader;
-use proto::{BufTransport, Protocol, Transport};
+use embassy_time::{with_timeout, Duration, Timer};
+use esp_println::dbg;
+use log::{debug, error, info, warn};
+use pool::FramePool;
+use proto::{BufTransport, ListenMode, Protocol, ServerMsg, ServerText, Transport, Tts};
 use serde::{Deserialize, Serialize};
 
+pub mod assets;
 pub mod audio;
 pub mod codec;
 #[macro_use]
 mod r#macro;
 pub mod net;
-pub mod p3;
 pub mod proto;
 pub mod util;
 pub mod wifi;
 
+#[cfg(feature = "sdcard")]
+pub use firmware_core::sdcard;
+pub use firmware_core::{dsp, ogg, p3, pool, Audio, Recording};
+
+/// How long to wait for the server's next message while it replies, before
+/// giving up on the reply.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(15);
+
 #[derive(Debug)]
 pub enum RobotState {
     Idle,
@@ -31,30 +43,64 @@ pub enum RobotState {
     Listening,
 }
 
-pub trait Audio {
-    type Error;
+/// Local prompts, played from the embedded [`assets`].
+#[derive(Debug, Clone, Copy)]
+pub enum Cue {
+    /// Wi-Fi has to be configured.
+    WifiConfig,
+    /// Heard the wake word.
+    Wake,
+    /// Something went wrong.
+    Error,
+}
 
-    fn play(&mut self, data: &[u8]) -> impl Future<Output = Result<(), Self::Error>>;
-    fn record(&mut self) -> impl Future<Output = Result<BytesMut, Self::Error>>;
+impl Cue {
+    fn asset(self) -> &'static str {
+        match self {
+            Cue::WifiConfig => "wificonfig",
+            Cue::Wake => "ding",
+            Cue::Error => "error",
+        }
+    }
 }
 
+/// Audio that plays nothing and records empty frames.
 pub struct DummyAudio;
 impl Audio for DummyAudio {
-    type Error = ();
+    type Error = Infallible;
 
     fn play(&mut self, _data: &[u8]) -> impl Future<Output = Result<(), Self::Error>> {
         async { Ok(()) }
     }
 
-    fn record(&mut self) -> impl Future<Output = Result<BytesMut, Self::Error>> {
-        async { Ok(BytesMut::new()) }
+    fn record(&mut self) -> impl Future<Output = Result<Recording, Self::Error>> {
+        static FRAMES: FramePool<4> = FramePool::new();
+        async {
+            // with every frame still held, wait for one to come back like a
+            // mic waiting for its next frame
+            loop {
+                if let Some(frame) = FRAMES.alloc() {
+                    return Ok(Recording::Audio(frame));
+                }
+                Timer::after_millis(20).await;
+            }
+        }
     }
 }
 
+/// What kept an utterance from reaching the server.
+#[derive(Debug)]
+pub enum ListenError<A, T> {
+    Audio(A),
+    Transport(T),
+}
+
 pub struct Robot<P, C> {
     state: RobotState,
     proto: Protocol<P>,
     codec: C,
+    /// Only start listening on a wake word, not on any speech.
+    wake_word: bool,
 }
 
 impl<P, C> Robot<P, C>
@@ -69,15 +115,37 @@ where
             state: RobotState::Idle,
             proto: Protocol::new(proto),
             codec,
+            wake_word: false,
         }
     }
 
+    /// Wait for the codec to report a wake word before listening, instead of
+    /// starting on the first speech it hears.
+    pub fn with_wake_word(mut self, enabled: bool) -> Self {
+        self.wake_word = enabled;
+        self
+    }
+
     // TODO: visable only for debug purpose
     pub async fn set_state(&mut self, state: RobotState) {
         info!("Robot state: {:?}", state);
         self.state = state;
     }
 
+    /// Play a local prompt, skipping it if there's no asset for it.
+    pub async fn play_cue(&mut self, cue: Cue) {
+        match AssetPlayer::new(&mut self.codec).play(cue.asset()).await {
+            Ok(()) => (),
+            Err(AssetError::NotFound) => debug!("No asset for {cue:?}"),
+            Err(e) => warn!("Failed to play {cue:?}: {e:?}"),
+        }
+    }
+
+    async fn report_error(&mut self, e: impl Debug) {
+        error!("{e:?}");
+        self.play_cue(Cue::Error).await;
+    }
+
     pub async fn main_loop(mut self) {
         extern crate alloc;
         use alloc::string::ToString;
@@ -86,23 +154,13 @@ where
         let id = self.proto.recv_hello().await.unwrap().to_string();
         info!("Session Started: {id}");
         dbg!(self.proto.transport.buf_read().await.unwrap());
-        self.proto.send_listening(&id).await.unwrap();
-        let mut p3 = P3Reader::new(include_bytes!("../assets/wificonfig.p3"));
-        while let Some(opus) = p3.next().await.unwrap() {
-            debug!("sending {} audio bytes", opus.len());
-            self.proto.transport.send_bin(&opus).await.unwrap();
-        }
-        self.proto.send_listening_stop(&id).await.unwrap();
 
         loop {
-            let msg = self.proto.recv().await.unwrap();
-            match msg {
-                proto::ServerMsg::Text(t) => {
-                    println!("{t:?}");
-                }
-                proto::ServerMsg::Binary(audio) => self.codec.play(audio).await.unwrap(),
-                proto::ServerMsg::Unknown(text) => {
-                    println!("Unknown message: {}", text);
+            match self.listen(&id).await {
+                Ok(()) => self.respond().await,
+                Err(e) => {
+                    self.report_error(e).await;
+                    self.set_state(RobotState::Idle).await;
                 }
             }
         }
@@ -116,6 +174,99 @@ where
         // }
     }
 
+    /// Play the server's reply to the last utterance, until it's done
+    /// speaking or stops sending.
+    async fn respond(&mut self) {
+        loop {
+            let msg = match with_timeout(RESPONSE_TIMEOUT, self.proto.recv()).await {
+                Ok(Ok(msg)) => msg,
+                Ok(Err(e)) => {
+                    // the connection is broken, waiting on it won't help
+                    self.report_error(e).await;
+                    break;
+                }
+                Err(_) => {
+                    warn!("No reply for {}s, giving up", RESPONSE_TIMEOUT.as_secs());
+                    break;
+                }
+            };
+            match msg {
+                ServerMsg::Text(ServerText::Tts(Tts::Start)) => {
+                    self.set_state(RobotState::Speaking).await;
+                }
+                ServerMsg::Text(ServerText::Tts(Tts::Stop)) => break,
+                ServerMsg::Text(t) => {
+                    info!("{t:?}");
+                }
+                ServerMsg::Binary(audio) => {
+                    if let Err(e) = self.codec.play(audio).await {
+                        self.report_error(e).await;
+                    }
+                }
+                ServerMsg::Unknown(text) => {
+                    warn!("Unknown message: {}", text);
+                }
+            }
+        }
+        self.set_state(RobotState::Idle).await;
+    }
+
+    /// Stream one utterance to the server, with the mic's voice activity
+    /// detector deciding where it starts and ends. With a wake word, speech
+    /// is ignored while idle until the word is heard, which is reported to
+    /// the server and starts the utterance right away.
+    pub async fn listen(
+        &mut self,
+        session_id: &str,
+    ) -> Result<(), ListenError<C::Error, P::Error>> {
+        loop {
+            match self.codec.record().await.map_err(ListenError::Audio)? {
+                Recording::WakeWord(word) if matches!(self.state, RobotState::Idle) => {
+                    info!("Wake word: {word}");
+                    self.proto
+                        .send_wake_word_detected(session_id, word)
+                        .await
+                        .map_err(ListenError::Transport)?;
+                    self.play_cue(Cue::Wake).await;
+                    self.proto
+                        .send_listening(session_id, ListenMode::Manual)
+                        .await
+                        .map_err(ListenError::Transport)?;
+                    self.set_state(RobotState::Listening).await;
+                }
+                Recording::WakeWord(_) => (),
+                Recording::Voice(VadEvent::SpeechStart)
+                    if matches!(self.state, RobotState::Listening) || self.wake_word => {}
+                Recording::Voice(VadEvent::SpeechStart) => {
+                    self.proto
+                        .send_listening(session_id, ListenMode::Manual)
+                        .await
+                        .map_err(ListenError::Transport)?;
+                    self.set_state(RobotState::Listening).await;
+                }
+                Recording::Audio(opus) if matches!(self.state, RobotState::Listening) => {
+                    self.proto
+                        .transport
+                        .send_bin(&opus)
+                        .await
+                        .map_err(ListenError::Transport)?;
+                }
+                Recording::Audio(_) => (),
+                // queued while the robot was speaking
+                Recording::Voice(VadEvent::SpeechEnd)
+                    if !matches!(self.state, RobotState::Listening) => {}
+                Recording::Voice(VadEvent::SpeechEnd) => {
+                    self.proto
+                        .send_listening_stop(session_id)
+                        .await
+                        .map_err(ListenError::Transport)?;
+                    self.set_state(RobotState::Idle).await;
+                    return Ok(());
+                }
+            }
+        }
+    }
+
     // async fn idle(&mut self) -> Result<(), P::Error> {
     //     match self.proto.recv().await? {
     //         Msg::Cmd(Command::Stop) => self.set_state(RobotState::Idle).await,
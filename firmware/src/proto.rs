@@ -118,6 +118,27 @@ pub enum Tts {
     SentenceEnd {},
 }
 
+/// Who decides when the user has stopped talking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListenMode {
+    /// The server's VAD.
+    Auto,
+    /// The device, by sending `listen` `stop`.
+    Manual,
+    /// Nobody, audio streams continuously.
+    Realtime,
+}
+
+impl ListenMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            ListenMode::Auto => "auto",
+            ListenMode::Manual => "manual",
+            ListenMode::Realtime => "realtime",
+        }
+    }
+}
+
 pub enum ServerMsg<'a> {
     Unknown(&'a str),
     Text(ServerText),
@@ -172,14 +193,39 @@ impl<T: BufTransport> Protocol<T> {
         }
     }
 
-    pub async fn send_listening(&mut self, session_id: &str) -> Result<(), T::Error> {
+    pub async fn send_listening(
+        &mut self,
+        session_id: &str,
+        mode: ListenMode,
+    ) -> Result<(), T::Error> {
         extern crate alloc;
         let msg = alloc::format!(
             r#"{{
             "session_id": "{session_id}",
             "type": "listen",
             "state": "start",
-            "mode": "auto"
+            "mode": "{}"
+            }}
+            "#,
+            mode.as_str()
+        );
+        self.transport.send_text(msg.as_str()).await
+    }
+
+    pub async fn send_wake_word_detected(
+        &mut self,
+        session_id: &str,
+        word: &str,
+    ) -> Result<(), T::Error> {
+        extern crate alloc;
+        // quoted and escaped, templates can be named anything
+        let word = serde_json::to_string(word).unwrap();
+        let msg = alloc::format!(
+            r#"{{
+            "session_id": "{session_id}",
+            "type": "listen",
+            "state": "detect",
+            "text": {word}
             }}
             "#
         );
@@ -1,6 +1,7 @@
 use core::slice;
 
 use bytes::BytesMut;
+use log::{debug, warn};
 
 pub trait BytesMutExtend {
     fn transmute<T>(&self) -> &[T];
@@ -42,3 +43,69 @@ impl<T> SliceExt for [T] {
         slice::from_raw_parts_mut(self.as_ptr() as *mut T, self.len())
     }
 }
+
+/// Global heap usage, as reported by the allocator.
+#[derive(Debug, Clone, Copy)]
+pub struct HeapUsage {
+    pub used: usize,
+    pub free: usize,
+}
+
+impl HeapUsage {
+    pub fn now() -> Self {
+        Self {
+            used: esp_alloc::HEAP.used(),
+            free: esp_alloc::HEAP.free(),
+        }
+    }
+}
+
+/// Logs heap usage every few iterations of a loop that shouldn't allocate,
+/// warning when it keeps growing.
+pub struct HeapMonitor {
+    name: &'static str,
+    count: u32,
+    last: usize,
+    peak: usize,
+}
+
+impl HeapMonitor {
+    /// Iterations between two reports.
+    const EVERY: u32 = 100;
+
+    pub fn new(name: &'static str) -> Self {
+        let used = HeapUsage::now().used;
+        Self {
+            name,
+            count: 0,
+            last: used,
+            peak: used,
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.count += 1;
+        if self.count < Self::EVERY {
+            return;
+        }
+        self.count = 0;
+
+        let HeapUsage { used, free } = HeapUsage::now();
+        let grown = used.saturating_sub(self.last);
+        self.peak = self.peak.max(used);
+        self.last = used;
+        if grown > 0 {
+            warn!(
+                "{}: heap grew {grown} bytes over {} frames, {used} used, {free} free, {} peak",
+                self.name,
+                Self::EVERY,
+                self.peak
+            );
+        } else {
+            debug!(
+                "{}: heap {used} used, {free} free, {} peak",
+                self.name, self.peak
+            );
+        }
+    }
+}
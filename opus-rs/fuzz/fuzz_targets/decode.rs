@@ -0,0 +1,54 @@
+//! Decode arbitrary packets, as received from the network.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use opus::{Channels, Decoder};
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    sample_rate: u8,
+    stereo: bool,
+    packets: Vec<Packet>,
+}
+
+#[derive(Debug, Arbitrary)]
+struct Packet {
+    data: Vec<u8>,
+    /// Output length in samples, may be neither a frame nor a channel
+    /// multiple.
+    output: u16,
+    fec: bool,
+    float: bool,
+    into: bool,
+}
+
+fuzz_target!(|input: Input| {
+    let rates = [8000, 12000, 16000, 24000, 48000];
+    let sample_rate = rates[input.sample_rate as usize % rates.len()];
+    let channels = match input.stereo {
+        false => Channels::Mono,
+        true => Channels::Stereo,
+    };
+    let mut decoder = Decoder::new(sample_rate, channels).unwrap();
+
+    for packet in input.packets {
+        let len = packet.output as usize;
+        let samples = match (packet.float, packet.into) {
+            (false, false) => decoder.decode(&packet.data, &mut vec![0; len], packet.fec),
+            (true, false) => decoder.decode_float(&packet.data, &mut vec![0.0; len], packet.fec),
+            (false, true) => decoder
+                .decode_into(&packet.data, &mut vec![0_i16; len], packet.fec)
+                .map(|pcm| pcm.len() / channels as usize),
+            (true, true) => decoder
+                .decode_into(&packet.data, &mut vec![0_f32; len], packet.fec)
+                .map(|pcm| pcm.len() / channels as usize),
+        };
+        if let Ok(samples) = samples {
+            assert!(samples * channels as usize <= len);
+        }
+        let _ = decoder.get_nb_samples(&packet.data);
+        let _ = decoder.get_last_packet_duration();
+    }
+});
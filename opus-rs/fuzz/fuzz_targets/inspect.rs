@@ -0,0 +1,19 @@
+//! Check the pure Rust packet parser against libopus.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use opus::packet;
+
+fuzz_target!(|data: &[u8]| {
+    match (packet::parse(data), packet::inspect(data)) {
+        (Ok(expected), Ok(info)) => {
+            assert_eq!(info.toc.0, expected.toc);
+            assert_eq!(info.payload_offset, expected.payload_offset);
+            assert_eq!(info.frames().collect::<Vec<_>>(), expected.frames);
+            assert_eq!(info.nb_samples(48000), packet::get_nb_samples(data, 48000).unwrap());
+        }
+        (Err(expected), Err(err)) => assert_eq!(err.code(), expected.code()),
+        (expected, info) => panic!("libopus gave {expected:?}, inspect gave {info:?}"),
+    }
+});
@@ -0,0 +1,29 @@
+//! Read arbitrary P3 clips with the firmware's reader, as loaded from the SD
+//! card or downloaded.
+
+#![no_main]
+
+use embassy_futures::block_on;
+use libfuzzer_sys::fuzz_target;
+
+#[allow(dead_code)]
+#[path = "../../../firmware-core/src/p3.rs"]
+mod p3;
+
+fuzz_target!(|data: &[u8]| {
+    // borrowing packets straight out of the clip
+    let mut reader = p3::P3Reader::new(data);
+    let mut total = 0;
+    while let Ok(Some(packet)) = reader.next_slice() {
+        total += packet.len() + p3::HEADER_LEN;
+    }
+    assert!(total <= data.len());
+    let _ = p3::duration(data);
+
+    // and reading them through `embedded_io_async`, as from a file
+    let mut reader = p3::P3Reader::new(data);
+    let mut buf = [0; 512];
+    while let Ok(Some(_)) | Err(p3::P3Error::TooLarge(_)) = block_on(reader.next_into(&mut buf)) {}
+    let mut reader = p3::P3Reader::new(data);
+    let _ = block_on(reader.skip_to(embassy_time::Duration::from_secs(3)));
+});
@@ -0,0 +1,44 @@
+//! Pad and unpad arbitrary packets, checking that the frames survive.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use opus::packet;
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    packet: Vec<u8>,
+    padding: u16,
+    /// Streams for the multistream functions, single stream if zero.
+    streams: u8,
+}
+
+fuzz_target!(|input: Input| {
+    // libopus returns early when there is nothing to add, without parsing
+    if input.padding == 0 {
+        return;
+    }
+    let len = input.packet.len();
+    let mut buf = input.packet.clone();
+    buf.resize(len + input.padding as usize, 0);
+
+    if input.streams == 0 {
+        let frames = packet::parse(&input.packet).map(|p| p.frames.concat());
+        if packet::pad(&mut buf, len).is_err() {
+            return;
+        }
+        // padding only succeeds on packets that parse
+        let frames = frames.unwrap();
+        assert_eq!(packet::parse(&buf).unwrap().frames.concat(), frames);
+        let unpadded = packet::unpad(&mut buf).unwrap();
+        assert!(unpadded <= len);
+        assert_eq!(packet::parse(&buf[..unpadded]).unwrap().frames.concat(), frames);
+    } else {
+        if packet::multistream_pad(&mut buf, len, input.streams).is_err() {
+            return;
+        }
+        let unpadded = packet::multistream_unpad(&mut buf, input.streams).unwrap();
+        assert!(unpadded <= buf.len());
+    }
+});
@@ -0,0 +1,68 @@
+//! Run arbitrary sequences of repacketizer operations, and the aggregator and
+//! splitter built on them.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use opus::{PacketAggregator, PacketSplitter, Repacketizer};
+
+#[derive(Debug, Arbitrary)]
+enum Op {
+    Begin,
+    Cat(u8),
+    Out(u16),
+    OutRange(usize, usize, u16),
+    NbFrames,
+    Push(u8),
+    Pop(u16),
+    Flush(u16),
+    Split(u8, u16),
+}
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    packets: Vec<Vec<u8>>,
+    frames: u8,
+    ops: Vec<Op>,
+}
+
+fuzz_target!(|input: Input| {
+    if input.packets.is_empty() {
+        return;
+    }
+    let packet = |i: u8| &input.packets[i as usize % input.packets.len()][..];
+
+    let mut rp = Repacketizer::new().unwrap();
+    let mut aggregator = PacketAggregator::new(input.frames as usize % 48 + 1).unwrap();
+    let mut splitter = PacketSplitter::new().unwrap();
+    let mut state = rp.begin();
+    for op in &input.ops {
+        match *op {
+            Op::Begin => state = rp.begin(),
+            Op::Cat(i) => drop(state.cat(packet(i))),
+            Op::Out(len) => drop(state.out(&mut vec![0; len as usize])),
+            Op::OutRange(begin, end, len) => {
+                drop(state.out_range(begin, end, &mut vec![0; len as usize]))
+            }
+            Op::NbFrames => assert!(state.get_nb_frames() <= 48),
+            Op::Push(i) => drop(aggregator.push(packet(i))),
+            Op::Pop(len) => drop(aggregator.pop(&mut vec![0; len as usize])),
+            Op::Flush(len) => drop(aggregator.flush(&mut vec![0; len as usize])),
+            Op::Split(i, len) => {
+                let Ok(mut split) = splitter.split(packet(i)) else {
+                    continue;
+                };
+                let mut out = vec![0; len as usize];
+                while let Ok(Some(n)) = split.next_into(&mut out) {
+                    assert!(n <= packet(i).len());
+                }
+            }
+        }
+    }
+
+    // whatever was queued can always be written out
+    let mut out = [0; 1276 * 48];
+    while aggregator.flush(&mut out).unwrap().is_some() {}
+    assert_eq!(aggregator.pending(), 0);
+});
@@ -10,9 +10,14 @@
 //!
 //! Only brief descriptions are included here. For detailed information, consult
 //! the [libopus documentation](https://opus-codec.org/docs/opus_api-1.1.2/).
+//!
+//! Without the default `alloc` feature the crate doesn't need a global
+//! allocator; codec states can then live in caller-provided [`CodecState`]
+//! memory rather than being allocated by libopus.
 #![warn(missing_docs)]
 #![no_std]
 
+#[cfg(feature = "alloc")]
 extern crate alloc;
 
 extern crate audiopus_sys as ffi;
@@ -20,8 +25,15 @@ extern crate audiopus_sys as ffi;
 use core::convert::TryFrom;
 use core::ffi::{c_int, CStr};
 use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::slice;
+
+#[cfg(feature = "bytes")]
+use bytes::BufMut;
 
+#[cfg(feature = "alloc")]
 use alloc::vec;
+#[cfg(feature = "alloc")]
 use alloc::vec::Vec;
 
 // ============================================================================
@@ -162,6 +174,114 @@ impl Bitrate {
     }
 }
 
+/// The type of signal being encoded, as a hint to the encoder's mode choice.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[repr(i32)]
+pub enum Signal {
+    /// Auto/default setting.
+    Auto = ffi::OPUS_AUTO,
+    /// Bias thresholds towards choosing LPC or Hybrid modes.
+    Voice = ffi::OPUS_SIGNAL_VOICE,
+    /// Bias thresholds towards choosing MDCT modes.
+    Music = ffi::OPUS_SIGNAL_MUSIC,
+}
+
+impl Signal {
+    fn decode(value: i32, what: &'static str) -> Result<Signal> {
+        match value {
+            ffi::OPUS_AUTO => Ok(Signal::Auto),
+            ffi::OPUS_SIGNAL_VOICE => Ok(Signal::Voice),
+            ffi::OPUS_SIGNAL_MUSIC => Ok(Signal::Music),
+            _ => Err(Error::bad_arg(what)),
+        }
+    }
+}
+
+/// The durations an Opus frame may have.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[repr(i32)]
+pub enum FrameDuration {
+    /// 2.5ms frames.
+    Ms2_5 = ffi::OPUS_FRAMESIZE_2_5_MS,
+    /// 5ms frames.
+    Ms5 = ffi::OPUS_FRAMESIZE_5_MS,
+    /// 10ms frames.
+    Ms10 = ffi::OPUS_FRAMESIZE_10_MS,
+    /// 20ms frames.
+    Ms20 = ffi::OPUS_FRAMESIZE_20_MS,
+    /// 40ms frames.
+    Ms40 = ffi::OPUS_FRAMESIZE_40_MS,
+    /// 60ms frames.
+    Ms60 = ffi::OPUS_FRAMESIZE_60_MS,
+    /// 80ms frames.
+    Ms80 = ffi::OPUS_FRAMESIZE_80_MS,
+    /// 100ms frames.
+    Ms100 = ffi::OPUS_FRAMESIZE_100_MS,
+    /// 120ms frames.
+    Ms120 = ffi::OPUS_FRAMESIZE_120_MS,
+}
+
+impl FrameDuration {
+    /// Every frame duration, shortest first.
+    pub const ALL: [FrameDuration; 9] = [
+        FrameDuration::Ms2_5,
+        FrameDuration::Ms5,
+        FrameDuration::Ms10,
+        FrameDuration::Ms20,
+        FrameDuration::Ms40,
+        FrameDuration::Ms60,
+        FrameDuration::Ms80,
+        FrameDuration::Ms100,
+        FrameDuration::Ms120,
+    ];
+
+    /// The duration in steps of 2.5ms.
+    fn units(self) -> u32 {
+        match self {
+            FrameDuration::Ms2_5 => 1,
+            FrameDuration::Ms5 => 2,
+            FrameDuration::Ms10 => 4,
+            FrameDuration::Ms20 => 8,
+            FrameDuration::Ms40 => 16,
+            FrameDuration::Ms60 => 24,
+            FrameDuration::Ms80 => 32,
+            FrameDuration::Ms100 => 40,
+            FrameDuration::Ms120 => 48,
+        }
+    }
+
+    /// Get the duration in microseconds.
+    pub fn as_micros(self) -> u32 {
+        self.units() * 2500
+    }
+
+    /// Get the number of samples *per channel* in a frame of this duration.
+    pub fn samples(self, sample_rate: u32) -> usize {
+        (sample_rate / 400 * self.units()) as usize
+    }
+
+    /// Get the duration of a frame of `samples` samples *per channel*, if it
+    /// is one Opus allows at `sample_rate`.
+    pub fn from_samples(samples: usize, sample_rate: u32) -> Option<FrameDuration> {
+        FrameDuration::ALL.into_iter().find(|d| d.samples(sample_rate) == samples)
+    }
+
+    fn from_int(value: i32) -> Option<FrameDuration> {
+        Some(match value {
+            ffi::OPUS_FRAMESIZE_2_5_MS => FrameDuration::Ms2_5,
+            ffi::OPUS_FRAMESIZE_5_MS => FrameDuration::Ms5,
+            ffi::OPUS_FRAMESIZE_10_MS => FrameDuration::Ms10,
+            ffi::OPUS_FRAMESIZE_20_MS => FrameDuration::Ms20,
+            ffi::OPUS_FRAMESIZE_40_MS => FrameDuration::Ms40,
+            ffi::OPUS_FRAMESIZE_60_MS => FrameDuration::Ms60,
+            ffi::OPUS_FRAMESIZE_80_MS => FrameDuration::Ms80,
+            ffi::OPUS_FRAMESIZE_100_MS => FrameDuration::Ms100,
+            ffi::OPUS_FRAMESIZE_120_MS => FrameDuration::Ms120,
+            _ => return None,
+        })
+    }
+}
+
 /// Get the libopus version string.
 ///
 /// Applications may look for the substring "-fixed" in the version string to
@@ -193,6 +313,130 @@ macro_rules! ctl {
 	}
 }
 
+// ============================================================================
+// Sample Types
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for i16 {}
+    #[cfg(not(feature = "disable-float-api"))]
+    impl Sealed for f32 {}
+}
+
+/// A PCM sample type libopus encodes from and decodes to, `i16` or `f32`.
+///
+/// `f32` is left out with the `disable-float-api` feature.
+pub trait Sample: private::Sealed + Copy {
+    #[doc(hidden)]
+    const ENCODE: &'static str;
+    #[doc(hidden)]
+    const DECODE: &'static str;
+
+    #[doc(hidden)]
+    unsafe fn encode(
+        st: *mut ffi::OpusEncoder,
+        pcm: *const Self,
+        frame_size: c_int,
+        data: *mut u8,
+        max_data_bytes: i32,
+    ) -> c_int;
+
+    #[doc(hidden)]
+    unsafe fn decode(
+        st: *mut ffi::OpusDecoder,
+        data: *const u8,
+        len: i32,
+        pcm: *mut Self,
+        frame_size: c_int,
+        decode_fec: c_int,
+    ) -> c_int;
+}
+
+impl Sample for i16 {
+    const ENCODE: &'static str = "opus_encode";
+    const DECODE: &'static str = "opus_decode";
+
+    unsafe fn encode(
+        st: *mut ffi::OpusEncoder,
+        pcm: *const i16,
+        frame_size: c_int,
+        data: *mut u8,
+        max_data_bytes: i32,
+    ) -> c_int {
+        unsafe { ffi::opus_encode(st, pcm, frame_size, data, max_data_bytes) }
+    }
+
+    unsafe fn decode(
+        st: *mut ffi::OpusDecoder,
+        data: *const u8,
+        len: i32,
+        pcm: *mut i16,
+        frame_size: c_int,
+        decode_fec: c_int,
+    ) -> c_int {
+        unsafe { ffi::opus_decode(st, data, len, pcm, frame_size, decode_fec) }
+    }
+}
+
+#[cfg(not(feature = "disable-float-api"))]
+impl Sample for f32 {
+    const ENCODE: &'static str = "opus_encode_float";
+    const DECODE: &'static str = "opus_decode_float";
+
+    unsafe fn encode(
+        st: *mut ffi::OpusEncoder,
+        pcm: *const f32,
+        frame_size: c_int,
+        data: *mut u8,
+        max_data_bytes: i32,
+    ) -> c_int {
+        unsafe { ffi::opus_encode_float(st, pcm, frame_size, data, max_data_bytes) }
+    }
+
+    unsafe fn decode(
+        st: *mut ffi::OpusDecoder,
+        data: *const u8,
+        len: i32,
+        pcm: *mut f32,
+        frame_size: c_int,
+        decode_fec: c_int,
+    ) -> c_int {
+        unsafe { ffi::opus_decode_float(st, data, len, pcm, frame_size, decode_fec) }
+    }
+}
+
+// ============================================================================
+// Caller-provided State
+
+/// Memory to initialize a codec state in, for [`Encoder::new_in`] and
+/// [`Decoder::new_in`].
+///
+/// `N` must be at least what the codec's `size` reports, which depends on how
+/// libopus was built. Typically this lives in a `static` or a `StaticCell`.
+#[repr(C, align(16))]
+pub struct CodecState<const N: usize>(MaybeUninit<[u8; N]>);
+
+impl<const N: usize> CodecState<N> {
+    /// Uninitialized memory for a codec state.
+    pub const fn new() -> Self {
+        CodecState(MaybeUninit::uninit())
+    }
+
+    /// The memory as `T`, if there is enough of it.
+    fn init<T>(&'static mut self, size: c_int, what: &'static str) -> Result<*mut T> {
+        if size as usize > N {
+            return Err(Error::from_code(what, ffi::OPUS_BUFFER_TOO_SMALL));
+        }
+        Ok(self.0.as_mut_ptr().cast())
+    }
+}
+
+impl<const N: usize> Default for CodecState<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 // ============================================================================
 // Encoder
 
@@ -207,6 +451,11 @@ macro_rules! enc_ctl {
 pub struct Encoder {
     ptr: *mut ffi::OpusEncoder,
     channels: Channels,
+    sample_rate: u32,
+    /// The duration set with `set_expert_frame_duration`.
+    expert_duration: Option<FrameDuration>,
+    /// Whether libopus allocated the state, rather than the caller.
+    owned: bool,
 }
 
 impl Encoder {
@@ -224,17 +473,82 @@ impl Encoder {
         if error != ffi::OPUS_OK || ptr.is_null() {
             Err(Error::from_code("opus_encoder_create", error))
         } else {
-            Ok(Encoder { ptr, channels })
+            Ok(Encoder {
+                ptr,
+                channels,
+                sample_rate,
+                expert_duration: None,
+                owned: true,
+            })
+        }
+    }
+
+    /// Get the size in bytes of the state of an encoder with `channels`.
+    pub fn size(channels: Channels) -> usize {
+        unsafe { ffi::opus_encoder_get_size(channels as c_int) as usize }
+    }
+
+    /// Initialize an encoder in `state` instead of having libopus allocate it.
+    ///
+    /// Fails with `BufferTooSmall` if `state` is smaller than
+    /// [`Encoder::size`].
+    pub fn new_in<const N: usize>(
+        state: &'static mut CodecState<N>,
+        sample_rate: u32,
+        channels: Channels,
+        mode: Application,
+    ) -> Result<Encoder> {
+        let size = unsafe { ffi::opus_encoder_get_size(channels as c_int) };
+        let ptr = state.init(size, "opus_encoder_init")?;
+        ffi!(opus_encoder_init, ptr, sample_rate as i32, channels as c_int, mode as c_int);
+        Ok(Encoder {
+            ptr,
+            channels,
+            sample_rate,
+            expert_duration: None,
+            owned: false,
+        })
+    }
+
+    /// Get the number of samples *per channel* in a frame of `duration` at
+    /// the encoder's sample rate.
+    pub fn frame_size(&self, duration: FrameDuration) -> usize {
+        duration.samples(self.sample_rate)
+    }
+
+    /// Check that `len` interleaved samples make up a frame the encoder
+    /// accepts, returning its size per channel.
+    fn check_frame(&self, len: usize, what: &'static str) -> Result<c_int> {
+        let channels = self.channels as usize;
+        let samples = len / channels;
+        let (legal, rule) = match self.expert_duration {
+            // the encoder codes the first frame and ignores the rest
+            Some(duration) => {
+                (samples >= duration.samples(self.sample_rate), FrameRule::AtLeast(duration))
+            }
+            None => {
+                (FrameDuration::from_samples(samples, self.sample_rate).is_some(), FrameRule::Frame)
+            }
+        };
+        if len.is_multiple_of(channels) && legal {
+            Ok(check_len(samples))
+        } else {
+            let sample_rate = self.sample_rate;
+            Err(Error::bad_frame(what, FrameMismatch { len, channels, sample_rate, rule }))
         }
     }
 
     /// Encode an Opus frame.
+    ///
+    /// `input` must hold exactly one frame of a [`FrameDuration`] at the
+    /// encoder's sample rate, or fails with an error naming the legal sizes.
     pub fn encode(&mut self, input: &[i16], output: &mut [u8]) -> Result<usize> {
+        let frame_size = self.check_frame(input.len(), "opus_encode")?;
         let len = ffi!(
             opus_encode,
             self.ptr,
             input.as_ptr(),
-            len(input) / self.channels as c_int,
+            frame_size,
             output.as_mut_ptr(),
             len(output)
         );
@@ -242,19 +556,59 @@ impl Encoder {
     }
 
     /// Encode an Opus frame from floating point input.
+    #[cfg(not(feature = "disable-float-api"))]
     pub fn encode_float(&mut self, input: &[f32], output: &mut [u8]) -> Result<usize> {
+        let frame_size = self.check_frame(input.len(), "opus_encode_float")?;
         let len = ffi!(
             opus_encode_float,
             self.ptr,
             input.as_ptr(),
-            len(input) / self.channels as c_int,
+            frame_size,
             output.as_mut_ptr(),
             len(output)
         );
         Ok(len as usize)
     }
 
+    /// Encode an Opus frame into uninitialized memory, returning the packet.
+    ///
+    /// `input` must hold one frame, as for [`Encoder::encode`].
+    pub fn encode_uninit<'a, S: Sample>(
+        &mut self,
+        input: &[S],
+        output: &'a mut [MaybeUninit<u8>],
+    ) -> Result<&'a mut [u8]> {
+        let frame_size = self.check_frame(input.len(), S::ENCODE)?;
+        let data = output.as_mut_ptr().cast();
+        let len =
+            match unsafe { S::encode(self.ptr, input.as_ptr(), frame_size, data, len(output)) } {
+                code if code < 0 => return Err(Error::from_code(S::ENCODE, code)),
+                code => code as usize,
+            };
+        // libopus wrote the first `len` bytes
+        Ok(unsafe { slice::from_raw_parts_mut(data, len) })
+    }
+
+    /// Encode an Opus frame into the spare capacity of `output`, advancing it
+    /// past the packet, and return the packet's length.
+    ///
+    /// The packet has to fit in [`BufMut::chunk_mut`], so reserve enough space
+    /// first when `output` is a growable buffer like `BytesMut`.
+    #[cfg(feature = "bytes")]
+    pub fn encode_into<S: Sample, B: BufMut + ?Sized>(
+        &mut self,
+        input: &[S],
+        output: &mut B,
+    ) -> Result<usize> {
+        // libopus only writes initialized bytes into the chunk
+        let chunk = unsafe { output.chunk_mut().as_uninit_slice_mut() };
+        let len = self.encode_uninit(input, chunk)?.len();
+        unsafe { output.advance_mut(len) };
+        Ok(len)
+    }
+
     /// Encode an Opus frame to a new buffer.
+    #[cfg(feature = "alloc")]
     pub fn encode_vec(&mut self, input: &[i16], max_size: usize) -> Result<Vec<u8>> {
         let mut output: Vec<u8> = vec![0; max_size];
         let result = self.encode(input, output.as_mut_slice())?;
@@ -263,6 +617,7 @@ impl Encoder {
     }
 
     /// Encode an Opus frame from floating point input to a new buffer.
+    #[cfg(all(feature = "alloc", not(feature = "disable-float-api")))]
     pub fn encode_vec_float(&mut self, input: &[f32], max_size: usize) -> Result<Vec<u8>> {
         let mut output: Vec<u8> = vec![0; max_size];
         let result = self.encode_float(input, output.as_mut_slice())?;
@@ -433,12 +788,137 @@ impl Encoder {
         }
     }
 
-    // TODO: Encoder-specific CTLs
+    /// Configures the type of signal being encoded.
+    pub fn set_signal(&mut self, value: Signal) -> Result<()> {
+        enc_ctl!(self, ffi::OPUS_SET_SIGNAL_REQUEST, value as i32);
+        Ok(())
+    }
+
+    /// Gets the encoder's configured signal type.
+    pub fn get_signal(&mut self) -> Result<Signal> {
+        let mut value: i32 = 0;
+        enc_ctl!(self, ffi::OPUS_GET_SIGNAL_REQUEST, &mut value);
+        Signal::decode(value, "opus_encoder_ctl(OPUS_GET_SIGNAL)")
+    }
+
+    /// Configures the maximum bandpass that the encoder will select
+    /// automatically. `Auto` is not accepted.
+    pub fn set_max_bandwidth(&mut self, value: Bandwidth) -> Result<()> {
+        enc_ctl!(self, ffi::OPUS_SET_MAX_BANDWIDTH_REQUEST, value as i32);
+        Ok(())
+    }
+
+    /// Gets the encoder's configured maximum allowed bandpass.
+    pub fn get_max_bandwidth(&mut self) -> Result<Bandwidth> {
+        let mut value: i32 = 0;
+        enc_ctl!(self, ffi::OPUS_GET_MAX_BANDWIDTH_REQUEST, &mut value);
+        Bandwidth::decode(value, "opus_encoder_ctl(OPUS_GET_MAX_BANDWIDTH)")
+    }
+
+    /// Configures the encoder's use of variable duration frames.
+    ///
+    /// With a duration set, each call to `encode` codes at most that much of
+    /// the input and ignores the rest. `None` uses the whole input, the
+    /// default.
+    pub fn set_expert_frame_duration(&mut self, value: Option<FrameDuration>) -> Result<()> {
+        let value = match value {
+            None => ffi::OPUS_FRAMESIZE_ARG,
+            Some(duration) => duration as i32,
+        };
+        enc_ctl!(self, ffi::OPUS_SET_EXPERT_FRAME_DURATION_REQUEST, value);
+        self.expert_duration = FrameDuration::from_int(value);
+        Ok(())
+    }
+
+    /// Gets the encoder's configured use of variable duration frames.
+    pub fn get_expert_frame_duration(&mut self) -> Result<Option<FrameDuration>> {
+        let mut value: i32 = 0;
+        enc_ctl!(self, ffi::OPUS_GET_EXPERT_FRAME_DURATION_REQUEST, &mut value);
+        match value {
+            ffi::OPUS_FRAMESIZE_ARG => Ok(None),
+            _ => match FrameDuration::from_int(value) {
+                Some(duration) => Ok(Some(duration)),
+                None => Err(Error::bad_arg("opus_encoder_ctl(OPUS_GET_EXPERT_FRAME_DURATION)")),
+            },
+        }
+    }
+
+    /// If set, disables almost all use of prediction, making frames almost
+    /// completely independent. This reduces quality.
+    pub fn set_prediction_disabled(&mut self, value: bool) -> Result<()> {
+        let value: i32 = if value { 1 } else { 0 };
+        enc_ctl!(self, ffi::OPUS_SET_PREDICTION_DISABLED_REQUEST, value);
+        Ok(())
+    }
+
+    /// Gets the encoder's configured prediction status.
+    pub fn get_prediction_disabled(&mut self) -> Result<bool> {
+        let mut value: i32 = 0;
+        enc_ctl!(self, ffi::OPUS_GET_PREDICTION_DISABLED_REQUEST, &mut value);
+        Ok(value != 0)
+    }
+
+    /// Configures the depth of signal being encoded, between 8 and 24 bits.
+    ///
+    /// This is a hint which helps the encoder identify silence and
+    /// near-silence.
+    pub fn set_lsb_depth(&mut self, value: i32) -> Result<()> {
+        enc_ctl!(self, ffi::OPUS_SET_LSB_DEPTH_REQUEST, value);
+        Ok(())
+    }
+
+    /// Gets the encoder's configured signal depth.
+    pub fn get_lsb_depth(&mut self) -> Result<i32> {
+        let mut value: i32 = 0;
+        enc_ctl!(self, ffi::OPUS_GET_LSB_DEPTH_REQUEST, &mut value);
+        Ok(value)
+    }
+
+    /// Gets whether the last encoded frame was a DTX frame, i.e. silence
+    /// that was not transmitted.
+    pub fn get_in_dtx(&mut self) -> Result<bool> {
+        let mut value: i32 = 0;
+        enc_ctl!(self, ffi::OPUS_GET_IN_DTX_REQUEST, &mut value);
+        Ok(value != 0)
+    }
+
+    /// If set, disables the use of phase inversion for intensity stereo,
+    /// improving the quality of mono downmixes at a slight cost to stereo.
+    pub fn set_phase_inversion_disabled(&mut self, value: bool) -> Result<()> {
+        let value: i32 = if value { 1 } else { 0 };
+        enc_ctl!(self, ffi::OPUS_SET_PHASE_INVERSION_DISABLED_REQUEST, value);
+        Ok(())
+    }
+
+    /// Gets the encoder's configured phase inversion status.
+    pub fn get_phase_inversion_disabled(&mut self) -> Result<bool> {
+        let mut value: i32 = 0;
+        enc_ctl!(self, ffi::OPUS_GET_PHASE_INVERSION_DISABLED_REQUEST, &mut value);
+        Ok(value != 0)
+    }
+
+    /// Configures Deep Redundancy (DRED), as a number of 10ms frames of
+    /// redundancy to add to each packet. Zero, the default, disables it.
+    #[cfg(feature = "dred")]
+    pub fn set_dred_duration(&mut self, frames: i32) -> Result<()> {
+        enc_ctl!(self, ffi::OPUS_SET_DRED_DURATION_REQUEST, frames);
+        Ok(())
+    }
+
+    /// Gets the encoder's configured amount of Deep Redundancy.
+    #[cfg(feature = "dred")]
+    pub fn get_dred_duration(&mut self) -> Result<i32> {
+        let mut value: i32 = 0;
+        enc_ctl!(self, ffi::OPUS_GET_DRED_DURATION_REQUEST, &mut value);
+        Ok(value)
+    }
 }
 
 impl Drop for Encoder {
     fn drop(&mut self) {
-        unsafe { ffi::opus_encoder_destroy(self.ptr) }
+        if self.owned {
+            unsafe { ffi::opus_encoder_destroy(self.ptr) }
+        }
     }
 }
 
@@ -468,6 +948,9 @@ macro_rules! dec_ctl {
 pub struct Decoder {
     ptr: *mut ffi::OpusDecoder,
     channels: Channels,
+    sample_rate: u32,
+    /// Whether libopus allocated the state, rather than the caller.
+    owned: bool,
 }
 
 impl Decoder {
@@ -479,17 +962,72 @@ impl Decoder {
         if error != ffi::OPUS_OK || ptr.is_null() {
             Err(Error::from_code("opus_decoder_create", error))
         } else {
-            Ok(Decoder { ptr, channels })
+            Ok(Decoder { ptr, channels, sample_rate, owned: true })
+        }
+    }
+
+    /// Get the size in bytes of the state of a decoder with `channels`.
+    pub fn size(channels: Channels) -> usize {
+        unsafe { ffi::opus_decoder_get_size(channels as c_int) as usize }
+    }
+
+    /// Initialize a decoder in `state` instead of having libopus allocate it.
+    ///
+    /// Fails with `BufferTooSmall` if `state` is smaller than
+    /// [`Decoder::size`].
+    pub fn new_in<const N: usize>(
+        state: &'static mut CodecState<N>,
+        sample_rate: u32,
+        channels: Channels,
+    ) -> Result<Decoder> {
+        let size = unsafe { ffi::opus_decoder_get_size(channels as c_int) };
+        let ptr = state.init(size, "opus_decoder_init")?;
+        ffi!(opus_decoder_init, ptr, sample_rate as i32, channels as c_int);
+        Ok(Decoder { ptr, channels, sample_rate, owned: false })
+    }
+
+    /// Get the number of samples *per channel* in a frame of `duration` at
+    /// the decoder's sample rate.
+    pub fn frame_size(&self, duration: FrameDuration) -> usize {
+        duration.samples(self.sample_rate)
+    }
+
+    /// Check that `len` interleaved samples are a valid output buffer for
+    /// decoding `input`, returning its size per channel.
+    fn check_output(
+        &self,
+        input: &[u8],
+        len: usize,
+        fec: bool,
+        what: &'static str,
+    ) -> Result<c_int> {
+        let channels = self.channels as usize;
+        let samples = len / channels;
+        // concealed audio is generated in whole 2.5ms steps
+        let step = FrameDuration::Ms2_5.samples(self.sample_rate);
+        let (legal, rule) = if !input.is_empty() && !fec {
+            (true, FrameRule::Interleaved)
+        } else {
+            (samples.is_multiple_of(step), FrameRule::Steps)
+        };
+        if len.is_multiple_of(channels) && legal {
+            Ok(check_len(samples))
+        } else {
+            let sample_rate = self.sample_rate;
+            Err(Error::bad_frame(what, FrameMismatch { len, channels, sample_rate, rule }))
         }
     }
 
     /// Decode an Opus packet.
     ///
-    /// To represent packet loss, pass an empty slice `&[]`.
+    /// To represent packet loss, pass an empty slice `&[]`. The length of
+    /// `output` then sets how much audio to conceal, as it does for FEC, and
+    /// must be a multiple of 2.5ms.
     ///
     /// The return value is the number of samples *per channel* decoded from
     /// the packet.
     pub fn decode(&mut self, input: &[u8], output: &mut [i16], fec: bool) -> Result<usize> {
+        let frame_size = self.check_output(input, output.len(), fec, "opus_decode")?;
         let ptr = match input.len() {
             0 => core::ptr::null(),
             _ => input.as_ptr(),
@@ -500,7 +1038,7 @@ impl Decoder {
             ptr,
             len(input),
             output.as_mut_ptr(),
-            len(output) / self.channels as c_int,
+            frame_size,
             fec as c_int
         );
         Ok(len as usize)
@@ -508,11 +1046,15 @@ impl Decoder {
 
     /// Decode an Opus packet with floating point output.
     ///
-    /// To represent packet loss, pass an empty slice `&[]`.
+    /// To represent packet loss, pass an empty slice `&[]`. The length of
+    /// `output` then sets how much audio to conceal, as it does for FEC, and
+    /// must be a multiple of 2.5ms.
     ///
     /// The return value is the number of samples *per channel* decoded from
     /// the packet.
+    #[cfg(not(feature = "disable-float-api"))]
     pub fn decode_float(&mut self, input: &[u8], output: &mut [f32], fec: bool) -> Result<usize> {
+        let frame_size = self.check_output(input, output.len(), fec, "opus_decode_float")?;
         let ptr = match input.len() {
             0 => core::ptr::null(),
             _ => input.as_ptr(),
@@ -523,12 +1065,44 @@ impl Decoder {
             ptr,
             len(input),
             output.as_mut_ptr(),
-            len(output) / self.channels as c_int,
+            frame_size,
             fec as c_int
         );
         Ok(len as usize)
     }
 
+    /// Decode an Opus packet into `output`, returning the part of it filled.
+    ///
+    /// To represent packet loss, pass an empty slice `&[]`; `output` then
+    /// sets how much audio to conceal, as it does for FEC, in multiples of
+    /// 2.5ms. Otherwise this
+    /// fails with `BufferTooSmall` when the packet holds more audio than fits
+    /// in `output`, which must be a multiple of the channel count.
+    pub fn decode_into<'a, S: Sample>(
+        &mut self,
+        input: &[u8],
+        output: &'a mut [S],
+        fec: bool,
+    ) -> Result<&'a mut [S]> {
+        let channels = self.channels as usize;
+        let frame_size = self.check_output(input, output.len(), fec, S::DECODE)?;
+        if !input.is_empty() && !fec && self.get_nb_samples(input)? * channels > output.len() {
+            return Err(Error::from_code(S::DECODE, ffi::OPUS_BUFFER_TOO_SMALL));
+        }
+
+        let ptr = match input.len() {
+            0 => core::ptr::null(),
+            _ => input.as_ptr(),
+        };
+        let decoded = unsafe {
+            S::decode(self.ptr, ptr, len(input), output.as_mut_ptr(), frame_size, fec as c_int)
+        };
+        match decoded {
+            code if code < 0 => Err(Error::from_code(S::DECODE, code)),
+            code => Ok(&mut output[..code as usize * channels]),
+        }
+    }
+
     /// Get the number of samples *per channel* of an Opus packet.
     pub fn get_nb_samples(&self, packet: &[u8]) -> Result<usize> {
         let len = ffi!(opus_decoder_get_nb_samples, self.ptr, packet.as_ptr(), packet.len() as i32);
@@ -607,17 +1181,179 @@ impl Decoder {
         dec_ctl!(self, ffi::OPUS_GET_PITCH_REQUEST, &mut value);
         Ok(value)
     }
+
+    /// Configures the decoder's computational complexity, between 0 and 10.
+    ///
+    /// This is what enables the neural decoder features built in: deep PLC
+    /// from 5, and OSCE speech enhancement from 6 (LACE) or 7 (NoLACE).
+    #[cfg(any(feature = "deep-plc", feature = "osce"))]
+    pub fn set_complexity(&mut self, value: i32) -> Result<()> {
+        dec_ctl!(self, ffi::OPUS_SET_COMPLEXITY_REQUEST, value);
+        Ok(())
+    }
+
+    /// Gets the decoder's complexity configuration.
+    #[cfg(any(feature = "deep-plc", feature = "osce"))]
+    pub fn get_complexity(&mut self) -> Result<i32> {
+        let mut value: i32 = 0;
+        dec_ctl!(self, ffi::OPUS_GET_COMPLEXITY_REQUEST, &mut value);
+        Ok(value)
+    }
+
+    // ----
+    // DRED
+
+    /// Decode audio for lost packets from the redundancy in `dred`.
+    ///
+    /// `offset` is how many samples before the start of the packet `dred` was
+    /// parsed from the audio starts, and `output` holds the number of samples
+    /// to decode, which must be a multiple of 2.5ms. Fails with `BadArg` if
+    /// `offset` is beyond what libopus can take.
+    #[cfg(feature = "dred")]
+    pub fn decode_dred(&mut self, dred: &Dred, offset: usize, output: &mut [i16]) -> Result<usize> {
+        let offset = try_len(offset, "opus_decoder_dred_decode")?;
+        let len = ffi!(
+            opus_decoder_dred_decode,
+            self.ptr,
+            dred.ptr,
+            offset,
+            output.as_mut_ptr(),
+            len(output) / self.channels as c_int
+        );
+        Ok(len as usize)
+    }
+
+    /// Decode audio for lost packets from the redundancy in `dred`, with
+    /// floating point output. See [`Decoder::decode_dred`].
+    #[cfg(all(feature = "dred", not(feature = "disable-float-api")))]
+    pub fn decode_dred_float(
+        &mut self,
+        dred: &Dred,
+        offset: usize,
+        output: &mut [f32],
+    ) -> Result<usize> {
+        let offset = try_len(offset, "opus_decoder_dred_decode_float")?;
+        let len = ffi!(
+            opus_decoder_dred_decode_float,
+            self.ptr,
+            dred.ptr,
+            offset,
+            output.as_mut_ptr(),
+            len(output) / self.channels as c_int
+        );
+        Ok(len as usize)
+    }
 }
 
 impl Drop for Decoder {
     fn drop(&mut self) {
-        unsafe { ffi::opus_decoder_destroy(self.ptr) }
+        if self.owned {
+            unsafe { ffi::opus_decoder_destroy(self.ptr) }
+        }
     }
 }
 
 // See `unsafe impl Send for Encoder`.
 unsafe impl Send for Decoder {}
 
+// ============================================================================
+// Deep Redundancy
+
+/// Extracts the Deep Redundancy (DRED) data carried in packets, to recover
+/// from the loss of the packets before them.
+#[cfg(feature = "dred")]
+#[derive(Debug)]
+pub struct DredDecoder {
+    ptr: *mut ffi::OpusDREDDecoder,
+}
+
+#[cfg(feature = "dred")]
+impl DredDecoder {
+    /// Create and initialize a DRED decoder.
+    pub fn new() -> Result<DredDecoder> {
+        let mut error = 0;
+        let ptr = unsafe { ffi::opus_dred_decoder_create(&mut error) };
+        if error != ffi::OPUS_OK || ptr.is_null() {
+            Err(Error::from_code("opus_dred_decoder_create", error))
+        } else {
+            Ok(DredDecoder { ptr })
+        }
+    }
+
+    /// Parse the redundancy in `packet` into `dred`, looking at most
+    /// `max_samples` at `sample_rate` back from its start.
+    ///
+    /// Returns how many samples before the packet the redundancy covers, zero
+    /// if there is none, and how many of those at its far end are silence.
+    /// Fails with `BadArg` if `max_samples` is beyond what libopus can take.
+    pub fn parse(
+        &mut self,
+        dred: &mut Dred,
+        packet: &[u8],
+        max_samples: usize,
+        sample_rate: u32,
+    ) -> Result<(usize, usize)> {
+        let max_samples = try_len(max_samples, "opus_dred_parse")?;
+        let mut end = 0;
+        let available = ffi!(
+            opus_dred_parse,
+            self.ptr,
+            dred.ptr,
+            packet.as_ptr(),
+            len(packet),
+            max_samples,
+            sample_rate as i32,
+            &mut end,
+            0
+        );
+        Ok((available as usize, end as usize))
+    }
+}
+
+#[cfg(feature = "dred")]
+impl Drop for DredDecoder {
+    fn drop(&mut self) {
+        unsafe { ffi::opus_dred_decoder_destroy(self.ptr) }
+    }
+}
+
+// See `unsafe impl Send for Encoder`.
+#[cfg(feature = "dred")]
+unsafe impl Send for DredDecoder {}
+
+/// The Deep Redundancy parsed from a packet by [`DredDecoder::parse`], to be
+/// decoded with [`Decoder::decode_dred`].
+#[cfg(feature = "dred")]
+#[derive(Debug)]
+pub struct Dred {
+    ptr: *mut ffi::OpusDRED,
+}
+
+#[cfg(feature = "dred")]
+impl Dred {
+    /// Allocate an empty DRED state.
+    pub fn new() -> Result<Dred> {
+        let mut error = 0;
+        let ptr = unsafe { ffi::opus_dred_alloc(&mut error) };
+        if error != ffi::OPUS_OK || ptr.is_null() {
+            Err(Error::from_code("opus_dred_alloc", error))
+        } else {
+            Ok(Dred { ptr })
+        }
+    }
+}
+
+#[cfg(feature = "dred")]
+impl Drop for Dred {
+    fn drop(&mut self) {
+        unsafe { ffi::opus_dred_free(self.ptr) }
+    }
+}
+
+// See `unsafe impl Send for Encoder`.
+#[cfg(feature = "dred")]
+unsafe impl Send for Dred {}
+
 // ============================================================================
 // Packet Analysis
 
@@ -625,6 +1361,7 @@ unsafe impl Send for Decoder {}
 pub mod packet {
     use super::ffi;
     use super::*;
+    #[cfg(feature = "alloc")]
     use core::{ptr, slice};
 
     /// Get the bandwidth of an Opus packet.
@@ -673,6 +1410,7 @@ pub mod packet {
     }
 
     /// Parse an Opus packet into one or more frames.
+    #[cfg(feature = "alloc")]
     pub fn parse(packet: &[u8]) -> Result<Packet> {
         let mut toc: u8 = 0;
         let mut frames = [ptr::null(); 48];
@@ -701,6 +1439,7 @@ pub mod packet {
     }
 
     /// A parsed Opus packet, retuned from `parse`.
+    #[cfg(feature = "alloc")]
     #[derive(Debug)]
     pub struct Packet<'a> {
         /// The TOC byte of the packet.
@@ -711,41 +1450,258 @@ pub mod packet {
         pub payload_offset: usize,
     }
 
-    /// Pad a given Opus packet to a larger size.
-    ///
-    /// The packet will be extended from the first `prev_len` bytes of the
-    /// buffer into the rest of the available space.
-    pub fn pad(packet: &mut [u8], prev_len: usize) -> Result<usize> {
-        let result = ffi!(opus_packet_pad, packet.as_mut_ptr(), check_len(prev_len), len(packet));
-        Ok(result as usize)
+    /// The coding mode of an Opus packet.
+    #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+    pub enum Mode {
+        /// Linear prediction coding, for speech.
+        Silk,
+        /// SILK for the low band and CELT above it.
+        Hybrid,
+        /// Transform coding, for music and low delay.
+        Celt,
     }
 
-    /// Remove all padding from a given Opus packet and rewrite the TOC sequence
-    /// to minimize space usage.
-    pub fn unpad(packet: &mut [u8]) -> Result<usize> {
-        let result = ffi!(opus_packet_unpad, packet.as_mut_ptr(), len(packet));
-        Ok(result as usize)
-    }
-}
+    /// The table-of-contents byte that starts every Opus packet, as described
+    /// in RFC 6716 section 3.1.
+    #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+    pub struct Toc(pub u8);
 
-// ============================================================================
-// Float Soft Clipping
+    impl Toc {
+        /// Get the configuration number, from 0 to 31.
+        pub fn config(self) -> u8 {
+            self.0 >> 3
+        }
 
-/// Soft-clipping to bring a float signal within the [-1,1] range.
-#[derive(Debug)]
-pub struct SoftClip {
-    channels: Channels,
-    memory: [f32; 2],
-}
+        /// Get the coding mode of the packet.
+        pub fn mode(self) -> Mode {
+            match self.config() {
+                0..=11 => Mode::Silk,
+                12..=15 => Mode::Hybrid,
+                _ => Mode::Celt,
+            }
+        }
 
-impl SoftClip {
-    /// Initialize a new soft-clipping state.
-    pub fn new(channels: Channels) -> SoftClip {
-        SoftClip { channels, memory: [0.0; 2] }
-    }
+        /// Get the bandwidth of the packet.
+        pub fn bandwidth(self) -> Bandwidth {
+            match self.config() {
+                0..=3 | 16..=19 => Bandwidth::Narrowband,
+                4..=7 => Bandwidth::Mediumband,
+                8..=11 | 20..=23 => Bandwidth::Wideband,
+                12 | 13 | 24..=27 => Bandwidth::Superwideband,
+                _ => Bandwidth::Fullband,
+            }
+        }
 
-    /// Apply soft-clipping to a float signal.
-    pub fn apply(&mut self, signal: &mut [f32]) {
+        /// Get the duration of each frame in the packet.
+        pub fn frame_duration(self) -> FrameDuration {
+            use FrameDuration::*;
+            let config = self.config() as usize;
+            match self.mode() {
+                Mode::Silk => [Ms10, Ms20, Ms40, Ms60][config % 4],
+                Mode::Hybrid => [Ms10, Ms20][config % 2],
+                Mode::Celt => [Ms2_5, Ms5, Ms10, Ms20][config % 4],
+            }
+        }
+
+        /// Get the number of channels of the packet.
+        pub fn channels(self) -> Channels {
+            match self.0 & 0x4 {
+                0 => Channels::Mono,
+                _ => Channels::Stereo,
+            }
+        }
+
+        /// Get the frame count code: 0 for one frame, 1 for two frames of
+        /// equal size, 2 for two frames of different sizes and 3 for any
+        /// number of frames.
+        pub fn code(self) -> u8 {
+            self.0 & 0x3
+        }
+    }
+
+    /// An Opus packet split into frames without calling into libopus,
+    /// returned from `inspect`.
+    #[derive(Debug, Clone)]
+    pub struct PacketInfo<'a> {
+        /// The TOC byte of the packet.
+        pub toc: Toc,
+        /// Whether each frame's size is coded, rather than all frames
+        /// sharing the same size.
+        pub vbr: bool,
+        /// The number of padding bytes at the end of the packet.
+        pub padding: usize,
+        /// The offset into the packet at which the payload is located.
+        pub payload_offset: usize,
+        payload: &'a [u8],
+        sizes: [u16; 48],
+        count: usize,
+    }
+
+    impl<'a> PacketInfo<'a> {
+        /// Get the number of frames in the packet.
+        pub fn nb_frames(&self) -> usize {
+            self.count
+        }
+
+        /// Get the number of samples *per channel* in the packet.
+        pub fn nb_samples(&self, sample_rate: u32) -> usize {
+            self.count * self.toc.frame_duration().samples(sample_rate)
+        }
+
+        /// Get the frames contained in the packet.
+        pub fn frames(&self) -> impl Iterator<Item = &'a [u8]> + use<'a> {
+            let mut payload = self.payload;
+            self.sizes.into_iter().take(self.count).map(move |size| {
+                let (frame, rest) = payload.split_at(size as usize);
+                payload = rest;
+                frame
+            })
+        }
+    }
+
+    /// Parse an Opus packet into its frames as RFC 6716 section 3 describes,
+    /// without calling into libopus.
+    ///
+    /// This accepts and rejects the same packets as `parse`.
+    pub fn inspect(packet: &[u8]) -> Result<PacketInfo<'_>> {
+        let invalid = || Error::from_code("packet::inspect", ffi::OPUS_INVALID_PACKET);
+        let (&toc, mut data) = packet.split_first().ok_or_else(invalid)?;
+        let toc = Toc(toc);
+
+        let mut padding = 0;
+        let (count, vbr) = match toc.code() {
+            0 => (1, false),
+            1 => (2, false),
+            2 => (2, true),
+            _ => {
+                let (&header, rest) = data.split_first().ok_or_else(invalid)?;
+                data = rest;
+                let count = (header & 0x3F) as usize;
+                let samples = toc.frame_duration().samples(48000);
+                if count == 0 || count * samples > MAX_PACKET_SAMPLES {
+                    return Err(invalid());
+                }
+                if header & 0x40 != 0 {
+                    // a length of 255 means 254 bytes and another length
+                    loop {
+                        let (&len, rest) = data.split_first().ok_or_else(invalid)?;
+                        data = rest;
+                        padding += len.min(254) as usize;
+                        if len < 255 {
+                            break;
+                        }
+                    }
+                }
+                (count, header & 0x80 != 0)
+            }
+        };
+        let data_len = data.len().checked_sub(padding).ok_or_else(invalid)?;
+        let mut data = &data[..data_len];
+
+        let mut sizes = [0; 48];
+        let last = if vbr {
+            let mut total = 0;
+            for size in &mut sizes[..count - 1] {
+                let (len, bytes) = match *data {
+                    [b0 @ 0..=251, ..] => (b0 as usize, 1),
+                    [b0, b1, ..] => (b0 as usize + 4 * b1 as usize, 2),
+                    _ => return Err(invalid()),
+                };
+                data = &data[bytes..];
+                *size = len as u16;
+                total += len;
+            }
+            data.len().checked_sub(total).ok_or_else(invalid)?
+        } else {
+            if !data.len().is_multiple_of(count) {
+                return Err(invalid());
+            }
+            let len = data.len() / count;
+            sizes[..count - 1].fill(len as u16);
+            len
+        };
+        // the last size is implied, so may be more than a frame can hold
+        if last > 1275 {
+            return Err(invalid());
+        }
+        sizes[count - 1] = last as u16;
+
+        Ok(PacketInfo {
+            toc,
+            vbr,
+            padding,
+            payload_offset: packet.len() - padding - data.len(),
+            payload: data,
+            sizes,
+            count,
+        })
+    }
+
+    /// Pad a given Opus packet to a larger size.
+    ///
+    /// The packet will be extended from the first `prev_len` bytes of the
+    /// buffer into the rest of the available space.
+    pub fn pad(packet: &mut [u8], prev_len: usize) -> Result<usize> {
+        let result = ffi!(opus_packet_pad, packet.as_mut_ptr(), check_len(prev_len), len(packet));
+        Ok(result as usize)
+    }
+
+    /// Remove all padding from a given Opus packet and rewrite the TOC sequence
+    /// to minimize space usage.
+    pub fn unpad(packet: &mut [u8]) -> Result<usize> {
+        let result = ffi!(opus_packet_unpad, packet.as_mut_ptr(), len(packet));
+        Ok(result as usize)
+    }
+
+    /// Pad a given multistream Opus packet of `nb_streams` streams to a larger
+    /// size.
+    ///
+    /// Only the last stream is padded. As with `pad`, the packet is extended
+    /// from the first `prev_len` bytes of the buffer into the rest of it.
+    pub fn multistream_pad(packet: &mut [u8], prev_len: usize, nb_streams: u8) -> Result<usize> {
+        let result = ffi!(
+            opus_multistream_packet_pad,
+            packet.as_mut_ptr(),
+            check_len(prev_len),
+            len(packet),
+            nb_streams as c_int
+        );
+        Ok(result as usize)
+    }
+
+    /// Remove all padding from a given multistream Opus packet of `nb_streams`
+    /// streams, returning its new length.
+    pub fn multistream_unpad(packet: &mut [u8], nb_streams: u8) -> Result<usize> {
+        let result = ffi!(
+            opus_multistream_packet_unpad,
+            packet.as_mut_ptr(),
+            len(packet),
+            nb_streams as c_int
+        );
+        Ok(result as usize)
+    }
+}
+
+// ============================================================================
+// Float Soft Clipping
+
+/// Soft-clipping to bring a float signal within the [-1,1] range.
+#[cfg(not(feature = "disable-float-api"))]
+#[derive(Debug)]
+pub struct SoftClip {
+    channels: Channels,
+    memory: [f32; 2],
+}
+
+#[cfg(not(feature = "disable-float-api"))]
+impl SoftClip {
+    /// Initialize a new soft-clipping state.
+    pub fn new(channels: Channels) -> SoftClip {
+        SoftClip { channels, memory: [0.0; 2] }
+    }
+
+    /// Apply soft-clipping to a float signal.
+    pub fn apply(&mut self, signal: &mut [f32]) {
         unsafe {
             ffi::opus_pcm_soft_clip(
                 signal.as_mut_ptr(),
@@ -853,8 +1809,11 @@ impl<'rp, 'buf> RepacketizerState<'rp, 'buf> {
     /// Construct a new packet from data previously submitted via `cat`, with
     /// a manually specified subrange.
     ///
-    /// The `end` index should not exceed the value of `get_nb_frames()`.
+    /// Fails with `BadArg` unless `begin < end <= get_nb_frames()`.
     pub fn out_range(&mut self, begin: usize, end: usize, buffer: &mut [u8]) -> Result<usize> {
+        if begin >= end || end > self.get_nb_frames() {
+            return Err(Error::bad_arg("opus_repacketizer_out_range"));
+        }
         let result = ffi!(
             opus_repacketizer_out_range,
             self.rp.ptr,
@@ -867,8 +1826,634 @@ impl<'rp, 'buf> RepacketizerState<'rp, 'buf> {
     }
 }
 
+/// The most frames an Opus packet may hold.
+#[cfg(feature = "alloc")]
+const MAX_FRAMES: usize = 48;
+
+/// The most audio an Opus packet may hold, in samples at 48kHz.
+const MAX_PACKET_SAMPLES: usize = 5760;
+
+/// Combines Opus packets into multi-frame packets, to save the per-packet
+/// overhead of the transport.
+///
+/// Frames only share a packet when they have the same mode, bandwidth, frame
+/// size and channel count, so a change in any of these ends a packet early.
+#[cfg(feature = "alloc")]
+#[derive(Debug)]
+pub struct PacketAggregator {
+    rp: Repacketizer,
+    frames: usize,
+    /// The packets queued, back to back.
+    data: Vec<u8>,
+    /// The end of each queued packet in `data`, and its frame count.
+    packets: Vec<(usize, usize)>,
+}
+
+#[cfg(feature = "alloc")]
+impl PacketAggregator {
+    /// Create an aggregator combining `frames` frames per packet.
+    ///
+    /// Fails with `BadArg` unless `frames` is between 1 and 48, the most a
+    /// packet may hold.
+    pub fn new(frames: usize) -> Result<PacketAggregator> {
+        if !(1..=MAX_FRAMES).contains(&frames) {
+            return Err(Error::bad_arg("PacketAggregator::new"));
+        }
+        Ok(PacketAggregator {
+            rp: Repacketizer::new()?,
+            frames,
+            data: Vec::new(),
+            packets: Vec::new(),
+        })
+    }
+
+    /// Get the number of frames combined per packet.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Get the number of frames queued and not yet written out.
+    pub fn pending(&self) -> usize {
+        self.packets.iter().map(|&(_, frames)| frames).sum()
+    }
+
+    /// Queue a packet to be combined with the ones that follow it.
+    pub fn push(&mut self, packet: &[u8]) -> Result<()> {
+        // reject malformed packets now, or they would block the queue
+        let frames = packet::inspect(packet)?.nb_frames();
+        self.data.extend_from_slice(packet);
+        self.packets.push((self.data.len(), frames));
+        Ok(())
+    }
+
+    /// Write the next combined packet into `output`, returning its length,
+    /// or `None` if more frames are needed to fill it.
+    ///
+    /// Call this until it returns `None` after each `push`, as a change of
+    /// configuration can leave more than one packet ready.
+    pub fn pop(&mut self, output: &mut [u8]) -> Result<Option<usize>> {
+        self.out(output, false)
+    }
+
+    /// Write the next packet into `output` even if it holds fewer frames
+    /// than asked for, returning its length, or `None` once the queue is
+    /// empty.
+    pub fn flush(&mut self, output: &mut [u8]) -> Result<Option<usize>> {
+        self.out(output, true)
+    }
+
+    fn out(&mut self, output: &mut [u8], partial: bool) -> Result<Option<usize>> {
+        let Some(&(first_end, _)) = self.packets.first() else {
+            return Ok(None);
+        };
+        let toc = self.data[0] & 0xFC;
+        let frame_samples = packet::get_samples_per_frame(&self.data[..first_end], 48000)?;
+
+        // take packets while they fit alongside the first
+        let (mut count, mut frames, mut start) = (0, 0, 0);
+        for &(end, n) in &self.packets {
+            let fits = frames + n <= self.frames
+                && (frames + n) * frame_samples <= MAX_PACKET_SAMPLES
+                && self.data[start] & 0xFC == toc;
+            if count > 0 && !fits {
+                break;
+            }
+            count += 1;
+            frames += n;
+            start = end;
+        }
+        let full = frames >= self.frames || frames * frame_samples >= MAX_PACKET_SAMPLES;
+        if !partial && !full && count == self.packets.len() {
+            return Ok(None);
+        }
+
+        let mut state = self.rp.begin();
+        let mut start = 0;
+        for &(end, _) in &self.packets[..count] {
+            state.cat(&self.data[start..end])?;
+            start = end;
+        }
+        let len = state.out(output)?;
+        self.data.drain(..start);
+        self.packets.drain(..count);
+        for (end, _) in &mut self.packets {
+            *end -= start;
+        }
+        Ok(Some(len))
+    }
+}
+
+/// Splits multi-frame Opus packets into packets of a single frame, so that
+/// each can be buffered and concealed on its own.
+#[derive(Debug)]
+pub struct PacketSplitter {
+    rp: Repacketizer,
+}
+
+impl PacketSplitter {
+    /// Create and initialize a splitter.
+    pub fn new() -> Result<PacketSplitter> {
+        Ok(PacketSplitter { rp: Repacketizer::new()? })
+    }
+
+    /// Begin splitting `packet` into its frames.
+    pub fn split<'rp, 'buf>(&'rp mut self, packet: &'buf [u8]) -> Result<Split<'rp, 'buf>> {
+        let mut state = self.rp.begin();
+        state.cat(packet)?;
+        let frames = state.get_nb_frames();
+        Ok(Split { state, next: 0, frames })
+    }
+}
+
+/// The frames of a packet being split by a [`PacketSplitter`].
+#[derive(Debug)]
+pub struct Split<'rp, 'buf> {
+    state: RepacketizerState<'rp, 'buf>,
+    next: usize,
+    frames: usize,
+}
+
+impl Split<'_, '_> {
+    /// Get the number of frames not yet written out.
+    pub fn remaining(&self) -> usize {
+        self.frames - self.next
+    }
+
+    /// Write the next frame into `output` as a packet of its own, returning
+    /// its length, or `None` once every frame has been written.
+    ///
+    /// A frame never takes more space than the packet it came from.
+    pub fn next_into(&mut self, output: &mut [u8]) -> Result<Option<usize>> {
+        if self.next == self.frames {
+            return Ok(None);
+        }
+        let len = self.state.out_range(self.next, self.next + 1, output)?;
+        self.next += 1;
+        Ok(Some(len))
+    }
+}
+
 // ============================================================================
-// TODO: Multistream API
+// Multistream Encoder
+
+macro_rules! ms_enc_ctl {
+	($this:ident, $ctl:path $(, $rest:expr)*) => {
+		ctl!(opus_multistream_encoder_ctl, $this, $ctl, $($rest),*)
+	}
+}
+
+/// The stream layout chosen by [`MSEncoder::new_surround`].
+///
+/// A decoder needs the same layout to make sense of the packets, see
+/// [`MSDecoder::new`].
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamLayout {
+    /// The total number of streams.
+    pub streams: u8,
+    /// How many of the streams are coupled (stereo) streams.
+    pub coupled_streams: u8,
+    /// The coded channel for each input channel, 255 for silence.
+    pub mapping: Vec<u8>,
+}
+
+/// An Opus multistream encoder with associated state.
+#[derive(Debug)]
+pub struct MSEncoder {
+    ptr: *mut ffi::OpusMSEncoder,
+    channels: c_int,
+}
+
+impl MSEncoder {
+    /// Create and initialize a multistream encoder.
+    ///
+    /// There is one input channel per entry of `mapping`, each giving the
+    /// coded channel it is encoded into. The first `2 * coupled_streams`
+    /// coded channels are the coupled stream pairs, the rest are mono streams.
+    pub fn new(
+        sample_rate: u32,
+        streams: u8,
+        coupled_streams: u8,
+        mapping: &[u8],
+        mode: Application,
+    ) -> Result<MSEncoder> {
+        let channels = mapping_len(mapping, "opus_multistream_encoder_create")?;
+        let mut error = 0;
+        let ptr = unsafe {
+            ffi::opus_multistream_encoder_create(
+                sample_rate as i32,
+                channels,
+                streams as c_int,
+                coupled_streams as c_int,
+                mapping.as_ptr(),
+                mode as c_int,
+                &mut error,
+            )
+        };
+        if error != ffi::OPUS_OK || ptr.is_null() {
+            Err(Error::from_code("opus_multistream_encoder_create", error))
+        } else {
+            Ok(MSEncoder { ptr, channels })
+        }
+    }
+
+    /// Create and initialize a multistream encoder for a standard channel
+    /// layout, letting libopus pick the streams and mapping.
+    ///
+    /// `mapping_family` is the Ogg Opus channel mapping family: 0 for mono or
+    /// stereo, 1 for the Vorbis surround layouts of up to 8 channels, 2 for
+    /// ambisonics and 255 for unrelated channels.
+    #[cfg(feature = "alloc")]
+    pub fn new_surround(
+        sample_rate: u32,
+        channels: u8,
+        mapping_family: u8,
+        mode: Application,
+    ) -> Result<(MSEncoder, StreamLayout)> {
+        let mut error = 0;
+        let mut streams = 0;
+        let mut coupled_streams = 0;
+        let mut mapping = vec![0; channels as usize];
+        let ptr = unsafe {
+            ffi::opus_multistream_surround_encoder_create(
+                sample_rate as i32,
+                channels as c_int,
+                mapping_family as c_int,
+                &mut streams,
+                &mut coupled_streams,
+                mapping.as_mut_ptr(),
+                mode as c_int,
+                &mut error,
+            )
+        };
+        if error != ffi::OPUS_OK || ptr.is_null() {
+            return Err(Error::from_code("opus_multistream_surround_encoder_create", error));
+        }
+        let encoder = MSEncoder { ptr, channels: channels as c_int };
+        let layout = StreamLayout {
+            streams: streams as u8,
+            coupled_streams: coupled_streams as u8,
+            mapping,
+        };
+        Ok((encoder, layout))
+    }
+
+    /// Encode an Opus frame.
+    pub fn encode(&mut self, input: &[i16], output: &mut [u8]) -> Result<usize> {
+        let len = ffi!(
+            opus_multistream_encode,
+            self.ptr,
+            input.as_ptr(),
+            len(input) / self.channels,
+            output.as_mut_ptr(),
+            len(output)
+        );
+        Ok(len as usize)
+    }
+
+    /// Encode an Opus frame from floating point input.
+    #[cfg(not(feature = "disable-float-api"))]
+    pub fn encode_float(&mut self, input: &[f32], output: &mut [u8]) -> Result<usize> {
+        let len = ffi!(
+            opus_multistream_encode_float,
+            self.ptr,
+            input.as_ptr(),
+            len(input) / self.channels,
+            output.as_mut_ptr(),
+            len(output)
+        );
+        Ok(len as usize)
+    }
+
+    /// Encode an Opus frame to a new buffer.
+    #[cfg(feature = "alloc")]
+    pub fn encode_vec(&mut self, input: &[i16], max_size: usize) -> Result<Vec<u8>> {
+        let mut output: Vec<u8> = vec![0; max_size];
+        let result = self.encode(input, output.as_mut_slice())?;
+        output.truncate(result);
+        Ok(output)
+    }
+
+    /// Encode an Opus frame from floating point input to a new buffer.
+    #[cfg(all(feature = "alloc", not(feature = "disable-float-api")))]
+    pub fn encode_vec_float(&mut self, input: &[f32], max_size: usize) -> Result<Vec<u8>> {
+        let mut output: Vec<u8> = vec![0; max_size];
+        let result = self.encode_float(input, output.as_mut_slice())?;
+        output.truncate(result);
+        Ok(output)
+    }
+
+    // ------------
+    // Generic CTLs
+
+    /// Reset the codec state to be equivalent to a freshly initialized state.
+    pub fn reset_state(&mut self) -> Result<()> {
+        ms_enc_ctl!(self, ffi::OPUS_RESET_STATE);
+        Ok(())
+    }
+
+    /// Get the final range of the codec's entropy coder.
+    pub fn get_final_range(&mut self) -> Result<u32> {
+        let mut value: u32 = 0;
+        ms_enc_ctl!(self, ffi::OPUS_GET_FINAL_RANGE_REQUEST, &mut value);
+        Ok(value)
+    }
+
+    /// Get the encoder's configured bandpass.
+    pub fn get_bandwidth(&mut self) -> Result<Bandwidth> {
+        let mut value: i32 = 0;
+        ms_enc_ctl!(self, ffi::OPUS_GET_BANDWIDTH_REQUEST, &mut value);
+        Bandwidth::decode(value, "opus_multistream_encoder_ctl(OPUS_GET_BANDWIDTH)")
+    }
+
+    /// Get the samping rate the encoder was intialized with.
+    pub fn get_sample_rate(&mut self) -> Result<u32> {
+        let mut value: i32 = 0;
+        ms_enc_ctl!(self, ffi::OPUS_GET_SAMPLE_RATE_REQUEST, &mut value);
+        Ok(value as u32)
+    }
+
+    // ------------
+    // Encoder CTLs
+
+    /// Set the encoder's total bitrate, shared out between the streams.
+    pub fn set_bitrate(&mut self, value: Bitrate) -> Result<()> {
+        ms_enc_ctl!(self, ffi::OPUS_SET_BITRATE_REQUEST, value.raw());
+        Ok(())
+    }
+
+    /// Get the encoder's total bitrate.
+    pub fn get_bitrate(&mut self) -> Result<Bitrate> {
+        let mut value: i32 = 0;
+        ms_enc_ctl!(self, ffi::OPUS_GET_BITRATE_REQUEST, &mut value);
+        Bitrate::from_raw(value)
+    }
+
+    /// Enable or disable variable bitrate.
+    pub fn set_vbr(&mut self, vbr: bool) -> Result<()> {
+        let value: i32 = if vbr { 1 } else { 0 };
+        ms_enc_ctl!(self, ffi::OPUS_SET_VBR_REQUEST, value);
+        Ok(())
+    }
+
+    /// Determine if variable bitrate is enabled.
+    pub fn get_vbr(&mut self) -> Result<bool> {
+        let mut value: i32 = 0;
+        ms_enc_ctl!(self, ffi::OPUS_GET_VBR_REQUEST, &mut value);
+        Ok(value != 0)
+    }
+
+    /// Enable or disable constrained VBR.
+    pub fn set_vbr_constraint(&mut self, vbr: bool) -> Result<()> {
+        let value: i32 = if vbr { 1 } else { 0 };
+        ms_enc_ctl!(self, ffi::OPUS_SET_VBR_CONSTRAINT_REQUEST, value);
+        Ok(())
+    }
+
+    /// Determine if constrained VBR is enabled.
+    pub fn get_vbr_constraint(&mut self) -> Result<bool> {
+        let mut value: i32 = 0;
+        ms_enc_ctl!(self, ffi::OPUS_GET_VBR_CONSTRAINT_REQUEST, &mut value);
+        Ok(value != 0)
+    }
+
+    /// Configures the encoder's use of inband forward error correction (FEC).
+    pub fn set_inband_fec(&mut self, value: bool) -> Result<()> {
+        let value: i32 = if value { 1 } else { 0 };
+        ms_enc_ctl!(self, ffi::OPUS_SET_INBAND_FEC_REQUEST, value);
+        Ok(())
+    }
+
+    /// Gets encoder's configured use of inband forward error correction.
+    pub fn get_inband_fec(&mut self) -> Result<bool> {
+        let mut value: i32 = 0;
+        ms_enc_ctl!(self, ffi::OPUS_GET_INBAND_FEC_REQUEST, &mut value);
+        Ok(value != 0)
+    }
+
+    /// Configures the encoder's use of discontinuous transmission (DTX).
+    pub fn set_dtx(&mut self, value: bool) -> Result<()> {
+        let value: i32 = if value { 1 } else { 0 };
+        ms_enc_ctl!(self, ffi::OPUS_SET_DTX_REQUEST, value);
+        Ok(())
+    }
+
+    /// Gets encoder's configured use of discontinuous transmission (DTX).
+    pub fn get_dtx(&mut self) -> Result<bool> {
+        let mut value: i32 = 0;
+        ms_enc_ctl!(self, ffi::OPUS_GET_DTX_REQUEST, &mut value);
+        Ok(value != 0)
+    }
+
+    /// Configures the encoder's computational complexity.
+    pub fn set_complexity(&mut self, value: i32) -> Result<()> {
+        ms_enc_ctl!(self, ffi::OPUS_SET_COMPLEXITY_REQUEST, value);
+        Ok(())
+    }
+
+    /// Gets the encoder's complexity configuration.
+    pub fn get_complexity(&mut self) -> Result<i32> {
+        let mut value: i32 = 0;
+        ms_enc_ctl!(self, ffi::OPUS_GET_COMPLEXITY_REQUEST, &mut value);
+        Ok(value)
+    }
+
+    /// Sets the encoder's expected packet loss percentage.
+    pub fn set_packet_loss_perc(&mut self, value: i32) -> Result<()> {
+        ms_enc_ctl!(self, ffi::OPUS_SET_PACKET_LOSS_PERC_REQUEST, value);
+        Ok(())
+    }
+
+    /// Gets the encoder's expected packet loss percentage.
+    pub fn get_packet_loss_perc(&mut self) -> Result<i32> {
+        let mut value: i32 = 0;
+        ms_enc_ctl!(self, ffi::OPUS_GET_PACKET_LOSS_PERC_REQUEST, &mut value);
+        Ok(value)
+    }
+
+    /// Gets the total samples of delay added by the entire codec.
+    pub fn get_lookahead(&mut self) -> Result<i32> {
+        let mut value: i32 = 0;
+        ms_enc_ctl!(self, ffi::OPUS_GET_LOOKAHEAD_REQUEST, &mut value);
+        Ok(value)
+    }
+}
+
+impl Drop for MSEncoder {
+    fn drop(&mut self) {
+        unsafe { ffi::opus_multistream_encoder_destroy(self.ptr) }
+    }
+}
+
+// See `unsafe impl Send for Encoder`.
+unsafe impl Send for MSEncoder {}
+
+// ============================================================================
+// Multistream Decoder
+
+macro_rules! ms_dec_ctl {
+	($this:ident, $ctl:path $(, $rest:expr)*) => {
+		ctl!(opus_multistream_decoder_ctl, $this, $ctl, $($rest),*)
+	}
+}
+
+/// An Opus multistream decoder with associated state.
+#[derive(Debug)]
+pub struct MSDecoder {
+    ptr: *mut ffi::OpusMSDecoder,
+    channels: c_int,
+}
+
+impl MSDecoder {
+    /// Create and initialize a multistream decoder.
+    ///
+    /// There is one output channel per entry of `mapping`, each giving the
+    /// coded channel it is decoded from, or 255 for silence.
+    pub fn new(
+        sample_rate: u32,
+        streams: u8,
+        coupled_streams: u8,
+        mapping: &[u8],
+    ) -> Result<MSDecoder> {
+        let channels = mapping_len(mapping, "opus_multistream_decoder_create")?;
+        let mut error = 0;
+        let ptr = unsafe {
+            ffi::opus_multistream_decoder_create(
+                sample_rate as i32,
+                channels,
+                streams as c_int,
+                coupled_streams as c_int,
+                mapping.as_ptr(),
+                &mut error,
+            )
+        };
+        if error != ffi::OPUS_OK || ptr.is_null() {
+            Err(Error::from_code("opus_multistream_decoder_create", error))
+        } else {
+            Ok(MSDecoder { ptr, channels })
+        }
+    }
+
+    /// Decode a multistream Opus packet.
+    ///
+    /// To represent packet loss, pass an empty slice `&[]`.
+    ///
+    /// The return value is the number of samples *per channel* decoded from
+    /// the packet.
+    pub fn decode(&mut self, input: &[u8], output: &mut [i16], fec: bool) -> Result<usize> {
+        let ptr = match input.len() {
+            0 => core::ptr::null(),
+            _ => input.as_ptr(),
+        };
+        let len = ffi!(
+            opus_multistream_decode,
+            self.ptr,
+            ptr,
+            len(input),
+            output.as_mut_ptr(),
+            len(output) / self.channels,
+            fec as c_int
+        );
+        Ok(len as usize)
+    }
+
+    /// Decode a multistream Opus packet with floating point output.
+    ///
+    /// To represent packet loss, pass an empty slice `&[]`.
+    ///
+    /// The return value is the number of samples *per channel* decoded from
+    /// the packet.
+    #[cfg(not(feature = "disable-float-api"))]
+    pub fn decode_float(&mut self, input: &[u8], output: &mut [f32], fec: bool) -> Result<usize> {
+        let ptr = match input.len() {
+            0 => core::ptr::null(),
+            _ => input.as_ptr(),
+        };
+        let len = ffi!(
+            opus_multistream_decode_float,
+            self.ptr,
+            ptr,
+            len(input),
+            output.as_mut_ptr(),
+            len(output) / self.channels,
+            fec as c_int
+        );
+        Ok(len as usize)
+    }
+
+    // ------------
+    // Generic CTLs
+
+    /// Reset the codec state to be equivalent to a freshly initialized state.
+    pub fn reset_state(&mut self) -> Result<()> {
+        ms_dec_ctl!(self, ffi::OPUS_RESET_STATE);
+        Ok(())
+    }
+
+    /// Get the final range of the codec's entropy coder.
+    pub fn get_final_range(&mut self) -> Result<u32> {
+        let mut value: u32 = 0;
+        ms_dec_ctl!(self, ffi::OPUS_GET_FINAL_RANGE_REQUEST, &mut value);
+        Ok(value)
+    }
+
+    /// Get the decoder's last bandpass.
+    pub fn get_bandwidth(&mut self) -> Result<Bandwidth> {
+        let mut value: i32 = 0;
+        ms_dec_ctl!(self, ffi::OPUS_GET_BANDWIDTH_REQUEST, &mut value);
+        Bandwidth::decode(value, "opus_multistream_decoder_ctl(OPUS_GET_BANDWIDTH)")
+    }
+
+    /// Get the samping rate the decoder was intialized with.
+    pub fn get_sample_rate(&mut self) -> Result<u32> {
+        let mut value: i32 = 0;
+        ms_dec_ctl!(self, ffi::OPUS_GET_SAMPLE_RATE_REQUEST, &mut value);
+        Ok(value as u32)
+    }
+
+    // ------------
+    // Decoder CTLs
+
+    /// Configures decoder gain adjustment, in Q8 dB units.
+    ///
+    /// See [`Decoder::set_gain`].
+    pub fn set_gain(&mut self, gain: i32) -> Result<()> {
+        ms_dec_ctl!(self, ffi::OPUS_SET_GAIN_REQUEST, gain);
+        Ok(())
+    }
+
+    /// Gets the decoder's configured gain adjustment.
+    pub fn get_gain(&mut self) -> Result<i32> {
+        let mut value: i32 = 0;
+        ms_dec_ctl!(self, ffi::OPUS_GET_GAIN_REQUEST, &mut value);
+        Ok(value)
+    }
+
+    /// Gets the duration (in samples) of the last packet successfully decoded
+    /// or concealed.
+    pub fn get_last_packet_duration(&mut self) -> Result<u32> {
+        let mut value: i32 = 0;
+        ms_dec_ctl!(self, ffi::OPUS_GET_LAST_PACKET_DURATION_REQUEST, &mut value);
+        Ok(value as u32)
+    }
+}
+
+impl Drop for MSDecoder {
+    fn drop(&mut self) {
+        unsafe { ffi::opus_multistream_decoder_destroy(self.ptr) }
+    }
+}
+
+// See `unsafe impl Send for Encoder`.
+unsafe impl Send for MSDecoder {}
+
+/// The channel count given by a multistream mapping, at most 255.
+fn mapping_len(mapping: &[u8], what: &'static str) -> Result<c_int> {
+    match mapping.len() {
+        1..=255 => Ok(mapping.len() as c_int),
+        _ => Err(Error::bad_arg(what)),
+    }
+}
 
 // ============================================================================
 // Error Handling
@@ -881,20 +2466,75 @@ pub type Result<T> = core::result::Result<T, Error>;
 pub struct Error {
     function: &'static str,
     code: ErrorCode,
+    frame: Option<FrameMismatch>,
+}
+
+/// Details of a buffer that did not hold a legal number of samples.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct FrameMismatch {
+    /// The number of interleaved samples passed.
+    pub len: usize,
+    /// The channel count of the codec.
+    pub channels: usize,
+    /// The sample rate of the codec.
+    pub sample_rate: u32,
+    /// What the buffer had to hold.
+    pub rule: FrameRule,
+}
+
+/// The sizes a buffer of samples is checked against.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FrameRule {
+    /// Exactly one frame, see [`FrameMismatch::frame_sizes`].
+    Frame,
+    /// At least the expert frame duration, the rest is ignored.
+    AtLeast(FrameDuration),
+    /// Any multiple of 2.5ms, as for concealment and FEC.
+    Steps,
+    /// Any whole number of interleaved samples, as when decoding a packet.
+    Interleaved,
+}
+
+impl FrameMismatch {
+    /// Get the legal frame sizes in samples *per channel*, shortest first.
+    pub fn frame_sizes(&self) -> impl Iterator<Item = usize> + use<> {
+        let sample_rate = self.sample_rate;
+        FrameDuration::ALL.into_iter().map(move |d| d.samples(sample_rate))
+    }
 }
 
 impl Error {
     fn bad_arg(what: &'static str) -> Error {
-        Error { function: what, code: ErrorCode::BadArg }
+        Error {
+            function: what,
+            code: ErrorCode::BadArg,
+            frame: None,
+        }
     }
 
     fn from_code(what: &'static str, code: c_int) -> Error {
         Error {
             function: what,
             code: ErrorCode::from_int(code),
+            frame: None,
         }
     }
 
+    fn bad_frame(what: &'static str, frame: FrameMismatch) -> Error {
+        Error {
+            function: what,
+            code: ErrorCode::BadArg,
+            frame: Some(frame),
+        }
+    }
+
+    /// Get the details of the buffer, if the error is because it did not hold
+    /// a legal number of samples.
+    #[inline]
+    pub fn frame_mismatch(&self) -> Option<FrameMismatch> {
+        self.frame
+    }
+
     /// Get the name of the Opus function from which the error originated.
     #[inline]
     pub fn function(&self) -> &'static str {
@@ -914,9 +2554,40 @@ impl Error {
     }
 }
 
-impl alloc::fmt::Display for Error {
+impl core::fmt::Display for Error {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
-        write!(f, "{}: {}", self.function, self.description())
+        let Some(frame) = self.frame else {
+            return write!(f, "{}: {}", self.function, self.description());
+        };
+        write!(
+            f,
+            "{}: {} samples is not a frame of {} channel(s) at {}Hz, expected ",
+            self.function, frame.len, frame.channels, frame.sample_rate
+        )?;
+        match frame.rule {
+            FrameRule::Frame => {}
+            FrameRule::AtLeast(duration) => {
+                let samples = duration.samples(frame.sample_rate);
+                return write!(f, "at least {} samples per channel", samples);
+            }
+            FrameRule::Steps => {
+                let step = FrameDuration::Ms2_5.samples(frame.sample_rate);
+                return write!(f, "a multiple of {} samples per channel", step);
+            }
+            FrameRule::Interleaved => {
+                return write!(f, "a multiple of {} samples", frame.channels);
+            }
+        }
+        let sizes = frame.frame_sizes();
+        let last = FrameDuration::ALL.len() - 1;
+        for (i, size) in sizes.enumerate() {
+            match i {
+                0 => write!(f, "{}", size)?,
+                _ if i == last => write!(f, " or {}", size)?,
+                _ => write!(f, ", {}", size)?,
+            }
+        }
+        write!(f, " samples per channel")
     }
 }
 
@@ -933,6 +2604,12 @@ fn check_len(val: usize) -> c_int {
     }
 }
 
+/// `val` as a C int, `BadArg` if it doesn't fit.
+#[cfg(feature = "dred")]
+fn try_len(val: usize, what: &'static str) -> Result<c_int> {
+    c_int::try_from(val).map_err(|_| Error::bad_arg(what))
+}
+
 #[inline]
 fn len<T>(slice: &[T]) -> c_int {
     check_len(slice.len())
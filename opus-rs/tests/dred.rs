@@ -0,0 +1,80 @@
+//! Deep redundancy and the neural decoder features, which need a libopus 1.5
+//! built with them.
+#![cfg(feature = "dred")]
+
+extern crate opus;
+use opus::*;
+
+// 16000Hz * 20 ms / 1000 = 320
+const FRAME: usize = 320;
+
+fn speech_like(frame: usize) -> Vec<i16> {
+	(0..FRAME).map(|i| {
+		let t = (frame * FRAME + i) as f32 / 16000.0;
+		(6000.0 * (2.0 * std::f32::consts::PI * 180.0 * t).sin()
+			* (2.0 * std::f32::consts::PI * 3.0 * t).sin().abs()) as i16
+	}).collect()
+}
+
+#[test]
+fn dred_ctls() {
+	let mut encoder = Encoder::new(16000, Channels::Mono, Application::Voip).unwrap();
+	assert_eq!(0, encoder.get_dred_duration().unwrap());
+	encoder.set_dred_duration(50).unwrap();
+	assert_eq!(50, encoder.get_dred_duration().unwrap());
+
+	let mut decoder = Decoder::new(16000, Channels::Mono).unwrap();
+	decoder.set_complexity(5).unwrap();
+	assert_eq!(5, decoder.get_complexity().unwrap());
+}
+
+#[test]
+fn dred_recovers_lost_packets() {
+	let mut encoder = Encoder::new(16000, Channels::Mono, Application::Voip).unwrap();
+	encoder.set_bitrate(Bitrate::Bits(32000)).unwrap();
+	encoder.set_packet_loss_perc(20).unwrap();
+	encoder.set_dred_duration(50).unwrap();
+	let packets: Vec<_> = (0..100)
+		.map(|i| encoder.encode_vec(&speech_like(i), 1500).unwrap())
+		.collect();
+
+	let mut decoder = Decoder::new(16000, Channels::Mono).unwrap();
+	decoder.set_complexity(5).unwrap();
+	let mut output = [0_i16; FRAME];
+	for packet in &packets[..97] {
+		decoder.decode(packet, &mut output, false).unwrap();
+	}
+
+	// packets 97 and 98 are lost, recover them from 99
+	let mut dred_decoder = DredDecoder::new().unwrap();
+	let mut dred = Dred::new().unwrap();
+	let (available, _) = dred_decoder.parse(&mut dred, &packets[99], 2 * FRAME, 16000).unwrap();
+	assert!(available >= 2 * FRAME, "{}", available);
+	for lost in [2, 1] {
+		let len = decoder.decode_dred(&dred, lost * FRAME, &mut output).unwrap();
+		assert_eq!(FRAME, len);
+	}
+	decoder.decode(&packets[99], &mut output, false).unwrap();
+}
+
+#[test]
+fn dred_offsets_out_of_range() {
+	let mut decoder = Decoder::new(16000, Channels::Mono).unwrap();
+	let mut dred_decoder = DredDecoder::new().unwrap();
+	let mut dred = Dred::new().unwrap();
+	let packet = Encoder::new(16000, Channels::Mono, Application::Voip).unwrap()
+		.encode_vec(&speech_like(0), 1500).unwrap();
+	let too_far = i32::MAX as usize + 1;
+
+	let err = dred_decoder.parse(&mut dred, &packet, too_far, 16000).unwrap_err();
+	assert_eq!(err.code(), ErrorCode::BadArg);
+	let mut output = [0_i16; FRAME];
+	let err = decoder.decode_dred(&dred, too_far, &mut output).unwrap_err();
+	assert_eq!(err.code(), ErrorCode::BadArg);
+	#[cfg(not(feature = "disable-float-api"))]
+	{
+		let mut output = [0_f32; FRAME];
+		let err = decoder.decode_dred_float(&dred, too_far, &mut output).unwrap_err();
+		assert_eq!(err.code(), ErrorCode::BadArg);
+	}
+}
@@ -0,0 +1,154 @@
+//! Cross-check the pure Rust packet parser against libopus.
+#![cfg(feature = "alloc")]
+
+extern crate opus;
+
+use opus::packet::{self, Mode, Toc};
+use opus::{Application, Bandwidth, Channels, FrameDuration};
+
+/// Check that `inspect` agrees with libopus about `data`.
+fn check(data: &[u8]) {
+	match (packet::parse(data), packet::inspect(data)) {
+		(Ok(expected), Ok(info)) => {
+			assert_eq!(info.toc.0, expected.toc, "{:?}", data);
+			assert_eq!(info.payload_offset, expected.payload_offset, "{:?}", data);
+			assert_eq!(info.frames().collect::<Vec<_>>(), expected.frames, "{:?}", data);
+			assert_eq!(info.nb_frames(), packet::get_nb_frames(data).unwrap());
+			assert_eq!(info.nb_samples(48000), packet::get_nb_samples(data, 48000).unwrap());
+			assert_eq!(info.toc.bandwidth(), packet::get_bandwidth(data).unwrap());
+			assert_eq!(info.toc.channels(), packet::get_nb_channels(data).unwrap());
+		}
+		(Err(expected), Err(err)) => assert_eq!(err.code(), expected.code(), "{:?}", data),
+		(expected, info) => panic!("{:?}: libopus gave {:?}, inspect gave {:?}", data, expected, info),
+	}
+}
+
+#[test]
+fn toc_fields() {
+	for byte in 0..=255 {
+		let toc = Toc(byte);
+		let data = [byte, 0, 0, 0];
+		assert_eq!(toc.bandwidth(), packet::get_bandwidth(&data).unwrap());
+		assert_eq!(toc.channels(), packet::get_nb_channels(&data).unwrap());
+		for rate in [8000, 12000, 16000, 24000, 48000] {
+			assert_eq!(toc.frame_duration().samples(rate), packet::get_samples_per_frame(&data, rate).unwrap());
+		}
+		assert_eq!(toc.code(), byte & 0x3);
+	}
+
+	assert_eq!(Toc(0x00).mode(), Mode::Silk);
+	assert_eq!(Toc(0x60).mode(), Mode::Hybrid);
+	assert_eq!(Toc(0xF8).mode(), Mode::Celt);
+	assert_eq!(Toc(0xF8).bandwidth(), Bandwidth::Fullband);
+	assert_eq!(Toc(0xF8).frame_duration(), FrameDuration::Ms20);
+	assert_eq!(Toc(0x18).frame_duration(), FrameDuration::Ms60);
+	assert_eq!(Toc(0x80).frame_duration(), FrameDuration::Ms2_5);
+}
+
+#[test]
+fn inspect_encoded() {
+	let mut rp = opus::Repacketizer::new().unwrap();
+	let modes = [Application::Voip, Application::Audio, Application::LowDelay];
+	for rate in [8000, 16000, 48000] {
+		for channels in [Channels::Mono, Channels::Stereo] {
+			for mode in modes {
+				let mut encoder = opus::Encoder::new(rate, channels, mode).unwrap();
+				let mut packets = Vec::new();
+				for duration in [FrameDuration::Ms10, FrameDuration::Ms20] {
+					let len = encoder.frame_size(duration) * channels as usize;
+					let input: Vec<i16> = (0..len).map(|i| ((i as f32 / 7.0).sin() * 16384.0) as i16).collect();
+					packets.push(encoder.encode_vec(&input, 1275).unwrap());
+					packets.push(encoder.encode_vec(&input, 1275).unwrap());
+				}
+				for packet in &packets {
+					check(packet);
+					let info = packet::inspect(packet).unwrap();
+					assert_eq!(info.nb_frames(), 1);
+					assert!(!info.vbr);
+					assert_eq!(info.padding, 0);
+				}
+
+				// two and three frames, of the same size or not
+				for group in [&packets[..2], &packets[2..], &packets[1..]] {
+					let mut out = [0; 4000];
+					let mut state = rp.begin();
+					for packet in group {
+						if state.cat(packet).is_err() {
+							break;
+						}
+					}
+					let len = state.out(&mut out).unwrap();
+					check(&out[..len]);
+
+					// and padded, which always takes code 3
+					packet::pad(&mut out[..len + 600], len).unwrap();
+					let len = len + 600;
+					check(&out[..len]);
+					let info = packet::inspect(&out[..len]).unwrap();
+					assert_eq!(info.toc.code(), 3);
+					assert!(info.padding > 0);
+				}
+			}
+		}
+	}
+	assert_eq!(packet::inspect(&[0x08, 1]).unwrap().toc.mode(), Mode::Silk);
+}
+
+#[test]
+fn inspect_handmade() {
+	// code 1 with an odd payload
+	check(&[249, 1, 2, 3]);
+	check(&[249, 1, 2]);
+	// code 2 with sizes in one and two bytes
+	check(&[250, 1, 7, 8]);
+	check(&[250, 252, 1, 0]);
+	check(&[250, 252, 0]);
+	check(&[250, 2, 7]);
+	// code 3: no frames, 120ms, 140ms, cbr and vbr
+	check(&[251, 0]);
+	check(&[251, 6, 1, 2, 3, 4, 5, 6]);
+	check(&[251, 7, 1, 2, 3, 4, 5, 6, 7]);
+	check(&[251, 3, 255, 254, 255, 254, 255, 254]);
+	check(&[251, 0x83, 1, 1, 5, 6, 7]);
+	check(&[251, 0x83, 1, 5, 6, 7]);
+	// padding lengths running over the end of the packet
+	check(&[251, 0x41, 2, 5, 0, 0]);
+	check(&[251, 0x41, 3, 5, 0, 0]);
+	check(&[251, 0x41, 255, 0, 5]);
+	check(&[251, 0x41, 255]);
+	// frames too long for their implied size
+	check(&[248; 1277]);
+	check(&[248; 1276]);
+
+	let info = packet::inspect(&[251, 0xC2, 2, 1, 5, 6, 7, 0, 0]).unwrap();
+	assert!(info.vbr);
+	assert_eq!(info.padding, 2);
+	assert_eq!(info.payload_offset, 4);
+	assert_eq!(info.frames().collect::<Vec<_>>(), [&[5][..], &[6, 7][..]]);
+
+	assert_eq!(packet::inspect(&[]).unwrap_err().code(), opus::ErrorCode::InvalidPacket);
+}
+
+#[test]
+fn inspect_random() {
+	// xorshift, so failures are reproducible
+	let mut state = 0x2545_F491_u32;
+	let mut next = move || {
+		state ^= state << 13;
+		state ^= state >> 17;
+		state ^= state << 5;
+		state
+	};
+	for _ in 0..50_000 {
+		let len = (next() % 40) as usize;
+		let mut data: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+		// favour small sizes and counts, which are more likely to parse
+		if len > 1 && next() % 2 == 0 {
+			data[1] &= 0xC7;
+		}
+		if len > 2 && next() % 2 == 0 {
+			data[2] &= 0x07;
+		}
+		check(&data);
+	}
+}
@@ -0,0 +1,257 @@
+//! Host side helpers for the P3 container used by the firmware assets.
+//!
+//! A P3 file is a plain sequence of Opus packets, each behind a 4 byte
+//! header: a type byte (`0` for Opus), a reserved byte, and the packet
+//! length as big endian `u16`.
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::Path;
+
+use anyhow::{bail, ensure, Context};
+use firmware_core::dsp::resample::Resampler;
+use opus::{Application, Bitrate, Channels, Decoder, Encoder};
+
+pub const HEADER_LEN: usize = 4;
+/// Type byte of Opus packets, the only kind there is so far.
+pub const TYPE_OPUS: u8 = 0;
+
+pub struct P3Writer<W> {
+    writer: W,
+}
+
+impl<W: Write> P3Writer<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn write_packet(&mut self, packet: &[u8]) -> io::Result<()> {
+        let len = u16::try_from(packet.len())
+            .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "packet too large for P3"))?;
+        let [hi, lo] = len.to_be_bytes();
+        self.writer.write_all(&[TYPE_OPUS, 0, hi, lo])?;
+        self.writer.write_all(packet)
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+pub struct P3Reader<R> {
+    reader: R,
+}
+
+impl<R: Read> P3Reader<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+
+    /// Next packet, `None` at the end of the stream.
+    pub fn next_packet(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut header = [0; HEADER_LEN];
+        match self.reader.read_exact(&mut header[..1]) {
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            result => result?,
+        }
+        self.reader.read_exact(&mut header[1..])?;
+        if header[0] != TYPE_OPUS {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("unknown P3 packet type {}", header[0]),
+            ));
+        }
+        let mut packet = vec![0; u16::from_be_bytes([header[2], header[3]]) as usize];
+        self.reader.read_exact(&mut packet)?;
+        Ok(Some(packet))
+    }
+}
+
+impl<R: Read> Iterator for P3Reader<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_packet().transpose()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EncodeOptions {
+    pub sample_rate: u32,
+    pub frame_ms: u32,
+    /// Bits per second, the encoder picks one when `None`.
+    pub bitrate: Option<i32>,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            sample_rate: 16000,
+            frame_ms: 60,
+            bitrate: None,
+        }
+    }
+}
+
+/// Encode mono PCM at `options.sample_rate` into P3, padding the last frame
+/// with silence. Returns the number of packets written.
+pub fn encode<W: Write>(
+    pcm: &[i16],
+    options: EncodeOptions,
+    writer: &mut P3Writer<W>,
+) -> anyhow::Result<usize> {
+    let frame_size = (options.sample_rate * options.frame_ms / 1000) as usize;
+    ensure!(frame_size > 0, "frame duration too short");
+    let mut encoder = Encoder::new(options.sample_rate, Channels::Mono, Application::Audio)
+        .context("creating the Opus encoder")?;
+    if let Some(bitrate) = options.bitrate {
+        encoder.set_bitrate(Bitrate::Bits(bitrate))?;
+    }
+
+    let mut frame = vec![0; frame_size];
+    let mut packet = [0; 4000];
+    let mut packets = 0;
+    for chunk in pcm.chunks(frame_size) {
+        frame[..chunk.len()].copy_from_slice(chunk);
+        frame[chunk.len()..].fill(0);
+        let len = encoder
+            .encode(&frame, &mut packet)
+            .with_context(|| format!("encoding {} ms frames", options.frame_ms))?;
+        writer.write_packet(&packet[..len])?;
+        packets += 1;
+    }
+    Ok(packets)
+}
+
+/// Decode every packet of a P3 stream into mono PCM at `sample_rate`.
+pub fn decode<R: Read>(reader: P3Reader<R>, sample_rate: u32) -> anyhow::Result<Vec<i16>> {
+    let mut decoder = Decoder::new(sample_rate, Channels::Mono)?;
+    // the longest Opus packet lasts 120ms
+    let mut frame = vec![0; sample_rate as usize * 120 / 1000];
+    let mut pcm = Vec::new();
+    for (i, packet) in reader.enumerate() {
+        let packet = packet?;
+        let len = decoder
+            .decode(&packet, &mut frame, false)
+            .with_context(|| format!("decoding packet {i}"))?;
+        pcm.extend_from_slice(&frame[..len]);
+    }
+    Ok(pcm)
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Info {
+    pub packets: usize,
+    /// Opus payload bytes, headers excluded.
+    pub bytes: usize,
+    pub min_packet: usize,
+    pub max_packet: usize,
+    /// Duration in samples at 48kHz, the Opus internal rate.
+    pub samples: usize,
+}
+
+impl Info {
+    pub fn duration_ms(&self) -> u64 {
+        self.samples as u64 / 48
+    }
+
+    /// Average payload bitrate, in bits per second.
+    pub fn bitrate(&self) -> u64 {
+        match self.samples {
+            0 => 0,
+            samples => self.bytes as u64 * 8 * 48000 / samples as u64,
+        }
+    }
+}
+
+pub fn info<R: Read>(reader: P3Reader<R>) -> anyhow::Result<Info> {
+    let mut info = Info {
+        min_packet: usize::MAX,
+        ..Default::default()
+    };
+    for (i, packet) in reader.enumerate() {
+        let packet = packet?;
+        info.packets += 1;
+        info.bytes += packet.len();
+        info.min_packet = info.min_packet.min(packet.len());
+        info.max_packet = info.max_packet.max(packet.len());
+        info.samples += opus::packet::get_nb_samples(&packet, 48000)
+            .with_context(|| format!("parsing packet {i}"))?;
+    }
+    if info.packets == 0 {
+        info.min_packet = 0;
+    }
+    Ok(info)
+}
+
+/// Read a WAV file as mono PCM at `sample_rate`, mixing channels down and
+/// resampling as needed.
+pub fn read_wav(path: impl AsRef<Path>, sample_rate: u32) -> anyhow::Result<Vec<i16>> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    let samples: Vec<i32> = match spec.sample_format {
+        hound::SampleFormat::Int => {
+            let shift = spec.bits_per_sample as i32 - 16;
+            reader
+                .samples::<i32>()
+                .map(|s| {
+                    s.map(|s| match shift {
+                        0.. => s >> shift,
+                        _ => s << -shift,
+                    })
+                })
+                .collect::<Result<_, _>>()?
+        }
+        hound::SampleFormat::Float => reader
+            .samples::<f32>()
+            .map(|s| s.map(|s| (s * 32768.0) as i32))
+            .collect::<Result<_, _>>()?,
+    };
+
+    let channels = spec.channels as usize;
+    if channels == 0 {
+        bail!("WAV file without channels");
+    }
+    let mono: Vec<i16> = samples
+        .chunks(channels)
+        .map(|frame| {
+            let sum: i32 = frame.iter().sum();
+            (sum / frame.len() as i32).clamp(i16::MIN as i32, i16::MAX as i32) as i16
+        })
+        .collect();
+    Ok(resample(&mono, spec.sample_rate, sample_rate))
+}
+
+/// Read headerless 16 bit little endian mono PCM.
+pub fn read_raw(path: impl AsRef<Path>, from: u32, sample_rate: u32) -> anyhow::Result<Vec<i16>> {
+    let bytes = std::fs::read(path)?;
+    let pcm: Vec<i16> = bytes
+        .chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+        .collect();
+    Ok(resample(&pcm, from, sample_rate))
+}
+
+pub fn write_wav(path: impl AsRef<Path>, pcm: &[i16], sample_rate: u32) -> anyhow::Result<()> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)?;
+    for &sample in pcm {
+        writer.write_sample(sample)?;
+    }
+    writer.finalize()?;
+    Ok(())
+}
+
+fn resample(pcm: &[i16], from: u32, to: u32) -> Vec<i16> {
+    if from == to {
+        return pcm.to_vec();
+    }
+    let mut out = Vec::with_capacity(pcm.len() * to as usize / from as usize);
+    Resampler::new(from, to).process(pcm, |sample| out.push(sample));
+    out
+}
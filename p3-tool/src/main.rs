@@ -0,0 +1,107 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use p3_tool::{EncodeOptions, P3Reader, P3Writer};
+
+/// Convert audio to and from the P3 format played by the firmware.
+#[derive(Parser)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Encode a WAV file, or raw 16 bit little endian mono PCM, into P3.
+    Encode {
+        input: PathBuf,
+        output: PathBuf,
+        /// Rate to encode at, the input is resampled to it.
+        #[arg(short = 'r', long, default_value_t = 16000)]
+        sample_rate: u32,
+        /// Duration of each Opus frame, in milliseconds.
+        #[arg(short, long, default_value_t = 60)]
+        frame_ms: u32,
+        /// Bitrate in bits per second, left to the encoder by default.
+        #[arg(short, long)]
+        bitrate: Option<i32>,
+        /// Sample rate of raw PCM input, used for files not ending in `.wav`.
+        #[arg(long, default_value_t = 16000)]
+        raw_rate: u32,
+    },
+    /// Print packet statistics and duration of a P3 file.
+    Info { input: PathBuf },
+    /// Decode a P3 file back to a mono WAV file.
+    Decode {
+        input: PathBuf,
+        output: PathBuf,
+        #[arg(short = 'r', long, default_value_t = 16000)]
+        sample_rate: u32,
+    },
+}
+
+fn open(path: &PathBuf) -> anyhow::Result<P3Reader<BufReader<File>>> {
+    let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    Ok(P3Reader::new(BufReader::new(file)))
+}
+
+fn main() -> anyhow::Result<()> {
+    match Cli::parse().command {
+        Command::Encode {
+            input,
+            output,
+            sample_rate,
+            frame_ms,
+            bitrate,
+            raw_rate,
+        } => {
+            let pcm = if input.extension().is_some_and(|ext| ext == "wav") {
+                p3_tool::read_wav(&input, sample_rate)
+            } else {
+                p3_tool::read_raw(&input, raw_rate, sample_rate)
+            }
+            .with_context(|| format!("reading {}", input.display()))?;
+
+            let file =
+                File::create(&output).with_context(|| format!("creating {}", output.display()))?;
+            let mut writer = P3Writer::new(BufWriter::new(file));
+            let options = EncodeOptions {
+                sample_rate,
+                frame_ms,
+                bitrate,
+            };
+            let packets = p3_tool::encode(&pcm, options, &mut writer)?;
+            writer.finish()?;
+            println!(
+                "{}: {packets} packets, {} ms",
+                output.display(),
+                packets as u32 * frame_ms
+            );
+        }
+        Command::Info { input } => {
+            let info = p3_tool::info(open(&input)?)?;
+            println!("packets:  {}", info.packets);
+            println!("duration: {} ms", info.duration_ms());
+            println!("payload:  {} bytes", info.bytes);
+            println!("packet:   {} to {} bytes", info.min_packet, info.max_packet);
+            println!("bitrate:  {} bps", info.bitrate());
+        }
+        Command::Decode {
+            input,
+            output,
+            sample_rate,
+        } => {
+            let pcm = p3_tool::decode(open(&input)?, sample_rate)?;
+            p3_tool::write_wav(&output, &pcm, sample_rate)?;
+            println!(
+                "{}: {} ms",
+                output.display(),
+                pcm.len() as u64 * 1000 / sample_rate as u64
+            );
+        }
+    }
+    Ok(())
+}
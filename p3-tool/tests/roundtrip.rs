@@ -0,0 +1,100 @@
+use std::f32::consts::PI;
+
+use p3_tool::{EncodeOptions, Info, P3Reader, P3Writer};
+
+fn tone(rate: u32, ms: u32) -> Vec<i16> {
+    (0..rate * ms / 1000)
+        .map(|i| (8000.0 * (2.0 * PI * 440.0 * i as f32 / rate as f32).sin()) as i16)
+        .collect()
+}
+
+fn encode(pcm: &[i16], options: EncodeOptions) -> Vec<u8> {
+    let mut writer = P3Writer::new(Vec::new());
+    p3_tool::encode(pcm, options, &mut writer).unwrap();
+    writer.finish().unwrap()
+}
+
+#[test]
+fn header_layout() {
+    let mut writer = P3Writer::new(Vec::new());
+    writer.write_packet(&[1, 2, 3]).unwrap();
+    writer.write_packet(&[0; 300]).unwrap();
+    let data = writer.finish().unwrap();
+    assert_eq!(&data[..7], &[0, 0, 0, 3, 1, 2, 3]);
+    assert_eq!(&data[7..11], &[0, 0, 1, 44]);
+
+    let packets: Vec<_> = P3Reader::new(&data[..]).collect::<Result<_, _>>().unwrap();
+    assert_eq!(packets, [vec![1, 2, 3], vec![0; 300]]);
+}
+
+#[test]
+fn oversized_packet() {
+    let mut writer = P3Writer::new(Vec::new());
+    assert!(writer.write_packet(&vec![0; 70000]).is_err());
+}
+
+#[test]
+fn unknown_type() {
+    let data = [1, 0, 0, 1, 0];
+    assert!(P3Reader::new(&data[..]).next_packet().is_err());
+}
+
+#[test]
+fn truncated() {
+    let data = [0, 0, 0, 4, 1, 2];
+    assert!(P3Reader::new(&data[..]).next_packet().is_err());
+    let data = [0, 0];
+    assert!(P3Reader::new(&data[..]).next_packet().is_err());
+}
+
+#[test]
+fn encode_info_decode() {
+    // one second, not a whole number of 60ms frames
+    let pcm = tone(16000, 1000);
+    let data = encode(&pcm, EncodeOptions::default());
+
+    let info = p3_tool::info(P3Reader::new(&data[..])).unwrap();
+    assert_eq!(info.packets, 17);
+    assert_eq!(info.duration_ms(), 17 * 60);
+    assert_eq!(info.bytes, data.len() - 17 * 4);
+    assert!(info.min_packet > 0 && info.max_packet >= info.min_packet);
+
+    let decoded = p3_tool::decode(P3Reader::new(&data[..]), 16000).unwrap();
+    assert_eq!(decoded.len(), 17 * 960);
+    let energy = |pcm: &[i16]| pcm.iter().map(|&s| (s as f64).powi(2)).sum::<f64>();
+    let (start, end) = (4000, 12000);
+    let ratio = energy(&decoded[start..end]) / energy(&pcm[start..end]);
+    assert!((0.5..2.0).contains(&ratio), "{ratio}");
+}
+
+#[test]
+fn frame_durations() {
+    let pcm = tone(24000, 240);
+    for frame_ms in [10, 20, 40, 60] {
+        let options = EncodeOptions {
+            sample_rate: 24000,
+            frame_ms,
+            bitrate: Some(32000),
+        };
+        let info = p3_tool::info(P3Reader::new(&encode(&pcm, options)[..])).unwrap();
+        assert_eq!(info.packets as u32, 240 / frame_ms);
+        assert_eq!(info.duration_ms(), 240);
+    }
+}
+
+#[test]
+fn invalid_frame_duration() {
+    let options = EncodeOptions {
+        frame_ms: 7,
+        ..Default::default()
+    };
+    let mut writer = P3Writer::new(Vec::new());
+    assert!(p3_tool::encode(&tone(16000, 100), options, &mut writer).is_err());
+}
+
+#[test]
+fn empty() {
+    let info = p3_tool::info(P3Reader::new(&[][..])).unwrap();
+    assert_eq!(info, Info::default());
+    assert_eq!(info.bitrate(), 0);
+}